@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX peers_public_key;
+INSERT INTO peers SELECT * FROM peers_without_keys;
+DROP TABLE peers_without_keys;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Peers created before keys were generated all carry the literal 'public_key', and
+-- would keep the index from being built. They can never connect, so they are set
+-- aside in peers_without_keys, along with any other rows sharing a key, and can be
+-- recreated from there.
+CREATE TABLE peers_without_keys AS
+SELECT * FROM peers
+WHERE public_key = 'public_key'
+   OR public_key IN (SELECT public_key FROM peers GROUP BY public_key HAVING count(*) > 1);
+DELETE FROM peers WHERE id IN (SELECT id FROM peers_without_keys);
+CREATE UNIQUE INDEX peers_public_key ON peers (public_key);
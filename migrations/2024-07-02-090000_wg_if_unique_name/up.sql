@@ -0,0 +1,2 @@
+-- Your SQL goes here
+CREATE UNIQUE INDEX wg_if_name ON wg_if (name);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Peers adopted from an existing setup only have their public key on the server
+ALTER TABLE peers ALTER COLUMN private_key DROP NOT NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE wg_if DROP COLUMN post_down;
+ALTER TABLE wg_if DROP COLUMN post_up;
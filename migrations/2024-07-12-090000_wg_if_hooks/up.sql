@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- wg-quick PostUp / PostDown commands of the server config, e.g. the forwarding rules
+ALTER TABLE wg_if ADD COLUMN post_up TEXT;
+ALTER TABLE wg_if ADD COLUMN post_down TEXT;
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER set_updated_at ON peers;
+DROP FUNCTION peers_set_updated_at();
+SELECT diesel_manage_updated_at('peers');
+
+ALTER TABLE peers DROP COLUMN usage_updated_at;
+ALTER TABLE peers DROP COLUMN kernel_tx;
+ALTER TABLE peers DROP COLUMN kernel_rx;
+ALTER TABLE peers ALTER COLUMN transfer_tx TYPE INTEGER;
+ALTER TABLE peers ALTER COLUMN transfer_rx TYPE INTEGER;
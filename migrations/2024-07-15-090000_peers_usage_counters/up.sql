@@ -0,0 +1,28 @@
+-- Your SQL goes here
+-- Byte counters outgrow INTEGER after 2 GiB
+ALTER TABLE peers ALTER COLUMN transfer_rx TYPE BIGINT;
+ALTER TABLE peers ALTER COLUMN transfer_tx TYPE BIGINT;
+-- The counters last read from the kernel, a lower reading means they were reset
+ALTER TABLE peers ADD COLUMN kernel_rx BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE peers ADD COLUMN kernel_tx BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE peers ADD COLUMN usage_updated_at TIMESTAMP;
+
+-- Polling the usage is not an edit of the peer, it must not move updated_at
+CREATE OR REPLACE FUNCTION peers_set_updated_at() RETURNS trigger AS $$
+DECLARE
+    usage_columns TEXT[] := ARRAY['transfer_rx', 'transfer_tx', 'kernel_rx', 'kernel_tx',
+        'last_handshake_at', 'endpoint_addr', 'usage_updated_at', 'updated_at'];
+BEGIN
+    IF (
+        to_jsonb(NEW) - usage_columns IS DISTINCT FROM to_jsonb(OLD) - usage_columns AND
+        NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+    ) THEN
+        NEW.updated_at := current_timestamp;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER set_updated_at ON peers;
+CREATE TRIGGER set_updated_at BEFORE UPDATE ON peers
+    FOR EACH ROW EXECUTE PROCEDURE peers_set_updated_at();
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE peer_traffic_rollups;
+DROP TABLE peer_traffic_samples;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Traffic of a peer between two polls
+CREATE TABLE peer_traffic_samples
+(
+    peer_id uuid NOT NULL REFERENCES peers(id) ON DELETE CASCADE,
+    sampled_at TIMESTAMP NOT NULL,
+    rx BIGINT NOT NULL,
+    tx BIGINT NOT NULL,
+    PRIMARY KEY (peer_id, sampled_at)
+);
+CREATE INDEX peer_traffic_samples_sampled_at ON peer_traffic_samples (sampled_at);
+
+-- Samples summed per hour, and hours summed per day
+CREATE TABLE peer_traffic_rollups
+(
+    peer_id uuid NOT NULL REFERENCES peers(id) ON DELETE CASCADE,
+    resolution VARCHAR(8) NOT NULL CHECK (resolution IN ('hour', 'day')),
+    bucket_start TIMESTAMP NOT NULL,
+    rx BIGINT NOT NULL,
+    tx BIGINT NOT NULL,
+    PRIMARY KEY (peer_id, resolution, bucket_start)
+);
+CREATE INDEX peer_traffic_rollups_bucket_start ON peer_traffic_rollups (resolution, bucket_start);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- A connection of a peer, from its first handshake to the last one before a gap
+CREATE TABLE peer_sessions
+(
+    id BIGSERIAL PRIMARY KEY,
+    peer_id uuid NOT NULL REFERENCES peers(id) ON DELETE CASCADE,
+    started_at TIMESTAMP NOT NULL,
+    last_handshake_at TIMESTAMP NOT NULL,
+    -- NULL while the session is open
+    ended_at TIMESTAMP,
+    endpoint_addr VARCHAR,
+    rx BIGINT NOT NULL DEFAULT 0,
+    tx BIGINT NOT NULL DEFAULT 0
+);
+CREATE INDEX peer_sessions_peer_id_started_at ON peer_sessions (peer_id, started_at DESC);
+-- at most one open session per peer
+CREATE UNIQUE INDEX peer_sessions_open ON peer_sessions (peer_id) WHERE ended_at IS NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE peer_events;
+ALTER TABLE peers DROP COLUMN expires_at;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Temporary access, the peer is disabled once it passed. NULL never expires
+ALTER TABLE peers ADD COLUMN expires_at TIMESTAMP;
+CREATE INDEX peers_expires_at ON peers (expires_at) WHERE expires_at IS NOT NULL;
+
+-- What the server did to a peer on its own, and why. Kept after the peer is deleted
+CREATE TABLE peer_events
+(
+    id BIGSERIAL PRIMARY KEY,
+    peer_id uuid NOT NULL,
+    peer_name VARCHAR NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    detail TEXT,
+    created_at TIMESTAMP NOT NULL
+);
+CREATE INDEX peer_events_peer_id_created_at ON peer_events (peer_id, created_at);
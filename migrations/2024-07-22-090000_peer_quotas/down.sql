@@ -0,0 +1,23 @@
+-- This file should undo anything in `up.sql`
+CREATE OR REPLACE FUNCTION peers_set_updated_at() RETURNS trigger AS $$
+DECLARE
+    usage_columns TEXT[] := ARRAY['transfer_rx', 'transfer_tx', 'kernel_rx', 'kernel_tx',
+        'last_handshake_at', 'endpoint_addr', 'usage_updated_at', 'updated_at'];
+BEGIN
+    IF (
+        to_jsonb(NEW) - usage_columns IS DISTINCT FROM to_jsonb(OLD) - usage_columns AND
+        NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+    ) THEN
+        NEW.updated_at := current_timestamp;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE peers DROP CONSTRAINT peers_quota_period_set;
+ALTER TABLE peers DROP COLUMN quota_exceeded_at;
+ALTER TABLE peers DROP COLUMN quota_warned_percent;
+ALTER TABLE peers DROP COLUMN quota_period_start;
+ALTER TABLE peers DROP COLUMN quota_used_from;
+ALTER TABLE peers DROP COLUMN quota_period;
+ALTER TABLE peers DROP COLUMN quota_bytes;
@@ -0,0 +1,29 @@
+-- Your SQL goes here
+-- A cap on rx + tx of a peer, per billing month or over its lifetime. NULL is unlimited
+ALTER TABLE peers ADD COLUMN quota_bytes BIGINT CHECK (quota_bytes > 0);
+ALTER TABLE peers ADD COLUMN quota_period VARCHAR(8) CHECK (quota_period IN ('monthly', 'total'));
+ALTER TABLE peers ADD CONSTRAINT peers_quota_period_set CHECK ((quota_bytes IS NULL) = (quota_period IS NULL));
+-- transfer_rx + transfer_tx when the current period began
+ALTER TABLE peers ADD COLUMN quota_used_from BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE peers ADD COLUMN quota_period_start TIMESTAMP;
+-- the highest warning threshold recorded in the current period
+ALTER TABLE peers ADD COLUMN quota_warned_percent INTEGER NOT NULL DEFAULT 0;
+-- set while the peer is disabled for exceeding its quota
+ALTER TABLE peers ADD COLUMN quota_exceeded_at TIMESTAMP;
+
+-- Enforcing the quota is not an edit of the peer either
+CREATE OR REPLACE FUNCTION peers_set_updated_at() RETURNS trigger AS $$
+DECLARE
+    usage_columns TEXT[] := ARRAY['transfer_rx', 'transfer_tx', 'kernel_rx', 'kernel_tx',
+        'last_handshake_at', 'endpoint_addr', 'usage_updated_at', 'updated_at',
+        'quota_used_from', 'quota_period_start', 'quota_warned_percent', 'quota_exceeded_at'];
+BEGIN
+    IF (
+        to_jsonb(NEW) - usage_columns IS DISTINCT FROM to_jsonb(OLD) - usage_columns AND
+        NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+    ) THEN
+        NEW.updated_at := current_timestamp;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+-- Rotating refresh tokens, stored as their SHA-256. A login starts a family, every refresh
+-- uses up the token it was given and adds the next one to the same family
+CREATE TABLE refresh_tokens
+(
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    family_id uuid NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    -- the jti of the access token issued along with it
+    access_jti uuid NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    revoked_at TIMESTAMP
+);
+CREATE INDEX refresh_tokens_family_id ON refresh_tokens (family_id);
+CREATE INDEX refresh_tokens_user_id ON refresh_tokens (user_id);
+CREATE INDEX refresh_tokens_access_jti ON refresh_tokens (access_jti);
+
+-- Access tokens revoked before their exp, kept until they would have expired anyway
+CREATE TABLE revoked_tokens
+(
+    jti uuid PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- What a user may do. Users from before roles could do everything and stay admins,
+-- new ones start as viewers
+ALTER TABLE users ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT 'admin'
+    CHECK (role IN ('admin', 'operator', 'viewer'));
+ALTER TABLE users ALTER COLUMN role SET DEFAULT 'viewer';
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- The user a peer belongs to. Peers from before, and those of deleted users, have no
+-- owner and are left to the admins
+ALTER TABLE peers ADD COLUMN owner_id uuid REFERENCES users (id) ON DELETE SET NULL;
+CREATE INDEX peers_owner_id ON peers (owner_id);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Long-lived tokens for scripts and service accounts, stored as their SHA-256. A token acts
+-- as its user, limited to its scopes
+CREATE TABLE api_tokens
+(
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    -- the start of the token, to tell the tokens of a user apart
+    prefix VARCHAR(16) NOT NULL,
+    scopes TEXT[] NOT NULL,
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+CREATE INDEX api_tokens_user_id ON api_tokens (user_id);
@@ -1,4 +1,5 @@
 use std::env;
+use std::time::Duration;
 
 use axum::http::header::{
     ACCEPT, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS,
@@ -19,9 +20,11 @@ struct ServerConfig {
     jwt_algorithm: Algorithm,
     allow_methods: Vec<Method>,
     jwt_exp_minutes: i64,
+    refresh_token_days: i64,
     allow_headers: Vec<HeaderName>,
     hash_cost:u32,
     timeout_secs:u64,
+    metrics_bind: String,
 }
 
 #[derive(Debug)]
@@ -33,10 +36,50 @@ struct DatabaseConfig {
     db_port: String,
 }
 
+#[derive(Debug)]
+struct WireguardConfig {
+    backend: String,
+    reconcile_on_startup: bool,
+    endpoint_host: String,
+    client_dns: Option<String>,
+    config_dir: Option<String>,
+    poll_interval_secs: u64,
+    live_interval_secs: u64,
+    online_secs: i64,
+    idle_secs: i64,
+    expired_delete_after_hours: Option<i64>,
+}
+
+// How long the traffic history is kept at each resolution
+#[derive(Debug)]
+struct TrafficConfig {
+    sample_retention_hours: i64,
+    hourly_retention_days: i64,
+    daily_retention_days: i64,
+}
+
+// When monthly quotas start over and at which shares of a quota a warning is recorded
+#[derive(Debug)]
+struct QuotaConfig {
+    billing_day: u32,
+    warn_percents: Vec<i32>,
+}
+
+// Who manages which peers, see service::ownership
+#[derive(Debug)]
+struct OwnershipConfig {
+    self_service: bool,
+    max_peers_per_user: Option<i64>,
+}
+
 #[derive(Debug)]
 pub struct Config {
     server: ServerConfig,
     db: DatabaseConfig,
+    wg: WireguardConfig,
+    traffic: TrafficConfig,
+    quota: QuotaConfig,
+    ownership: OwnershipConfig,
 }
 
 impl Config {
@@ -57,6 +100,8 @@ impl Config {
     pub fn bind(&self) -> String {
         format!("{}:{}", self.server.host, self.server.port)
     }
+    // Separate, internal address for /metrics, it is never served with the api
+    pub fn metrics_bind(&self) -> &str { &self.server.metrics_bind }
     pub fn hash_cost(&self) -> u32 { self.server.hash_cost }
     pub fn timeout_secs(&self) -> u64 { self.server.timeout_secs }
 
@@ -80,9 +125,76 @@ impl Config {
         self.server.allow_methods.clone()
     }
     pub fn jwt_exp_minutes(&self) -> i64 { self.server.jwt_exp_minutes }
+    // how long a refresh token can be exchanged, every refresh starts it over
+    pub fn refresh_token_days(&self) -> i64 {
+        self.server.refresh_token_days
+    }
     pub fn allow_headers(&self) -> Vec<HeaderName> {
         self.server.allow_headers.clone()
     }
+    // "sudo" drives the kernel through `sudo wg`, "fake" keeps an in-memory simulation
+    pub fn wg_backend(&self) -> &str {
+        &self.wg.backend
+    }
+    // Converge every interface to the database when the server starts
+    pub fn wg_reconcile_on_startup(&self) -> bool {
+        self.wg.reconcile_on_startup
+    }
+    // Public host clients use to reach the interfaces, the port comes from each interface
+    pub fn wg_endpoint_host(&self) -> &str {
+        &self.wg.endpoint_host
+    }
+    pub fn wg_client_dns(&self) -> Option<&str> {
+        self.wg.client_dns.as_deref()
+    }
+    // Directory the server configs are installed to, e.g. /etc/wireguard, none when unset
+    pub fn wg_config_dir(&self) -> Option<&str> {
+        self.wg.config_dir.as_deref()
+    }
+    // How often peer usage is read from the interfaces, None when polling is off
+    pub fn wg_poll_interval(&self) -> Option<Duration> {
+        (self.wg.poll_interval_secs > 0).then(|| Duration::from_secs(self.wg.poll_interval_secs))
+    }
+    // How often the live status is read while a dashboard is subscribed
+    pub fn wg_live_interval(&self) -> Duration {
+        Duration::from_secs(self.wg.live_interval_secs)
+    }
+    // Peers with a handshake up to this old are online, up to peer_idle_within idle, past it offline
+    pub fn peer_online_within(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.wg.online_secs)
+    }
+    pub fn peer_idle_within(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.wg.idle_secs)
+    }
+    // How long expired peers are kept, disabled, before they are deleted. None keeps them
+    pub fn peer_expired_delete_after(&self) -> Option<chrono::Duration> {
+        self.wg.expired_delete_after_hours.map(chrono::Duration::hours)
+    }
+    // Raw samples, hourly and daily buckets older than these are deleted
+    pub fn traffic_sample_retention(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.traffic.sample_retention_hours)
+    }
+    pub fn traffic_hourly_retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.traffic.hourly_retention_days)
+    }
+    pub fn traffic_daily_retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.traffic.daily_retention_days)
+    }
+    // Day of the month monthly quotas start over, at midnight UTC
+    pub fn quota_billing_day(&self) -> u32 {
+        self.quota.billing_day
+    }
+    pub fn quota_warn_percents(&self) -> &[i32] {
+        &self.quota.warn_percents
+    }
+    // viewers create and change their own peers, as operators do
+    pub fn peer_self_service(&self) -> bool {
+        self.ownership.self_service
+    }
+    // peers a user other than an admin may own, None for no limit
+    pub fn max_peers_per_user(&self) -> Option<i64> {
+        self.ownership.max_peers_per_user
+    }
 }
 
 // Create a static OnceCell to store the application configuration
@@ -102,6 +214,10 @@ async fn init_config() -> Config {
         log_level: env::var("LOG_LEVEL").unwrap(),
         secret: env::var("SECRET").unwrap_or("secret is missing!".to_string()),
         hash_cost:env::var("HASH_COST").expect("HASH_COST must be set ").parse().unwrap(),
+        metrics_bind: env::var("METRICS_BIND")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| String::from("127.0.0.1:9100")),
         timeout_secs:env::var("APP_REQUEST_TIMEOUT_SECONDS").expect("APP_REQUEST_TIMEOUT_SECONDS must be set ").parse().unwrap(),
         allow_origin: env::var("ALLOWED_ORIGINS")
             .unwrap_or("[*]".to_string())
@@ -122,7 +238,11 @@ async fn init_config() -> Config {
             Method::OPTIONS,
         ],
         jwt_exp_minutes: env::var("JWT_EXP_MINUTES")
-            .unwrap_or(180.to_string())
+            .unwrap_or(15.to_string())
+            .parse()
+            .unwrap(),
+        refresh_token_days: env::var("REFRESH_TOKEN_DAYS")
+            .unwrap_or(30.to_string())
             .parse()
             .unwrap(),
         allow_headers: vec![
@@ -143,10 +263,103 @@ async fn init_config() -> Config {
         db_port: env::var("PG_PORT").expect("PG_PORT must be set"),
     };
 
-    // Create a Config instance by combining server and database configurations
+    let wireguard_config = WireguardConfig {
+        backend: env::var("WG_BACKEND").unwrap_or_else(|_| String::from("sudo")),
+        reconcile_on_startup: env::var("WG_RECONCILE_ON_STARTUP")
+            .unwrap_or_else(|_| String::from("false"))
+            .parse()
+            .expect("WG_RECONCILE_ON_STARTUP must be true or false"),
+        endpoint_host: env::var("WG_ENDPOINT_HOST").unwrap_or_else(|_| String::from("127.0.0.1")),
+        client_dns: env::var("WG_CLIENT_DNS").ok().filter(|v| !v.is_empty()),
+        config_dir: env::var("WG_CONFIG_DIR").ok().filter(|v| !v.is_empty()),
+        poll_interval_secs: env::var("WG_POLL_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| String::from("30"))
+            .parse()
+            .expect("WG_POLL_INTERVAL_SECONDS must be a number of seconds, 0 turns polling off"),
+        live_interval_secs: env::var("WG_LIVE_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| String::from("5"))
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .expect("WG_LIVE_INTERVAL_SECONDS must be a positive number of seconds"),
+        online_secs: env::var("PEER_ONLINE_SECONDS")
+            .unwrap_or_else(|_| String::from("180"))
+            .parse()
+            .expect("PEER_ONLINE_SECONDS must be a number of seconds"),
+        idle_secs: env::var("PEER_IDLE_SECONDS")
+            .unwrap_or_else(|_| String::from("900"))
+            .parse()
+            .expect("PEER_IDLE_SECONDS must be a number of seconds"),
+        expired_delete_after_hours: env::var("PEER_EXPIRED_DELETE_AFTER_HOURS")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().expect("PEER_EXPIRED_DELETE_AFTER_HOURS must be a number of hours")),
+    };
+    assert!(
+        wireguard_config.idle_secs >= wireguard_config.online_secs,
+        "PEER_IDLE_SECONDS must not be below PEER_ONLINE_SECONDS"
+    );
+
+    let traffic_config = TrafficConfig {
+        sample_retention_hours: env::var("TRAFFIC_SAMPLE_RETENTION_HOURS")
+            .unwrap_or_else(|_| String::from("48"))
+            .parse()
+            .expect("TRAFFIC_SAMPLE_RETENTION_HOURS must be a number of hours"),
+        hourly_retention_days: env::var("TRAFFIC_HOURLY_RETENTION_DAYS")
+            .unwrap_or_else(|_| String::from("31"))
+            .parse()
+            .expect("TRAFFIC_HOURLY_RETENTION_DAYS must be a number of days"),
+        daily_retention_days: env::var("TRAFFIC_DAILY_RETENTION_DAYS")
+            .unwrap_or_else(|_| String::from("730"))
+            .parse()
+            .expect("TRAFFIC_DAILY_RETENTION_DAYS must be a number of days"),
+    };
+
+    let quota_config = QuotaConfig {
+        billing_day: env::var("QUOTA_BILLING_DAY")
+            .unwrap_or_else(|_| String::from("1"))
+            .parse()
+            .ok()
+            .filter(|day| (1..=28).contains(day))
+            .expect("QUOTA_BILLING_DAY must be a day of the month from 1 to 28"),
+        warn_percents: env::var("QUOTA_WARN_PERCENTS")
+            .unwrap_or_else(|_| String::from("80,90"))
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse()
+                    .ok()
+                    .filter(|percent| (1..=100).contains(percent))
+                    .expect("QUOTA_WARN_PERCENTS must be a list of percentages from 1 to 100")
+            })
+            .collect(),
+    };
+
+    let ownership_config = OwnershipConfig {
+        self_service: env::var("PEER_SELF_SERVICE")
+            .unwrap_or_else(|_| String::from("false"))
+            .parse()
+            .expect("PEER_SELF_SERVICE must be true or false"),
+        max_peers_per_user: env::var("MAX_PEERS_PER_USER")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse()
+                    .ok()
+                    .filter(|max| *max >= 0)
+                    .expect("MAX_PEERS_PER_USER must be a number of peers")
+            }),
+    };
+
+    // Create a Config instance by combining server, database, wireguard, traffic, quota and ownership configurations
     Config {
         server: server_config,
         db: database_config,
+        wg: wireguard_config,
+        traffic: traffic_config,
+        quota: quota_config,
+        ownership: ownership_config,
     }
 }
 
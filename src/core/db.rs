@@ -1,6 +1,5 @@
 use deadpool_diesel::postgres::Pool;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use crate::errors::internal_error;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/");
 // Function to run database migrations
@@ -49,3 +48,70 @@ pub async fn run_migrations(pool: &Pool) {
 // {
 //     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 // }
+
+// A database from before keys were generated: every peer carries the literal 'public_key'
+#[cfg(test)]
+mod tests {
+    use diesel::dsl::sql;
+    use diesel::migration::MigrationSource;
+    use diesel::pg::Pg;
+    use diesel::prelude::*;
+    use diesel::sql_types::BigInt;
+    use diesel_migrations::MigrationHarness;
+
+    use super::MIGRATIONS;
+
+    const UNIQUE_PUBLIC_KEY: &str = "2024-07-01-120000_peers_unique_public_key";
+
+    fn count(conn: &mut PgConnection, table: &str) -> i64 {
+        diesel::select(sql::<BigInt>(&format!("(SELECT count(*) FROM {table})")))
+            .get_result(conn)
+            .unwrap()
+    }
+
+    #[test]
+    #[ignore = "requires a PostgreSQL database in TEST_DATABASE_URL"]
+    fn sets_aside_peers_without_keys() {
+        let url = std::env::var("TEST_DATABASE_URL")
+            .unwrap_or_else(|_| "postgres://postgres@127.0.0.1/rustify_test".to_string());
+        let mut conn = PgConnection::establish(&url).unwrap();
+        // a schema of its own, the other tests share the migrated public one
+        let schema = format!("pre_keys_{}", uuid::Uuid::new_v4().simple());
+        diesel::sql_query(format!("CREATE SCHEMA {schema}")).execute(&mut conn).unwrap();
+        diesel::sql_query(format!("SET search_path TO {schema}")).execute(&mut conn).unwrap();
+
+        // sets up the table the applied migrations are recorded in
+        conn.applied_migrations().unwrap();
+        let mut migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS).unwrap();
+        migrations.sort_by_key(|migration| migration.name().to_string());
+        for migration in migrations
+            .iter()
+            .take_while(|migration| migration.name().to_string().as_str() < UNIQUE_PUBLIC_KEY)
+        {
+            conn.run_migration(migration).unwrap();
+        }
+        diesel::sql_query(
+            "INSERT INTO wg_if (name, pubkey, privkey, address, port) \
+             VALUES ('wg0', 'pubkey', 'privkey', '10.8.0.1/24', 51820)",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        for name in ["laptop", "phone"] {
+            diesel::sql_query(format!(
+                "INSERT INTO peers (name, private_key, public_key, if_pubkey, address, interface_id) \
+                 VALUES ('{name}', 'private_key', 'public_key', 'new_peer.name', '', \
+                 (SELECT id FROM wg_if))"
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        }
+
+        let migrated = conn.run_pending_migrations(MIGRATIONS).map(|_| ()).map_err(|err| err.to_string());
+        let counts = migrated
+            .is_ok()
+            .then(|| (count(&mut conn, "peers"), count(&mut conn, "peers_without_keys")));
+        diesel::sql_query(format!("DROP SCHEMA {schema} CASCADE")).execute(&mut conn).unwrap();
+        migrated.unwrap();
+        assert_eq!(counts, Some((0, 2)));
+    }
+}
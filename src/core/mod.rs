@@ -4,4 +4,4 @@ pub(crate) mod security;
 // pub mod app_state;
 pub use config::{get_config, Config};
 pub use db::run_migrations;
-pub use security::{create_token, hash_password, verify_password, verify_token};
+pub use security::{create_token, hash_password, verify_password, verify_token, Claims, RevokedTokens};
@@ -1,23 +1,49 @@
+use std::collections::HashMap;
 use std::ops::Add;
+use std::sync::RwLock;
 use bcrypt::{hash, verify};
 
 use crate::core::get_config;
-use chrono::{Utc,Duration};
+use crate::domain::models::user::Role;
+use chrono::{NaiveDateTime, Utc,Duration};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::errors::{ApiError, BaseError, Result as AppResult};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: Uuid,
     pub name: String,
     pub exp: usize,
     pub iat: usize,
+    // identifies the token for revocation
+    pub jti: Uuid,
+    pub role: Role,
 }
 
-pub async fn create_token(sub: Uuid,name:String, key_enc: EncodingKey) -> String {
+// Access tokens revoked before their exp, by jti. Checked on every request, so it is kept in
+// memory and reloaded from the revoked_tokens table.
+#[derive(Debug, Default)]
+pub struct RevokedTokens(RwLock<HashMap<Uuid, NaiveDateTime>>);
+
+impl RevokedTokens {
+    pub fn is_revoked(&self, jti: &Uuid) -> bool {
+        self.0.read().unwrap().contains_key(jti)
+    }
+
+    pub fn extend(&self, tokens: impl IntoIterator<Item = (Uuid, NaiveDateTime)>) {
+        self.0.write().unwrap().extend(tokens);
+    }
+
+    // Swaps in the list as stored, which also drops the tokens that expired by now
+    pub fn replace(&self, tokens: impl IntoIterator<Item = (Uuid, NaiveDateTime)>) {
+        *self.0.write().unwrap() = tokens.into_iter().collect();
+    }
+}
+
+pub async fn create_token(sub: Uuid,name:String, role: Role, jti: Uuid, key_enc: EncodingKey) -> String {
     let config = get_config().await;
     let jwt_duration = config.jwt_exp_minutes();
     let iat = Utc::now();
@@ -27,18 +53,27 @@ pub async fn create_token(sub: Uuid,name:String, key_enc: EncodingKey) -> String
         name,
         exp: exp.timestamp() as usize,
         iat: iat.timestamp() as usize,
+        jti,
+        role,
     };
     encode(&Header::new(config.jwt_algorithm()), &claims, &key_enc).expect("JWT encode should work")
 }
 
-pub fn verify_token(key: DecodingKey, algorithm: Algorithm, token: &str) -> AppResult<String> {
+pub fn verify_token(
+    key: DecodingKey,
+    algorithm: Algorithm,
+    token: &str,
+    revoked: &RevokedTokens,
+) -> AppResult<Claims> {
     let validations = Validation::new(algorithm);
     // validations.validate_exp=true;
-    Ok(
-        decode::<Claims>(token, &key, &validations)?
-            .claims
-            .name
-    )
+    let claims = decode::<Claims>(token, &key, &validations)?.claims;
+    if revoked.is_revoked(&claims.jti) {
+        return Err(BaseError::AuthFailJwtInvalid {
+            source: "Revoked".to_string(),
+        });
+    }
+    Ok(claims)
 }
 
 pub fn verify_password(req_id: Uuid, password: &str, hash_value: &str) -> Result<bool, ApiError> {
@@ -1,3 +1,7 @@
+use std::time::{Duration, Instant};
+
+use crate::core::Claims;
+use crate::domain::models::user::{ApiScope, Role};
 use crate::errors::*;
 use axum::extract::FromRequestParts;
 use tracing::debug;
@@ -5,20 +9,75 @@ use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 pub struct Ctx {
-    result_user_id: Result<String>,
+    result_claims: Result<Claims>,
+    // the scopes of an API token, None for a login session
+    scopes: Option<Vec<ApiScope>>,
     req_id: Uuid,
+    started_at: Instant,
 }
 
 impl Ctx {
-    pub fn new(result_user_id: Result<String>, uuid: Uuid) -> Self {
+    pub fn new(result_claims: Result<Claims>, uuid: Uuid) -> Self {
         Self {
-            result_user_id,
+            result_claims,
+            scopes: None,
             req_id: uuid,
+            started_at: Instant::now(),
         }
     }
 
+    // The caller authenticated with an API token
+    pub fn with_scopes(mut self, scopes: Vec<ApiScope>) -> Self {
+        self.scopes = Some(scopes);
+        self
+    }
+
+    pub fn is_api_token(&self) -> bool {
+        self.scopes.is_some()
+    }
+
     pub fn user_id(&self) -> ApiResult<String> {
-        self.result_user_id.clone().map_err(|error| ApiError {
+        self.claims().map(|claims| claims.name)
+    }
+
+    // Ok when the caller has `role` or a higher one, 403 when authenticated with a lower one
+    pub fn require(&self, role: Role) -> ApiResult<()> {
+        let claims = self.claims()?;
+        if claims.role < role {
+            return Err(self.forbidden(format!("this requires the {} role", role.as_str())));
+        }
+        Ok(())
+    }
+
+    // Ok for login sessions and for API tokens with a scope granting `scope`
+    pub fn require_scope(&self, scope: ApiScope) -> ApiResult<()> {
+        self.claims()?;
+        match &self.scopes {
+            Some(scopes) if !scopes.iter().any(|granted| granted.grants(scope)) => {
+                Err(self.forbidden(format!("this requires the {} scope", scope.as_str())))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Ok for login sessions only, API tokens can not manage users, sessions or tokens
+    pub fn require_session(&self) -> ApiResult<()> {
+        self.claims()?;
+        if self.scopes.is_some() {
+            return Err(self.forbidden("this is not available to API tokens".to_string()));
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self, source: String) -> ApiError {
+        ApiError {
+            error: BaseError::Forbidden { source },
+            req_id: self.req_id,
+        }
+    }
+
+    pub fn claims(&self) -> ApiResult<Claims> {
+        self.result_claims.clone().map_err(|error| ApiError {
             error,
             req_id: self.req_id,
         })
@@ -27,6 +86,11 @@ impl Ctx {
     pub fn req_id(&self) -> Uuid {
         self.req_id
     }
+
+    // time since the request came in
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
 }
 
 // ugly but direct implementation from axum, until "async trait fn" are in stable rust, instead of importing some 3rd party macro
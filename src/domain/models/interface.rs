@@ -0,0 +1,192 @@
+use std::fmt;
+use std::fmt::Formatter;
+use crate::infra::errors::InfraError;
+use crate::domain::models::ipam::parse_networks;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use chrono::Utc;
+use serde_json::json;
+
+// Linux IFNAMSIZ is 16 including the trailing NUL
+const MAX_NAME_LEN: usize = 15;
+// WireGuard refuses to carry IPv6 below the IPv6 minimum MTU
+const MIN_MTU: i32 = 1280;
+const MAX_MTU: i32 = 65535;
+// pseudo interface name asking `wg show` for every interface
+pub const ALL_INTERFACES: &str = "all";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceModel {
+    pub id: i32,
+    pub name: String,
+    pub pubkey: String,
+    pub privkey: String,
+    pub address: String,
+    pub port: i32,
+    pub mtu: Option<i32>,
+    pub fwmark: Option<i32>,
+    // wg-quick hooks, run by the shell as root when the interface goes up / down
+    pub post_up: Option<String>,
+    pub post_down: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum InterfaceError {
+    InternalServerError,
+    NotFound(i32),
+    InfraError(InfraError),
+    NameExists(String),
+    HasPeers(i64),
+    // the kernel interface and the peers' keys and addresses depend on the name and address
+    PeersPinned(i64),
+    Invalid(String),
+}
+
+impl fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InterfaceError::InternalServerError => write!(f, "InternalServerError"),
+            InterfaceError::NotFound(id) => write!(f, "interface with id : {id} not found"),
+            InterfaceError::InfraError(_err) => write!(f, "infra error"),
+            InterfaceError::NameExists(name) => write!(f, "interface {name} already exists"),
+            InterfaceError::HasPeers(count) => {
+                write!(f, "interface still has {count} peer(s), remove them first")
+            }
+            InterfaceError::PeersPinned(count) => write!(
+                f,
+                "the name and address of an interface with {count} peer(s) can not change, remove them first"
+            ),
+            InterfaceError::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl From<InfraError> for InterfaceError {
+    fn from(value: InfraError) -> Self {
+        InterfaceError::InfraError(value)
+    }
+}
+
+impl IntoResponse for InterfaceError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::NameExists(_) | Self::HasPeers(_) | Self::PeersPinned(_) => StatusCode::CONFLICT,
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (
+            status,
+            Json(
+                json!({"resource":"InterfaceModel", "message": self.to_string(), "happened_at" :  Utc::now().timestamp() }),
+            ),
+        )
+            .into_response()
+    }
+}
+
+// Same rule wg-quick applies to interface names
+pub fn validate_name(name: &str) -> Result<(), InterfaceError> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_=+.-".contains(c));
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !valid_chars {
+        return Err(InterfaceError::Invalid(format!(
+            "name must be 1 to {MAX_NAME_LEN} characters of [a-zA-Z0-9_=+.-]"
+        )));
+    }
+    if name == ALL_INTERFACES {
+        return Err(InterfaceError::Invalid(format!(
+            "{ALL_INTERFACES} is what `wg show` takes for every interface, pick another name"
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate_port(port: i32) -> Result<(), InterfaceError> {
+    if !(1..=65535).contains(&port) {
+        return Err(InterfaceError::Invalid("port must be between 1 and 65535".to_string()));
+    }
+    Ok(())
+}
+
+pub fn validate_mtu(mtu: Option<i32>) -> Result<(), InterfaceError> {
+    match mtu {
+        Some(mtu) if !(MIN_MTU..=MAX_MTU).contains(&mtu) => Err(InterfaceError::Invalid(format!(
+            "mtu must be between {MIN_MTU} and {MAX_MTU}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+pub fn validate_fwmark(fwmark: Option<i32>) -> Result<(), InterfaceError> {
+    match fwmark {
+        Some(fwmark) if fwmark < 0 => {
+            Err(InterfaceError::Invalid("fwmark must not be negative".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+// A PostUp / PostDown command is a single config line, a line break would smuggle in other keys
+pub fn validate_hook(hook: Option<&str>) -> Result<(), InterfaceError> {
+    match hook {
+        Some(hook) if hook.contains(['\n', '\r']) => Err(InterfaceError::Invalid(
+            "post_up and post_down must fit on one line, separate commands with `;`".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// One or more comma separated CIDRs, e.g. "10.8.0.1/24, fd00:8::1/64"
+pub fn validate_address(address: &str) -> Result<(), InterfaceError> {
+    match parse_networks(address) {
+        Ok(networks) if !networks.is_empty() => Ok(()),
+        Ok(_) => Err(InterfaceError::Invalid("address must not be empty".to_string())),
+        Err(err) => Err(InterfaceError::Invalid(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert!(validate_name("wg0").is_ok());
+        assert!(validate_name("wg-office_1.v2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a-very-long-name0").is_err());
+        assert!(validate_name("wg 0").is_err());
+        assert!(validate_name("wg/0").is_err());
+        assert!(validate_name(ALL_INTERFACES).is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert!(validate_port(51820).is_ok());
+        assert!(validate_port(0).is_err());
+        assert!(validate_port(65536).is_err());
+        assert!(validate_mtu(None).is_ok());
+        assert!(validate_mtu(Some(1420)).is_ok());
+        assert!(validate_mtu(Some(1000)).is_err());
+        assert!(validate_fwmark(Some(0)).is_ok());
+        assert!(validate_fwmark(Some(-1)).is_err());
+    }
+
+    #[test]
+    fn hooks() {
+        assert!(validate_hook(None).is_ok());
+        assert!(validate_hook(Some("iptables -A FORWARD -i %i -j ACCEPT; sysctl -q net.ipv4.ip_forward=1")).is_ok());
+        assert!(validate_hook(Some("true\nPrivateKey = x")).is_err());
+    }
+
+    #[test]
+    fn addresses() {
+        assert!(validate_address("10.8.0.1/24").is_ok());
+        assert!(validate_address("10.8.0.1/24, fd00:8::1/64").is_ok());
+        assert!(validate_address("10.8.0.1").is_err());
+        assert!(validate_address(" ").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+use crate::infra::errors::InfraError;
+
+// Hands out tunnel addresses to peers from the subnet(s) configured on `wg_if.address`,
+// e.g. "10.8.0.1/24" or "10.8.0.1/24, fd00:8::1/64" for a dual stack interface.
+// Peers get one host address per subnet, stored as "10.8.0.2/32, fd00:8::2/128".
+
+#[derive(Debug)]
+pub enum IpamError {
+    InvalidNetwork(String),
+    InvalidAddress(String),
+    OutOfRange(IpAddr),
+    AddressInUse(IpAddr),
+    Exhausted(IpNet),
+    Infra(InfraError),
+}
+
+impl fmt::Display for IpamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpamError::InvalidNetwork(net) => write!(f, "interface address {net} is not a valid CIDR"),
+            IpamError::InvalidAddress(addr) => write!(f, "{addr} is not a valid IP address"),
+            IpamError::OutOfRange(addr) => {
+                write!(f, "{addr} is not an assignable host address of the interface subnet")
+            }
+            IpamError::AddressInUse(addr) => write!(f, "{addr} is already assigned to another peer"),
+            IpamError::Exhausted(net) => write!(f, "no free addresses left in {net}"),
+            IpamError::Infra(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<InfraError> for IpamError {
+    fn from(value: InfraError) -> Self {
+        IpamError::Infra(value)
+    }
+}
+
+pub fn parse_networks(if_address: &str) -> Result<Vec<IpNet>, IpamError> {
+    if_address
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse::<IpNet>().map_err(|_| IpamError::InvalidNetwork(v.to_string())))
+        .collect()
+}
+
+// Addresses of a peer, tolerating both "10.8.0.2" and "10.8.0.2/32" notations
+pub fn parse_addresses(address: &str) -> Vec<IpAddr> {
+    address
+        .split(',')
+        .map(str::trim)
+        .filter_map(|v| {
+            v.parse::<IpNet>()
+                .map(|net| net.addr())
+                .or_else(|_| v.parse::<IpAddr>())
+                .ok()
+        })
+        .collect()
+}
+
+// Picks one address per interface subnet, honouring the explicitly requested ones
+pub fn allocate(
+    if_address: &str,
+    used: &[String],
+    requested: Option<&str>,
+) -> Result<String, IpamError> {
+    let networks = parse_networks(if_address)?;
+    if networks.is_empty() {
+        return Err(IpamError::InvalidNetwork(if_address.to_string()));
+    }
+    let used: HashSet<IpAddr> = used.iter().flat_map(|v| parse_addresses(v)).collect();
+    let requested = match requested {
+        Some(requested) => parse_requested(requested)?,
+        None => vec![],
+    };
+    if let Some(stray) = requested
+        .iter()
+        .find(|addr| !networks.iter().any(|net| net.contains(*addr)))
+    {
+        return Err(IpamError::OutOfRange(*stray));
+    }
+
+    let mut allocated = Vec::with_capacity(networks.len());
+    for network in networks {
+        let address = match requested.iter().find(|addr| network.contains(*addr)) {
+            Some(addr) => {
+                if !is_assignable(&network, addr) {
+                    return Err(IpamError::OutOfRange(*addr));
+                }
+                if used.contains(addr) {
+                    return Err(IpamError::AddressInUse(*addr));
+                }
+                *addr
+            }
+            None => network
+                .hosts()
+                .find(|addr| is_assignable(&network, addr) && !used.contains(addr))
+                .ok_or(IpamError::Exhausted(network.trunc()))?,
+        };
+        allocated.push(IpNet::from(address).to_string());
+    }
+    Ok(allocated.join(", "))
+}
+
+// For addresses handed out elsewhere, e.g. peers adopted from an existing setup
+pub fn check_free(address: &str, used: &[String]) -> Result<(), IpamError> {
+    let used: HashSet<IpAddr> = used.iter().flat_map(|v| parse_addresses(v)).collect();
+    match parse_addresses(address).into_iter().find(|addr| used.contains(addr)) {
+        Some(addr) => Err(IpamError::AddressInUse(addr)),
+        None => Ok(()),
+    }
+}
+
+fn parse_requested(requested: &str) -> Result<Vec<IpAddr>, IpamError> {
+    requested
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse::<IpAddr>()
+                .or_else(|_| v.parse::<IpNet>().map(|net| net.addr()))
+                .map_err(|_| IpamError::InvalidAddress(v.to_string()))
+        })
+        .collect()
+}
+
+// Not the interface's own address, nor the network/broadcast address of the subnet
+fn is_assignable(network: &IpNet, addr: &IpAddr) -> bool {
+    *addr != network.addr()
+        && (network.prefix_len() >= network.max_prefix_len() - 1
+            || (*addr != network.network() && *addr != network.broadcast()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_interface_address() {
+        assert_eq!(allocate("10.8.0.1/24", &[], None).unwrap(), "10.8.0.2/32");
+        assert_eq!(
+            allocate("10.8.0.1/24", &["10.8.0.2/32".to_string(), "10.8.0.4".to_string()], None)
+                .unwrap(),
+            "10.8.0.3/32"
+        );
+        // the interface does not have to sit on the first host address
+        assert_eq!(allocate("10.8.0.5/24", &[], None).unwrap(), "10.8.0.1/32");
+    }
+
+    #[test]
+    fn dual_stack() {
+        let used = vec!["10.8.0.2/32, fd00:8::2/128".to_string()];
+        assert_eq!(
+            allocate("10.8.0.1/24, fd00:8::1/64", &used, None).unwrap(),
+            "10.8.0.3/32, fd00:8::3/128"
+        );
+    }
+
+    #[test]
+    fn requested_address() {
+        let used = vec!["10.8.0.2/32".to_string()];
+        assert_eq!(
+            allocate("10.8.0.1/24", &used, Some("10.8.0.42")).unwrap(),
+            "10.8.0.42/32"
+        );
+        assert!(matches!(
+            allocate("10.8.0.1/24", &used, Some("10.8.0.2/32")),
+            Err(IpamError::AddressInUse(_))
+        ));
+        assert!(matches!(
+            allocate("10.8.0.1/24", &used, Some("10.9.0.2")),
+            Err(IpamError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            allocate("10.8.0.1/24", &used, Some("10.8.0.255")),
+            Err(IpamError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            allocate("10.8.0.1/24", &used, Some("10.8.0.1")),
+            Err(IpamError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            allocate("10.8.0.1/24", &used, Some("nope")),
+            Err(IpamError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn exhausted() {
+        // a /30 has two hosts, one of them is the interface
+        let used = vec!["10.8.0.2/32".to_string()];
+        assert!(matches!(
+            allocate("10.8.0.1/30", &used, None),
+            Err(IpamError::Exhausted(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_network() {
+        assert!(matches!(
+            allocate("address", &[], None),
+            Err(IpamError::InvalidNetwork(_))
+        ));
+    }
+
+    #[test]
+    fn taken_addresses() {
+        let used = vec!["10.8.0.2/32, fd00:8::2/128".to_string()];
+        assert!(check_free("10.8.0.3/32, fd00:8::3/128", &used).is_ok());
+        assert!(matches!(
+            check_free("10.8.0.3/32, fd00:8::2/128", &used),
+            Err(IpamError::AddressInUse(addr)) if addr.to_string() == "fd00:8::2"
+        ));
+    }
+}
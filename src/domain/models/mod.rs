@@ -1,2 +1,4 @@
+pub(crate) mod interface;
+pub(crate) mod ipam;
 pub(crate) mod peer;
 pub mod user;
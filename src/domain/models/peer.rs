@@ -4,7 +4,8 @@ use crate::infra::errors::InfraError;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
@@ -16,17 +17,166 @@ pub struct PeerModel {
     pub persistent_keepalive: usize,
     pub allowed_ips: String,
     pub preshared_key: Option<String>,
-    pub private_key: String,
+    pub private_key: Option<String>,
     pub public_key: String,
     pub if_pubkey: String,
     pub address: String,
-    pub transfer_rx: usize,
-    pub transfer_tx: usize,
+    // bytes since the peer was created, across interface restarts
+    pub transfer_rx: u64,
+    pub transfer_tx: u64,
     pub last_handshake_at: Option<NaiveDateTime>,
     pub endpoint_addr: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub interface_id:i32,
+    // when the counters were last read from the interface
+    pub usage_updated_at: Option<NaiveDateTime>,
+    // disabled by the expiry scheduler once passed
+    pub expires_at: Option<NaiveDateTime>,
+    // None is unlimited
+    pub quota: Option<PeerQuota>,
+    // the user it belongs to, None for peers only admins manage
+    pub owner_id: Option<Uuid>,
+}
+
+impl PeerModel {
+    pub fn presence(&self, thresholds: PresenceThresholds, now: NaiveDateTime) -> PeerPresence {
+        thresholds.presence(self.last_handshake_at.map(|at| now - at))
+    }
+
+    // rx and tx together, what a quota counts
+    pub fn transfer_total(&self) -> i64 {
+        (self.transfer_rx + self.transfer_tx) as i64
+    }
+
+    pub fn is_over_quota(&self) -> bool {
+        self.quota
+            .as_ref()
+            .is_some_and(|quota| quota.used(self.transfer_total()) >= quota.bytes)
+    }
+}
+
+// A peer for the unit tests, set up as a new one on interface 1. Tests spell out only the
+// fields they are about, with struct update syntax.
+#[cfg(test)]
+impl PeerModel {
+    pub fn for_tests() -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: "laptop".to_string(),
+            enabled: true,
+            persistent_keepalive: 25,
+            allowed_ips: "0.0.0.0/0, ::/0".to_string(),
+            preshared_key: None,
+            private_key: Some("peer-private=".to_string()),
+            public_key: "peer-public=".to_string(),
+            if_pubkey: "server-public=".to_string(),
+            address: "10.8.0.2/32".to_string(),
+            transfer_rx: 0,
+            transfer_tx: 0,
+            last_handshake_at: None,
+            endpoint_addr: None,
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+            interface_id: 1,
+            usage_updated_at: None,
+            expires_at: None,
+            quota: None,
+            owner_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPeriod {
+    // starts over on the billing day of every month
+    Monthly,
+    // over the lifetime of the peer
+    Total,
+}
+
+impl QuotaPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaPeriod::Monthly => "monthly",
+            QuotaPeriod::Total => "total",
+        }
+    }
+
+    pub fn parse(period: &str) -> Option<Self> {
+        match period {
+            "monthly" => Some(QuotaPeriod::Monthly),
+            "total" => Some(QuotaPeriod::Total),
+            _ => None,
+        }
+    }
+}
+
+// The bytes a peer may move per period, rx and tx together
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct QuotaLimit {
+    pub bytes: i64,
+    pub period: QuotaPeriod,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerQuota {
+    pub bytes: i64,
+    pub period: QuotaPeriod,
+    // transfer_rx + transfer_tx when the current period began
+    pub used_from: i64,
+    pub period_start: NaiveDateTime,
+    // the highest warning threshold recorded in the current period
+    pub warned_percent: i32,
+    // set while the peer is disabled for going over
+    pub exceeded_at: Option<NaiveDateTime>,
+}
+
+impl PeerQuota {
+    pub fn used(&self, transfer_total: i64) -> i64 {
+        (transfer_total - self.used_from).max(0)
+    }
+
+    pub fn remaining(&self, transfer_total: i64) -> i64 {
+        (self.bytes - self.used(transfer_total)).max(0)
+    }
+}
+
+// Whether a peer is connected, judged by the age of its latest handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PeerPresence {
+    Online,
+    // connected a while ago, a peer without traffic does not shake hands
+    Idle,
+    Offline,
+    NeverConnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PresenceThresholds {
+    // handshakes up to this old are online, up to `idle` old idle
+    pub online: TimeDelta,
+    pub idle: TimeDelta,
+}
+
+impl Default for PresenceThresholds {
+    // REKEY_AFTER_TIME plus the 60 seconds a rekey may take, and a quarter of an hour
+    fn default() -> Self {
+        Self { online: TimeDelta::seconds(180), idle: TimeDelta::seconds(900) }
+    }
+}
+
+impl PresenceThresholds {
+    pub fn presence(&self, handshake_age: Option<TimeDelta>) -> PeerPresence {
+        match handshake_age {
+            None => PeerPresence::NeverConnected,
+            Some(age) if age <= self.online => PeerPresence::Online,
+            Some(age) if age <= self.idle => PeerPresence::Idle,
+            Some(_) => PeerPresence::Offline,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,13 +184,42 @@ pub enum PeerError {
     InternalServerError,
     NotFound(Uuid),
     InfraError(InfraError),
+    InterfaceNotFound(i32),
+    PublicKeyExists,
+    // imported peers only have their public key on the server
+    PrivateKeyUnknown(Uuid),
+    // enabling it needs a later expires_at
+    Expired(Uuid),
+    // enabling it needs a larger quota or the next period
+    QuotaExceeded(Uuid),
+    // the owner has as many peers as a user may have
+    LimitReached(i64),
+    OwnerNotFound(Uuid),
+    Invalid(String),
 }
 impl fmt::Display for PeerError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             PeerError::InternalServerError => write!(f, "InternalServerError"),
-            PeerError::NotFound(uuid) => write!(f, "user with userid : {uuid} not found"),
+            PeerError::NotFound(uuid) => write!(f, "peer with id : {uuid} not found"),
             PeerError::InfraError(_err) => write!(f, "infra error"),
+            PeerError::InterfaceNotFound(id) => write!(f, "interface with id : {id} not found"),
+            PeerError::PublicKeyExists => write!(f, "a peer with this public key already exists"),
+            PeerError::PrivateKeyUnknown(uuid) => write!(
+                f,
+                "peer with id : {uuid} was imported without its private key, its client config can not be generated"
+            ),
+            PeerError::Expired(uuid) => write!(
+                f,
+                "peer with id : {uuid} has expired, move its expires_at to enable it again"
+            ),
+            PeerError::QuotaExceeded(uuid) => write!(
+                f,
+                "peer with id : {uuid} used up its quota, raise it or wait for the next period to enable it again"
+            ),
+            PeerError::LimitReached(max) => write!(f, "a user may have at most {max} peers"),
+            PeerError::OwnerNotFound(uuid) => write!(f, "user with id : {uuid} not found"),
+            PeerError::Invalid(reason) => write!(f, "{reason}"),
         }
     }
 }
@@ -55,6 +234,18 @@ impl IntoResponse for PeerError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Internal server error: {}", db_error),
             ),
+            Self::InterfaceNotFound(id) => (
+                StatusCode::BAD_REQUEST,
+                format!("Interface with id {} has not been found", id),
+            ),
+            Self::PublicKeyExists => (
+                StatusCode::CONFLICT,
+                "a peer with this public key already exists".to_string(),
+            ),
+            Self::PrivateKeyUnknown(_) | Self::Expired(_) | Self::QuotaExceeded(_) | Self::LimitReached(_) => {
+                (StatusCode::CONFLICT, self.to_string())
+            }
+            Self::OwnerNotFound(_) | Self::Invalid(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 String::from("Internal server error"),
@@ -70,22 +261,28 @@ impl IntoResponse for PeerError {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Hash)]
-pub struct PeerCreate {
-    name: String,
-    // interface_id:u32,
-    persistent_keepalive: Option<u16>,
-    allowed_ips: String,
+// Seconds between keepalive packets in the client config, 0 turns them off
+pub fn validate_keepalive(keepalive: Option<i32>) -> Result<(), PeerError> {
+    match keepalive {
+        Some(keepalive) if !(0..=65535).contains(&keepalive) => Err(PeerError::Invalid(
+            "persistent_keepalive must be between 0 and 65535".to_string(),
+        )),
+        _ => Ok(()),
+    }
 }
 
-impl PeerCreate {
-    pub fn new(name: String, allowed_ips: String, persistent_keepalive: Option<u16>) -> Self {
-        PeerCreate {
-            name,
-            allowed_ips,
-            persistent_keepalive,
-        }
-    }
+// The routes the client sends through the tunnel, comma separated CIDRs, e.g. "0.0.0.0/0, ::/0".
+// Returned as written to the client config, anything but CIDRs would end up there as is.
+pub fn normalize_allowed_ips(allowed_ips: &str) -> Result<String, PeerError> {
+    let networks = allowed_ips
+        .split(',')
+        .map(|network| {
+            network.trim().parse::<IpNet>().map_err(|_| {
+                PeerError::Invalid(format!("allowed_ips: {:?} is not a valid CIDR", network.trim()))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(networks.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))
 }
 
 // #[derive(Debug, Deserialize, Serialize, Clone, Hash)]
@@ -145,3 +342,27 @@ impl PeerCreate {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keepalive() {
+        assert!(validate_keepalive(None).is_ok());
+        assert!(validate_keepalive(Some(0)).is_ok());
+        assert!(validate_keepalive(Some(65535)).is_ok());
+        assert!(validate_keepalive(Some(-1)).is_err());
+        assert!(validate_keepalive(Some(65536)).is_err());
+    }
+
+    #[test]
+    fn allowed_ips() {
+        assert_eq!(normalize_allowed_ips("0.0.0.0/0,::/0").unwrap(), "0.0.0.0/0, ::/0");
+        assert_eq!(normalize_allowed_ips(" 10.8.0.0/24 ").unwrap(), "10.8.0.0/24");
+        assert!(normalize_allowed_ips("").is_err());
+        assert!(normalize_allowed_ips("10.8.0.1").is_err());
+        assert!(normalize_allowed_ips("10.8.0.0/24,").is_err());
+        assert!(normalize_allowed_ips("10.8.0.0/24\nDNS = 10.0.0.1").is_err());
+    }
+}
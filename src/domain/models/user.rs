@@ -3,6 +3,7 @@ use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
 use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::fmt;
 use std::fmt::Formatter;
@@ -13,10 +14,76 @@ pub struct UserModel {
     pub id: Uuid,
     pub username: String,
     pub hashed_password: String,
+    pub role: Role,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+// Each role may do what the ones before it may: viewers read peers and their stats,
+// operators manage peers, admins manage interfaces and users as well
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "operator" => Some(Role::Operator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+// What an API token may do, within the role of its user. peers:write takes in peers:read,
+// and is needed for the client configurations and dumps, which hold keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "peers:read")]
+    PeersRead,
+    #[serde(rename = "peers:write")]
+    PeersWrite,
+    #[serde(rename = "interfaces:admin")]
+    InterfacesAdmin,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::PeersRead => "peers:read",
+            ApiScope::PeersWrite => "peers:write",
+            ApiScope::InterfacesAdmin => "interfaces:admin",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "peers:read" => Some(ApiScope::PeersRead),
+            "peers:write" => Some(ApiScope::PeersWrite),
+            "interfaces:admin" => Some(ApiScope::InterfacesAdmin),
+            _ => None,
+        }
+    }
+
+    // Whether a token with this scope may go where `required` is
+    pub fn grants(&self, required: ApiScope) -> bool {
+        *self == required || (*self == ApiScope::PeersWrite && required == ApiScope::PeersRead)
+    }
+}
+
 #[derive(Debug)]
 pub enum UserError {
     InternalServerError,
@@ -25,6 +92,8 @@ pub enum UserError {
     UsernameNotFound(String),
     WrongCredentials,
     UsernameExists,
+    // a role change that would leave no admin
+    LastAdmin,
 }
 
 impl fmt::Display for UserError {
@@ -36,6 +105,7 @@ impl fmt::Display for UserError {
             UserError::UsernameNotFound(_err) => write!(f, "username doesn't exist"),
             UserError::WrongCredentials => write!(f, "Username or password is wrong"),
             UserError::UsernameExists => write!(f, "username is not available"),
+            UserError::LastAdmin => write!(f, "the last admin can not give up the role"),
         }
     }
 }
@@ -63,6 +133,7 @@ impl IntoResponse for UserError {
                 StatusCode::CONFLICT,
                 "this username is not available".to_string(),
             ),
+            Self::LastAdmin => (StatusCode::CONFLICT, self.to_string()),
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 String::from("Internal server error"),
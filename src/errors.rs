@@ -2,7 +2,7 @@ use std::fmt;
 
 use axum::{
     http::StatusCode,
-    http::{header, HeaderMap, HeaderValue},
+    http::{header, HeaderValue},
     response::{IntoResponse, Response}, Json,
 };
 use serde::{Deserialize, Serialize};
@@ -10,6 +10,8 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::domain::ctx::Ctx;
+use crate::domain::models::interface::InterfaceError;
+use crate::domain::models::ipam::IpamError;
 use crate::domain::models::peer::PeerError;
 use crate::domain::models::user::UserError;
 use crate::infra::errors::InfraError;
@@ -24,9 +26,18 @@ pub struct ApiError {
 pub enum BaseError {
     Generic { description: String },
     LoginFail,
-    AuthFailNoJwtCookie,
+    // neither an Authorization header nor the cookie
+    AuthFailNoToken,
+    // an Authorization header claiming Bearer without a usable token
+    AuthFailHeaderInvalid,
     AuthFailJwtInvalid { source: String },
+    // unknown, expired, revoked or already used refresh token
+    AuthFailRefreshInvalid,
+    // unknown, expired or deleted API token
+    AuthFailApiTokenInvalid,
     AuthFailCtxNotInRequestExt,
+    // authenticated, but the role does not allow it
+    Forbidden { source: String },
     Serde { source: String },
     // SurrealDb { source: String },
     // SurrealDbNoResult { source: String, id: String },
@@ -44,9 +55,14 @@ pub enum BaseError {
     // EmptyHeaderValue { source: String },
     InternalServerError, // Represents an internal server error
     BodyParsingError(String),
-    PeerDumpError(&'static str),
+    PeerDumpError(String),
     UserError { source: String },
     PeerError { source: String },
+    Conflict { source: String },
+    NotFound { source: String },
+    InterfaceError { source: String },
+    // the WireGuard backend refused a change, the database side was rolled back
+    WgApply { source: String },
 }
 
 /// ApiError has to have the req_id to report to the client and implements IntoResponse.
@@ -73,7 +89,7 @@ impl IntoResponse for BaseError {
     // Define the conversion to an Axum response
     fn into_response(self) -> Response {
         // Define status and error message based on the error variant
-        let (status, err_msg) = match self {
+        let (status, err_msg) = match &self {
             Self::InternalServerError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal Server Error".into(),
@@ -82,27 +98,46 @@ impl IntoResponse for BaseError {
                 StatusCode::BAD_REQUEST,
                 format!("Bad request error: {}", message),
             ),
-            Self::UserError { source } => (StatusCode::UNAUTHORIZED, source),
-            Self::AuthFailNoJwtCookie=>(StatusCode::UNAUTHORIZED,"You are not logged in".to_string()),
+            Self::UserError { source } => (StatusCode::UNAUTHORIZED, source.clone()),
+            Self::AuthFailNoToken=>(StatusCode::UNAUTHORIZED,"You are not logged in".to_string()),
+            Self::AuthFailJwtInvalid { .. } | Self::AuthFailRefreshInvalid | Self::AuthFailApiTokenInvalid => {
+                (StatusCode::UNAUTHORIZED, self.to_string())
+            }
+            Self::AuthFailHeaderInvalid => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::Forbidden { .. } => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::Conflict { source } => (StatusCode::CONFLICT, source.clone()),
+            Self::NotFound { source } => (StatusCode::NOT_FOUND, source.clone()),
+            Self::WgApply { source } => (StatusCode::INTERNAL_SERVER_ERROR, source.clone()),
             _ => (StatusCode::BAD_REQUEST, "--> check here".to_string()),
         };
 
         // Create a JSON response containing the error message
-        (status, Json(json!({ "message": err_msg }))).into_response()
+        let mut response = (status, Json(json!({ "message": err_msg }))).into_response();
+        if let Some(challenge) = challenge(&self, status) {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        response
     }
 }
 
 const INTERNAL: &str = "Internal error";
+const AUTH_REALM: &str = "rustify";
 
 impl fmt::Display for BaseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Generic { description } => write!(f, "{description}"),
             Self::LoginFail => write!(f, "Login fail"),
-            Self::AuthFailNoJwtCookie => write!(f, "You are not logged in"),
+            Self::AuthFailNoToken => write!(f, "You are not logged in"),
+            Self::AuthFailHeaderInvalid => {
+                write!(f, "The Authorization header must be `Bearer <token>`")
+            }
             Self::AuthFailJwtInvalid { .. } => {
                 write!(f, "The provided JWT token is not valid")
             }
+            Self::AuthFailRefreshInvalid => write!(f, "The refresh token is not valid, log in again"),
+            Self::AuthFailApiTokenInvalid => write!(f, "The API token is not valid"),
+            Self::Forbidden { source } => write!(f, "Forbidden - {source}"),
             Self::Serde { source } => write!(f, "Serde error - {source}"),
             Self::AuthFailCtxNotInRequestExt => write!(f, "{INTERNAL}"),
             // Self::SurrealDb { .. } => write!(f, "{INTERNAL}"),
@@ -125,7 +160,11 @@ impl fmt::Display for BaseError {
             Self::BodyParsingError(msg) => write!(f, "{msg}"),
             Self::PeerDumpError(msg) => write!(f, "{msg}"),
             Self::UserError { source } => write!(f, "Auth Model - {source}"),
-            Self::PeerError { source } => write!(f, "Peer Model - {source}")
+            Self::PeerError { source } => write!(f, "Peer Model - {source}"),
+            Self::Conflict { source } => write!(f, "Conflict - {source}"),
+            Self::NotFound { source } => write!(f, "Not found - {source}"),
+            Self::InterfaceError { source } => write!(f, "Interface Model - {source}"),
+            Self::WgApply { source } => write!(f, "WireGuard apply failed - {source}"),
         }
     }
 }
@@ -139,12 +178,16 @@ impl IntoResponse for ApiError {
             // | BaseError::SurrealDbNoResult { .. }
             // | BaseError::UserAgentMissing { .. }
             | BaseError::BodyParsingError(..)
+            | BaseError::AuthFailHeaderInvalid
             // | BaseError::SurrealDbParse { .. },
-            | BaseError::PeerError { .. } => StatusCode::BAD_REQUEST,
+            | BaseError::PeerError { .. }
+            | BaseError::InterfaceError { .. } => StatusCode::BAD_REQUEST,
             BaseError::Generic { .. }
             | BaseError::LoginFail
-            | BaseError::AuthFailNoJwtCookie
+            | BaseError::AuthFailNoToken
             | BaseError::AuthFailJwtInvalid { .. }
+            | BaseError::AuthFailRefreshInvalid
+            | BaseError::AuthFailApiTokenInvalid
             | BaseError::AuthFailCtxNotInRequestExt
             // | BaseError::SurrealDb { .. }
             // | BaseError::AuthorizationHeaderEmpty { .. }
@@ -153,13 +196,17 @@ impl IntoResponse for ApiError {
             // | BaseError::AuthorizationHeaderFormatWrong { .. } ,
             | BaseError::UserError { .. } => StatusCode::UNAUTHORIZED,
             BaseError::Execution { .. }
-            | BaseError::InternalServerError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            | BaseError::PeerDumpError(..)
+            | BaseError::WgApply { .. }
+            | BaseError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             // BaseError::AuthorizationHeaderMissing { .. }
             // | BaseError::WrongToken { .. }
             // | BaseError::UnAuthorizedUser { .. }
-            BaseError::PeerDumpError(..)
-            | BaseError::UserNotFound { .. } => StatusCode::NOT_FOUND,
+            BaseError::UserNotFound => StatusCode::NOT_FOUND,
             BaseError::UserAgentMissing => StatusCode::BAD_REQUEST,
+            BaseError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            BaseError::Conflict { .. } => StatusCode::CONFLICT,
+            BaseError::NotFound { .. } => StatusCode::NOT_FOUND,
         };
         let body = Json(json!({
             "error": {
@@ -167,7 +214,10 @@ impl IntoResponse for ApiError {
                 "req_id": self.req_id.to_string()
             }
         }));
-        let mut response = (status_code, headers(), body).into_response();
+        let mut response = (status_code, body).into_response();
+        if let Some(challenge) = challenge(&self.error, status_code) {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, challenge);
+        }
         // Insert the real Error into the response - for the logger
         response.extensions_mut().insert(self.error);
         response
@@ -185,8 +235,13 @@ impl From<serde_json::Error> for BaseError {
 
 impl From<InfraError> for BaseError {
     fn from(value: InfraError) -> Self {
-        Self::PeerError {
-            source: value.to_string(),
+        match value {
+            InfraError::UniqueViolation => Self::Conflict {
+                source: value.to_string(),
+            },
+            _ => Self::PeerError {
+                source: value.to_string(),
+            },
         }
     }
 }
@@ -201,8 +256,17 @@ impl From<jsonwebtoken::errors::Error> for BaseError {
 
 impl From<UserError> for BaseError {
     fn from(value: UserError) -> Self {
-        Self::UserError {
-            source: value.to_string(),
+        match value {
+            UserError::NotFound(..) => Self::NotFound {
+                source: value.to_string(),
+            },
+            UserError::UsernameExists | UserError::LastAdmin => Self::Conflict {
+                source: value.to_string(),
+            },
+            UserError::InfraError(err) => err.into(),
+            _ => Self::UserError {
+                source: value.to_string(),
+            },
         }
     }
 }
@@ -220,11 +284,56 @@ impl From<PeerError> for BaseError {
             //     source: err.to_string(),
             // }
             // // }
-            Self::PeerError {
+        match value {
+            PeerError::NotFound(..) => Self::NotFound {
                 source: value.to_string(),
-            }
+            },
+            PeerError::PublicKeyExists
+            | PeerError::PrivateKeyUnknown(..)
+            | PeerError::Expired(..)
+            | PeerError::QuotaExceeded(..)
+            | PeerError::LimitReached(..) => Self::Conflict {
+                source: value.to_string(),
+            },
+            _ => Self::PeerError {
+                source: value.to_string(),
+            },
+        }
+    }
+}
+impl From<InterfaceError> for BaseError {
+    fn from(value: InterfaceError) -> Self {
+        match value {
+            InterfaceError::NotFound(..) => Self::NotFound {
+                source: value.to_string(),
+            },
+            InterfaceError::NameExists(..)
+            | InterfaceError::HasPeers(..)
+            | InterfaceError::PeersPinned(..) => Self::Conflict {
+                source: value.to_string(),
+            },
+            InterfaceError::InfraError(err) => err.into(),
+            _ => Self::InterfaceError {
+                source: value.to_string(),
+            },
+        }
+    }
+}
+
+impl From<IpamError> for BaseError {
+    fn from(value: IpamError) -> Self {
+        match value {
+            IpamError::AddressInUse(..) | IpamError::Exhausted(..) => BaseError::Conflict {
+                source: value.to_string(),
+            },
+            IpamError::Infra(err) => err.into(),
+            _ => BaseError::PeerError {
+                source: value.to_string(),
+            },
         }
     }
+}
+
 // Define an enumeration for custom application errors
 // #[derive(Debug)]
 // pub enum AppError2 {  // Represents an error related to request body parsing
@@ -235,34 +344,32 @@ impl From<PeerError> for BaseError {
         BaseError::InternalServerError
     }
 
-    #[cfg(test)]
-    mod tests {
-        #[test]
-        fn display_description() {
-            let err = super::BaseError::Generic {
-                description: "super description".to_owned(),
-            };
-            assert_eq!(format!("{err}"), "super description");
-            assert_eq!(err.to_string(), "super description");
-        }
-    }
-
 // #[derive(Debug)]
 // pub enum UserError {
 // }
 
-    fn headers() -> HeaderMap {
-        HeaderMap::from_iter(vec![
-            // (header::ACCEPT_RANGES,HeaderValue::from_static("bytes")),
-            //  (header::CONTENT_LENGTH,HeaderValue::from_str(format!("is").as_str()).unwrap()),
-            // (header::CONTENT_RANGE,HeaderValue::from_str("asdf").unwrap()),
-            //(header::TRANSFER_ENCODING,"trailers".to_string()),
-            (
-                header::WWW_AUTHENTICATE,
-                HeaderValue::from_str("Bearer").unwrap(),
-            ),
-        ])
-    }
+// The RFC 6750 challenge telling a client how to authenticate, on the responses that refuse
+// its credentials. A browser does not prompt for Bearer, the cookie keeps working there.
+fn challenge(error: &BaseError, status: StatusCode) -> Option<HeaderValue> {
+    let params = match error {
+        BaseError::AuthFailHeaderInvalid => {
+            r#", error="invalid_request", error_description="malformed Authorization header""#
+        }
+        BaseError::AuthFailJwtInvalid { .. } => {
+            r#", error="invalid_token", error_description="the token is invalid or expired""#
+        }
+        BaseError::AuthFailRefreshInvalid => {
+            r#", error="invalid_token", error_description="the refresh token is invalid""#
+        }
+        BaseError::AuthFailApiTokenInvalid => {
+            r#", error="invalid_token", error_description="the API token is invalid or expired""#
+        }
+        BaseError::Forbidden { .. } => r#", error="insufficient_scope""#,
+        _ if status == StatusCode::UNAUTHORIZED => "",
+        _ => return None,
+    };
+    HeaderValue::from_str(&format!(r#"Bearer realm="{AUTH_REALM}"{params}"#)).ok()
+}
 
 // impl IntoResponse for UserError {
 //     fn into_response(self) -> Response {
@@ -302,3 +409,43 @@ impl From<PeerError> for BaseError {
 //         )
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn display_description() {
+        let err = super::BaseError::Generic {
+            description: "super description".to_owned(),
+        };
+        assert_eq!(format!("{err}"), "super description");
+        assert_eq!(err.to_string(), "super description");
+    }
+
+    #[test]
+    fn challenges() {
+        use axum::response::IntoResponse;
+
+        use super::{header, ApiError, BaseError};
+
+        let challenge = |error: BaseError| {
+            let response = ApiError { error, req_id: uuid::Uuid::nil() }.into_response();
+            let challenge = response.headers().get(header::WWW_AUTHENTICATE);
+            (response.status().as_u16(), challenge.map(|value| value.to_str().unwrap().to_string()))
+        };
+        assert_eq!(challenge(BaseError::AuthFailNoToken), (401, Some(r#"Bearer realm="rustify""#.to_string())));
+        let (status, invalid) = challenge(BaseError::AuthFailJwtInvalid { source: "ExpiredSignature".to_string() });
+        assert_eq!(status, 401);
+        assert!(invalid.unwrap().contains(r#"error="invalid_token""#));
+        let (status, api_token) = challenge(BaseError::AuthFailApiTokenInvalid);
+        assert_eq!(status, 401);
+        assert!(api_token.unwrap().contains("the API token is invalid"));
+        let (status, malformed) = challenge(BaseError::AuthFailHeaderInvalid);
+        assert_eq!(status, 400);
+        assert!(malformed.unwrap().contains(r#"error="invalid_request""#));
+        let (status, forbidden) = challenge(BaseError::Forbidden { source: "admin".to_string() });
+        assert_eq!(status, 403);
+        assert!(forbidden.unwrap().contains(r#"error="insufficient_scope""#));
+        // nothing to authenticate for
+        assert_eq!(challenge(BaseError::NotFound { source: "peer".to_string() }), (404, None));
+    }
+}
@@ -0,0 +1,65 @@
+use axum::extract::State;
+use axum::Json;
+use chrono::Utc;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::user::{Role, UserError};
+use crate::errors::{ApiError, ApiResult, BaseError};
+use crate::handlers::api_tokens::{ApiTokenResponse, CreateApiTokenRequest, CreatedApiTokenResponse};
+use crate::infra::errors::InfraError;
+use crate::infra::user_repository;
+use crate::service::api_token::{self, MAX_NAME_LEN};
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::JsonExtractor;
+
+pub async fn create_token(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    JsonExtractor(mut payload): JsonExtractor<CreateApiTokenRequest>,
+) -> ApiResult<Json<CreatedApiTokenResponse>> {
+    let claims = ctx.claims()?;
+    let invalid = |message: String| ApiError::from(&ctx)(BaseError::BodyParsingError(message));
+    let name = payload.name.trim().to_string();
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(invalid(format!("name must hold 1 to {MAX_NAME_LEN} characters")));
+    }
+    payload.scopes.sort_by_key(|scope| scope.as_str());
+    payload.scopes.dedup();
+    if payload.scopes.is_empty() {
+        return Err(invalid("scopes must hold at least one scope".to_string()));
+    }
+    let expires_at = payload.expires_at.flatten();
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(invalid("expires_at must be in the future".to_string()));
+    }
+
+    // a token for another user, e.g. a service account, is for admins to hand out
+    let user_id = match payload.user_id {
+        Some(user_id) if user_id != claims.sub => {
+            ctx.require(Role::Admin)?;
+            user_repository::read(&state.pool, user_id)
+                .await
+                .map_err(|err| match err {
+                    InfraError::NotFound => UserError::NotFound(user_id),
+                    err => UserError::InfraError(err),
+                })
+                .map_err(ApiError::from(&ctx))?;
+            user_id
+        }
+        _ => claims.sub,
+    };
+
+    let (token, stored) = api_token::create(
+        &state.pool,
+        user_id,
+        name,
+        payload.scopes,
+        expires_at.map(|expires_at| expires_at.naive_utc()),
+    )
+    .await
+    .map_err(ApiError::from(&ctx))?;
+    Ok(Json(CreatedApiTokenResponse {
+        token,
+        api_token: ApiTokenResponse::from_db(stored),
+    }))
+}
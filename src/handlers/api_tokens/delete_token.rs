@@ -0,0 +1,32 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use uuid::Uuid;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::user::Role;
+use crate::errors::{internal_error, ApiError, ApiResult, BaseError};
+use crate::infra::api_token_repository;
+use crate::infra::errors::InfraError;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+// The token is refused from the next request on
+pub async fn delete_token(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(token_id): PathExtractor<Uuid>,
+) -> ApiResult<StatusCode> {
+    let claims = ctx.claims()?;
+    // the tokens of others are not found, unless the caller is an admin
+    let user_id = (claims.role < Role::Admin).then_some(claims.sub);
+    api_token_repository::delete(&state.pool, token_id, user_id)
+        .await
+        .map_err(|err| match err {
+            InfraError::NotFound => BaseError::NotFound {
+                source: format!("api token with id : {token_id} not found"),
+            },
+            err => internal_error(err),
+        })
+        .map_err(ApiError::from(&ctx))?;
+    Ok(StatusCode::NO_CONTENT)
+}
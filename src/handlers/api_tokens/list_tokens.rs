@@ -0,0 +1,28 @@
+use axum::extract::{Query, State};
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::user::Role;
+use crate::errors::{internal_error, ApiError, ApiResult};
+use crate::handlers::api_tokens::{ApiTokenResponse, ApiTokensQuery, ListApiTokensResponse};
+use crate::infra::api_token_repository;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+pub async fn list_tokens(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    Query(query): Query<ApiTokensQuery>,
+) -> ApiResult<Json<ListApiTokensResponse>> {
+    let claims = ctx.claims()?;
+    let user_id = match claims.role {
+        Role::Admin => query.user_id,
+        _ => Some(claims.sub),
+    };
+    let tokens = api_token_repository::get_all(&state.pool, user_id)
+        .await
+        .map_err(internal_error)
+        .map_err(ApiError::from(&ctx))?;
+    Ok(Json(ListApiTokensResponse {
+        tokens: tokens.into_iter().map(ApiTokenResponse::from_db).collect(),
+    }))
+}
@@ -0,0 +1,82 @@
+use axum::routing::{delete, get};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::domain::models::user::ApiScope;
+use crate::infra::api_token_repository::ApiToken;
+use crate::service::api_token;
+use crate::utils::deserialize_some;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+mod create_token;
+mod delete_token;
+mod list_tokens;
+
+// API tokens of the caller, admins manage the tokens of every user. Only login sessions
+// get here, a token can not mint or delete tokens.
+pub fn api_tokens_router(state: AppState) -> Router {
+    Router::new()
+        // the token is in the response once, only its hash is kept
+        .route("/", get(list_tokens::list_tokens).post(create_token::create_token))
+        .route("/:id", delete(delete_token::delete_token))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    name: String,
+    scopes: Vec<ApiScope>,
+    // never expires when missing or null
+    #[serde(default, deserialize_with = "deserialize_some")]
+    expires_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+    // admins only, for a service account; the caller when missing
+    user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ApiTokensQuery {
+    // for admins, everybody else only lists their own tokens
+    user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiTokenResponse {
+    id: Uuid,
+    user_id: Uuid,
+    name: String,
+    // the start of the token
+    prefix: String,
+    scopes: Vec<ApiScope>,
+    expires_at: Option<chrono::NaiveDateTime>,
+    last_used_at: Option<chrono::NaiveDateTime>,
+    created_at: chrono::NaiveDateTime,
+}
+
+impl ApiTokenResponse {
+    pub fn from_db(token: ApiToken) -> Self {
+        Self {
+            scopes: api_token::scopes(&token.scopes),
+            id: token.id,
+            user_id: token.user_id,
+            name: token.name,
+            prefix: token.prefix,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiTokenResponse {
+    // shown this once
+    token: String,
+    #[serde(flatten)]
+    api_token: ApiTokenResponse,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListApiTokensResponse {
+    tokens: Vec<ApiTokenResponse>,
+}
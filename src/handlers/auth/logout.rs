@@ -0,0 +1,40 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use tower_cookies::Cookies;
+use tracing::debug;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::ApiError;
+use crate::handlers::auth::remove_session_cookies;
+use crate::service::auth;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Revokes the session the access token belongs to
+pub async fn logout(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    ctx: Ctx,
+) -> Result<StatusCode, ApiError> {
+    debug!("->> {:<12} - logout", "HANDLER");
+    let claims = ctx.claims()?;
+    auth::logout(&state, &claims)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    remove_session_cookies(&cookies).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Revokes every session of the user, this one included
+pub async fn logout_all(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    ctx: Ctx,
+) -> Result<StatusCode, ApiError> {
+    debug!("->> {:<12} - logout_all", "HANDLER");
+    let claims = ctx.claims()?;
+    auth::logout_all(&state, claims.sub)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    remove_session_cookies(&cookies).await;
+    Ok(StatusCode::NO_CONTENT)
+}
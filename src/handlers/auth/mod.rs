@@ -1,35 +1,70 @@
-use crate::domain::models::user::UserModel;
+use crate::core::get_config;
+use crate::domain::models::user::{Role, UserModel};
 use crate::handlers::auth::register::register;
-use crate::utils::middlewares::mw_ctx::AppState;
+use crate::service::auth::TokenPair;
+use crate::utils::middlewares::mw_ctx::{mw_require_session, AppState};
 use axum::routing::post;
-use axum::Router;
+use axum::{middleware, Router};
 use login::auth_login;
+use logout::{logout, logout_all};
+use refresh::refresh_token;
 use serde::{Deserialize, Serialize};
+use tower_cookies::cookie::time::{Duration, OffsetDateTime};
+use tower_cookies::cookie::{Expiration, SameSite};
+use tower_cookies::{Cookie, Cookies};
 use uuid::Uuid;
 
 mod login;
+mod logout;
+mod refresh;
 mod register;
 
+// The refresh token cookie is only sent to the auth routes
+pub const REFRESH_COOKIE: &str = "refresh_token";
+const REFRESH_COOKIE_PATH: &str = "/api/v1/auth";
+
 pub fn auth_router(state: AppState) -> Router {
     Router::new()
+        .route("/logout", post(logout))
+        .route("/logout-all", post(logout_all))
+        // API tokens are deleted through /tokens instead
+        .route_layer(middleware::from_fn(mw_require_session))
         .route("/login/access-token", post(auth_login))
+        .route("/refresh", post(refresh_token))
         .route("/register", post(register))
         .with_state(state)
 }
 
-#[derive(Debug, Serialize)]
-pub struct SingInSuccess {
-    token_type: String,
-    access_token: String,
+pub async fn set_session_cookies(cookies: &Cookies, tokens: &TokenPair) {
+    let config = get_config().await;
+    let expires = |duration: Duration| Expiration::from(OffsetDateTime::now_utc().checked_add(duration));
+    cookies.add(
+        Cookie::build(Cookie::new(config.jwt_key(), tokens.access_token.clone()))
+            // if not set, the path defaults to the path from which it was called - prohibiting gql on root if login is on /api
+            .path("/")
+            // .domain("127.0.0.1")
+            .same_site(SameSite::Strict)
+            .expires(expires(Duration::minutes(config.jwt_exp_minutes())))
+            .http_only(false)
+            .secure(false)
+            .build(),
+    );
+    // scripts never need the refresh token, the browser sends it to /auth/refresh
+    cookies.add(
+        Cookie::build(Cookie::new(REFRESH_COOKIE, tokens.refresh_token.clone()))
+            .path(REFRESH_COOKIE_PATH)
+            .same_site(SameSite::Strict)
+            .expires(expires(Duration::days(config.refresh_token_days())))
+            .http_only(true)
+            .secure(false)
+            .build(),
+    );
 }
 
-impl SingInSuccess {
-    pub fn create_bearer(token_str: String) -> Self {
-        Self {
-            access_token: token_str,
-            token_type: "Bearer".to_string(),
-        }
-    }
+pub async fn remove_session_cookies(cookies: &Cookies) {
+    let config = get_config().await;
+    cookies.remove(Cookie::build(config.jwt_key().to_string()).path("/").build());
+    cookies.remove(Cookie::build(REFRESH_COOKIE).path(REFRESH_COOKIE_PATH).build());
 }
 
 // #[derive(Debug, Deserialize)]
@@ -55,17 +90,25 @@ impl SingInSuccess {
 //     pub state: String,
 // }
 
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct RegisterUserRequest {
     // pub name: String,
     pub username: String,
     pub password: String,
+    // viewer when not given. The first user is always an admin
+    pub role: Option<Role>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserResponse {
     id: Uuid,
     username: String,
+    role: Role,
 }
 
 impl UserResponse {
@@ -73,6 +116,7 @@ impl UserResponse {
         Self {
             id: user.id,
             username: user.username,
+            role: user.role,
         }
     }
 }
@@ -0,0 +1,31 @@
+use axum::{extract::State, Json};
+use tower_cookies::Cookies;
+use tracing::debug;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, BaseError};
+use crate::handlers::auth::login::SingInSuccess;
+use crate::handlers::auth::{set_session_cookies, RefreshRequest, REFRESH_COOKIE};
+use crate::service::auth;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Exchanges a refresh token, from the body or else the cookie, for a new pair. The token
+// sent is used up, the response carries its successor.
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    cookies: Cookies,
+    ctx: Ctx,
+    payload: Option<Json<RefreshRequest>>,
+) -> Result<Json<SingInSuccess>, ApiError> {
+    debug!("->> {:<12} - refresh_token", "HANDLER");
+    let refresh_token = payload
+        .map(|Json(payload)| payload.refresh_token)
+        .or_else(|| cookies.get(REFRESH_COOKIE).map(|cookie| cookie.value().to_string()))
+        .ok_or(BaseError::AuthFailRefreshInvalid)
+        .map_err(ApiError::from(&ctx))?;
+    let tokens = auth::refresh(&state, &refresh_token)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    set_session_cookies(&cookies, &tokens).await;
+    Ok(Json(SingInSuccess::create_bearer(tokens)))
+}
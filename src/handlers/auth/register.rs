@@ -2,7 +2,9 @@ use axum::extract::State;
 use axum::Json;
 
 use crate::core::hash_password;
-use crate::domain::models::user::UserError;
+use crate::domain::ctx::Ctx;
+use crate::domain::models::user::{Role, UserError};
+use crate::errors::{ApiError, ApiResult};
 use crate::handlers::auth::{RegisterUserRequest, UserResponse};
 use crate::infra::user_repository;
 use crate::infra::user_repository::read_by_username;
@@ -12,22 +14,72 @@ use tracing::debug;
 
 pub async fn register(
     State(AppState { pool, .. }): State<AppState>,
+    ctx: Ctx,
     JsonExtractor(payload): JsonExtractor<RegisterUserRequest>,
-) -> Result<Json<UserResponse>, UserError> {
-    debug!("{:?}", payload.clone());
+) -> ApiResult<Json<UserResponse>> {
+    debug!("->> {:<12} - register {}", "HANDLER", payload.username);
+    // outside the session layer for the first user, API tokens can not add users, not even an admin's
+    if ctx.is_api_token() {
+        ctx.require_session()?;
+    }
     if let Ok(_value) = read_by_username(&pool, payload.username.clone()).await {
-        return Err(UserError::UsernameExists);
+        return Err(ApiError::from(&ctx)(UserError::UsernameExists));
     }
     let new_user = user_repository::NewUserDb {
         username: payload.username,
         hashed_password: hash_password(&payload.password)
             .await
             .unwrap()
-            .to_string()
+            .to_string(),
+        role: payload.role.unwrap_or(Role::Viewer).as_str().to_string(),
+    };
+    let db_user = match ctx.require(Role::Admin) {
+        Ok(()) => user_repository::create(&pool, new_user)
+            .await
+            .map_err(UserError::InfraError)
+            .map_err(ApiError::from(&ctx))?,
+        // the first user signs up on its own and becomes the admin, the others are added by an admin
+        Err(not_admin) => user_repository::create_first(&pool, new_user)
+            .await
+            .map_err(UserError::InfraError)
+            .map_err(ApiError::from(&ctx))?
+            .ok_or(not_admin)?,
     };
-    let db_user = user_repository::create(&pool, new_user)
-        .await
-        .map_err(UserError::InfraError)?;
     let user_response = UserResponse::from_db(db_user);
     Ok(Json(user_response))
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::core::Claims;
+    use crate::domain::models::user::ApiScope;
+    use crate::errors::BaseError;
+
+    #[tokio::test]
+    async fn api_tokens_can_not_register_users() {
+        let claims = Claims {
+            sub: Uuid::new_v4(),
+            name: "admin".to_string(),
+            exp: 0,
+            iat: 0,
+            jti: Uuid::new_v4(),
+            role: Role::Admin,
+        };
+        let req_id = Uuid::new_v4();
+        let ctx = Ctx::new(Ok(claims), req_id).with_scopes(vec![ApiScope::PeersRead]);
+        let payload = RegisterUserRequest {
+            username: "intruder".to_string(),
+            password: "password".to_string(),
+            role: Some(Role::Admin),
+        };
+        // refused before the database is asked
+        let err = register(State(AppState::for_tests()), ctx, JsonExtractor(payload))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.error, BaseError::Forbidden { .. }), "{err:?}");
+        assert_eq!(err.req_id, req_id);
+    }
+}
@@ -0,0 +1,53 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::interface::{
+    validate_address, validate_fwmark, validate_hook, validate_mtu, validate_name,
+    validate_port, InterfaceError,
+};
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::interfaces::{CreateInterfaceRequest, InterfaceResponse};
+use crate::infra::errors::InfraError;
+use crate::infra::wg_if_repository;
+use crate::service::keys::WgKeyPair;
+use crate::utils::{middlewares::mw_ctx::AppState, JsonExtractor};
+
+pub async fn create_interface(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    JsonExtractor(payload): JsonExtractor<CreateInterfaceRequest>,
+) -> ApiResult<Json<InterfaceResponse>> {
+    validate_name(&payload.name)
+        .and(validate_address(&payload.address))
+        .and(validate_port(payload.port))
+        .and(validate_mtu(payload.mtu))
+        .and(validate_fwmark(payload.fwmark))
+        .and(validate_hook(payload.post_up.as_deref()))
+        .and(validate_hook(payload.post_down.as_deref()))
+        .map_err(ApiError::from(&ctx))?;
+
+    // Every interface gets its own server keypair
+    let keypair = WgKeyPair::generate();
+    let name = payload.name.clone();
+    let new_interface = wg_if_repository::NewInterfaceForm {
+        name: payload.name,
+        pubkey: keypair.public_key,
+        privkey: keypair.private_key,
+        address: payload.address,
+        port: payload.port,
+        mtu: payload.mtu,
+        fwmark: payload.fwmark,
+        post_up: payload.post_up,
+        post_down: payload.post_down,
+    };
+    wg_if_repository::create(&state.pool, new_interface)
+        .await
+        .map(InterfaceResponse::from_db)
+        .map(Json)
+        .map_err(|db_error| match db_error {
+            InfraError::UniqueViolation => InterfaceError::NameExists(name),
+            _ => InterfaceError::InfraError(db_error),
+        })
+        .map_err(ApiError::from(&ctx))
+}
@@ -0,0 +1,27 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::interface::InterfaceError;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::interfaces::InterfaceResponse;
+use crate::infra::errors::InfraError;
+use crate::infra::wg_if_repository;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+pub async fn get_interface(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+) -> ApiResult<Json<InterfaceResponse>> {
+    wg_if_repository::read(&state.pool, if_id)
+        .await
+        .map(InterfaceResponse::from_db)
+        .map(Json)
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => InterfaceError::NotFound(if_id),
+            _ => InterfaceError::InfraError(db_error),
+        })
+        .map_err(ApiError::from(&ctx))
+}
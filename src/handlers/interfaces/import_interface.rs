@@ -0,0 +1,61 @@
+use axum::extract::multipart::MultipartError;
+use axum::extract::{Multipart, State};
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult, BaseError};
+use crate::handlers::interfaces::ImportLiveRequest;
+use crate::service::import::{self, ImportReport};
+use crate::utils::{middlewares::mw_ctx::AppState, JsonExtractor};
+
+// Adopts an interface from `wg show <name> dump`
+pub async fn import_live(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    JsonExtractor(payload): JsonExtractor<ImportLiveRequest>,
+) -> ApiResult<Json<ImportReport>> {
+    let (interface, peers) = import::from_live(&state, &payload.name, &payload.address)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    import::import(&state, interface, peers)
+        .await
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
+
+// Adopts an interface from an uploaded wg-quick config, multipart fields:
+// `file` the config, `name` the interface name, by default the file name without .conf
+pub async fn import_config(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<ImportReport>> {
+    let body_error =
+        |err: MultipartError| ApiError::from(&ctx)(BaseError::BodyParsingError(err.body_text()));
+    let mut name = None;
+    let mut config = None;
+    while let Some(field) = multipart.next_field().await.map_err(body_error)? {
+        match field.name() {
+            Some("name") => name = Some(field.text().await.map_err(body_error)?),
+            Some("file") => {
+                if name.is_none() {
+                    name = field
+                        .file_name()
+                        .map(|file_name| file_name.trim_end_matches(".conf").to_string());
+                }
+                config = Some(field.text().await.map_err(body_error)?);
+            }
+            _ => {}
+        }
+    }
+    let (Some(name), Some(config)) = (name, config) else {
+        return Err(ApiError::from(&ctx)(BaseError::BodyParsingError(
+            "expected a `file` field and an interface `name`".to_string(),
+        )));
+    };
+    let (interface, peers) = import::from_config(&name, &config).map_err(ApiError::from(&ctx))?;
+    import::import(&state, interface, peers)
+        .await
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
@@ -0,0 +1,19 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::interfaces::ListInterfacesResponse;
+use crate::infra::wg_if_repository;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+pub async fn list_interfaces(
+    ctx: Ctx,
+    State(state): State<AppState>,
+) -> ApiResult<Json<ListInterfacesResponse>> {
+    wg_if_repository::get_all(&state.pool)
+        .await
+        .map(ListInterfacesResponse::from_db)
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
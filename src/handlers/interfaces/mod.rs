@@ -0,0 +1,135 @@
+use axum::routing::{get, patch, post};
+use axum::{middleware, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::interface::{InterfaceError, InterfaceModel};
+use crate::domain::models::user::{ApiScope, Role};
+use crate::errors::{ApiError, ApiResult};
+use crate::infra::errors::InfraError;
+use crate::infra::wg_if_repository;
+use crate::utils::deserialize_some;
+use crate::utils::middlewares::mw_ctx::{mw_require_role, mw_require_scope, AppState};
+
+mod create_interface;
+mod get_interface;
+mod import_interface;
+mod list_interfaces;
+mod reconcile;
+mod remove_interface;
+mod server_config;
+mod update_interface;
+
+// Define a sub-router for handling wireguard interface routes
+pub fn interfaces_router(state: AppState) -> Router {
+    Router::new()
+        .route("/", post(create_interface::create_interface))
+        // adopt an interface configured before this service, with its peers
+        .route("/import/live", post(import_interface::import_live))
+        .route("/import/config", post(import_interface::import_config))
+        .route(
+            "/:id",
+            patch(update_interface::update_interface).delete(remove_interface::delete_interface),
+        )
+        // differences between the database and the live interface
+        .route("/:id/drift", get(reconcile::get_drift))
+        // converge the live interface to the database
+        .route("/:id/reconcile", post(reconcile::reconcile_interface))
+        // server side wg-quick config, rendered from the database
+        .route("/:id/config", get(server_config::download_config))
+        .route("/:id/config/apply", post(server_config::apply_config))
+        .route_layer(middleware::from_fn_with_state(Role::Admin, mw_require_role))
+        .route_layer(middleware::from_fn_with_state(ApiScope::InterfacesAdmin, mw_require_scope))
+        // everyone picks the interface of a peer, API tokens with any scope as well
+        .route("/", get(list_interfaces::list_interfaces))
+        .route("/:id", get(get_interface::get_interface))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInterfaceRequest {
+    name: String,
+    address: String,
+    port: i32,
+    mtu: Option<i32>,
+    fwmark: Option<i32>,
+    post_up: Option<String>,
+    post_down: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportLiveRequest {
+    name: String,
+    // the dump does not carry the tunnel address of the interface
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateInterfaceRequest {
+    name: Option<String>,
+    address: Option<String>,
+    port: Option<i32>,
+    // null removes the setting
+    #[serde(default, deserialize_with = "deserialize_some")]
+    mtu: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    fwmark: Option<Option<i32>>,
+    // null or an empty string removes the hook
+    #[serde(default, deserialize_with = "deserialize_some")]
+    post_up: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    post_down: Option<Option<String>>,
+}
+
+// The server private key never leaves the service
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InterfaceResponse {
+    id: i32,
+    name: String,
+    public_key: String,
+    address: String,
+    port: i32,
+    mtu: Option<i32>,
+    fwmark: Option<i32>,
+    post_up: Option<String>,
+    post_down: Option<String>,
+}
+
+impl InterfaceResponse {
+    pub fn from_db(interface: InterfaceModel) -> Self {
+        Self {
+            id: interface.id,
+            name: interface.name,
+            public_key: interface.pubkey,
+            address: interface.address,
+            port: interface.port,
+            mtu: interface.mtu,
+            fwmark: interface.fwmark,
+            post_up: interface.post_up,
+            post_down: interface.post_down,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListInterfacesResponse {
+    interfaces: Vec<InterfaceResponse>,
+}
+
+impl ListInterfacesResponse {
+    pub fn from_db(interfaces: Vec<InterfaceModel>) -> Self {
+        Self {
+            interfaces: interfaces.into_iter().map(InterfaceResponse::from_db).collect(),
+        }
+    }
+}
+
+pub(super) async fn read_interface(state: &AppState, ctx: &Ctx, if_id: i32) -> ApiResult<InterfaceModel> {
+    wg_if_repository::read(&state.pool, if_id)
+        .await
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => InterfaceError::NotFound(if_id),
+            _ => InterfaceError::InfraError(db_error),
+        })
+        .map_err(ApiError::from(ctx))
+}
@@ -0,0 +1,33 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::interfaces::read_interface;
+use crate::service::reconcile::{self, DriftReport, ReconcileReport};
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+pub async fn get_drift(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+) -> ApiResult<Json<DriftReport>> {
+    let interface = read_interface(&state, &ctx, if_id).await?;
+    reconcile::drift(&state, &interface)
+        .await
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
+
+pub async fn reconcile_interface(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+) -> ApiResult<Json<ReconcileReport>> {
+    let interface = read_interface(&state, &ctx, if_id).await?;
+    reconcile::reconcile(&state, &interface)
+        .await
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
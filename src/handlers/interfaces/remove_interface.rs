@@ -0,0 +1,27 @@
+use axum::extract::State;
+use axum::Json;
+use serde_json::{json, Value};
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::interface::InterfaceError;
+use crate::errors::{ApiError, ApiResult};
+use crate::infra::errors::InfraError;
+use crate::infra::wg_if_repository;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+pub async fn delete_interface(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+) -> ApiResult<Json<Value>> {
+    // Peers reference the interface, deleting it would orphan their keys and addresses
+    wg_if_repository::remove(&state.pool, if_id)
+        .await
+        .map(|val| json!({"deleted_interface":val}).into())
+        .map_err(|error| match error {
+            InterfaceError::InfraError(InfraError::NotFound) => InterfaceError::NotFound(if_id),
+            error => error,
+        })
+        .map_err(ApiError::from(&ctx))
+}
@@ -0,0 +1,49 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+
+use crate::core::get_config;
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::interfaces::read_interface;
+use crate::service::server_config::{self, AppliedConfig};
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+// wg-quick config of the server side of an interface, as a downloadable .conf file
+pub async fn download_config(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+) -> ApiResult<impl IntoResponse> {
+    let interface = read_interface(&state, &ctx, if_id).await?;
+    let conf = server_config::render(&state, &interface)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.conf\"", interface.name),
+            ),
+            // the config holds the server private key
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        conf,
+    ))
+}
+
+// Installs the config to WG_CONFIG_DIR, when set, and syncs the live interface to it
+pub async fn apply_config(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+) -> ApiResult<Json<AppliedConfig>> {
+    let interface = read_interface(&state, &ctx, if_id).await?;
+    server_config::apply(&state, &interface, get_config().await.wg_config_dir())
+        .await
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
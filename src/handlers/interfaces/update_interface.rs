@@ -0,0 +1,54 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::interface::{
+    validate_address, validate_fwmark, validate_hook, validate_mtu, validate_name,
+    validate_port, InterfaceError,
+};
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::interfaces::{InterfaceResponse, UpdateInterfaceRequest};
+use crate::infra::errors::InfraError;
+use crate::infra::wg_if_repository::{self, UpdateInterfaceForm};
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::{JsonExtractor, PathExtractor};
+
+pub async fn update_interface(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(if_id): PathExtractor<i32>,
+    JsonExtractor(payload): JsonExtractor<UpdateInterfaceRequest>,
+) -> ApiResult<Json<InterfaceResponse>> {
+    payload
+        .name
+        .as_deref()
+        .map_or(Ok(()), validate_name)
+        .and(payload.address.as_deref().map_or(Ok(()), validate_address))
+        .and(payload.port.map_or(Ok(()), validate_port))
+        .and(validate_mtu(payload.mtu.flatten()))
+        .and(validate_fwmark(payload.fwmark.flatten()))
+        .and(validate_hook(payload.post_up.as_ref().and_then(Option::as_deref)))
+        .and(validate_hook(payload.post_down.as_ref().and_then(Option::as_deref)))
+        .map_err(ApiError::from(&ctx))?;
+
+    let name = payload.name.clone().unwrap_or_default();
+    let update_values = UpdateInterfaceForm {
+        name: payload.name,
+        address: payload.address,
+        port: payload.port,
+        mtu: payload.mtu,
+        fwmark: payload.fwmark,
+        post_up: payload.post_up,
+        post_down: payload.post_down,
+    };
+    wg_if_repository::update(&state.pool, if_id, update_values)
+        .await
+        .map(InterfaceResponse::from_db)
+        .map(Json)
+        .map_err(|error| match error {
+            InterfaceError::InfraError(InfraError::NotFound) => InterfaceError::NotFound(if_id),
+            InterfaceError::InfraError(InfraError::UniqueViolation) => InterfaceError::NameExists(name),
+            error => error,
+        })
+        .map_err(ApiError::from(&ctx))
+}
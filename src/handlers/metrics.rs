@@ -0,0 +1,23 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+use crate::service::metrics;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Prometheus scrape endpoint, outside of the authenticated api. It is only served on
+// the internal METRICS_BIND listener, never merged into the public app
+pub fn metrics_router(state: AppState) -> Router {
+    Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(state)
+}
+
+async fn scrape(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&state).await,
+    )
+}
@@ -1,16 +1,36 @@
+use crate::handlers::api_tokens::api_tokens_router;
 use crate::handlers::auth::auth_router;
+use crate::handlers::interfaces::interfaces_router;
+use crate::domain::models::user::Role;
 use crate::handlers::peers::{peer_router, peers_router};
-use crate::utils::middlewares::mw_ctx::{mw_require_auth, AppState};
+use crate::handlers::users::users_router;
+use crate::utils::middlewares::mw_ctx::{mw_require_auth, mw_require_role, mw_require_session, AppState};
 use axum::{middleware, Router};
 
+mod api_tokens;
 mod auth;
+mod interfaces;
+mod metrics;
 mod peers;
+mod users;
+pub use metrics::metrics_router;
 pub use peers::PeersFilter;
 // api router
 pub fn api_router(state: AppState) -> Router {
     Router::new()
+        .nest(
+            "/users",
+            users_router(state.clone())
+                .route_layer(middleware::from_fn_with_state(Role::Admin, mw_require_role))
+                .route_layer(middleware::from_fn(mw_require_session)),
+        )
+        .nest(
+            "/tokens",
+            api_tokens_router(state.clone()).route_layer(middleware::from_fn(mw_require_session)),
+        )
         .nest("/peer", peer_router(state.clone()))
         .nest("/peers", peers_router(state.clone()))
+        .nest("/interfaces", interfaces_router(state.clone()))
         .route_layer(middleware::from_fn(mw_require_auth))
         .nest("/auth", auth_router(state.clone()))
 }
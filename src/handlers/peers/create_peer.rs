@@ -1,12 +1,20 @@
 // Import necessary modules and types
 use axum::extract::State;
 use axum::Json;
+use chrono::Utc;
 use crate::domain::ctx::Ctx;
+use crate::domain::models::peer::{normalize_allowed_ips, validate_keepalive, PeerError};
+use crate::domain::models::user::Role;
 
 // Import internal modules and types
-use crate::errors::{ApiError, ApiResult};
+use crate::errors::{ApiError, ApiResult, BaseError};
 use crate::handlers::peers::{CreatePeerRequest, PeerResponse};
-use crate::infra::peer_repository;
+use crate::infra::errors::InfraError;
+use crate::infra::{peer_repository, wg_if_repository};
+use crate::service::keys::{generate_preshared_key, WgKeyPair};
+use crate::service::ownership;
+use crate::service::peer::PeerService;
+use crate::service::quota;
 
 // This is a placeholder to extract JSON data from the request body.
 use crate::utils::{middlewares::mw_ctx::AppState, JsonExtractor};
@@ -17,22 +25,75 @@ pub async fn create_peer(
     ctx: Ctx,
     JsonExtractor(new_peer): JsonExtractor<CreatePeerRequest>, // Extract JSON data from the request body
 ) -> ApiResult<Json<PeerResponse>> {
+    let claims = ctx.claims()?;
+    // The peer belongs to its creator, admins may create it for someone else
+    let owner_id = match new_peer.owner_id {
+        Some(owner_id) => {
+            ctx.require(Role::Admin)?;
+            if let Some(owner_id) = owner_id {
+                ownership::check_owner(&state, owner_id).await.map_err(ApiError::from(&ctx))?;
+            }
+            owner_id
+        }
+        None => Some(claims.sub),
+    };
+
+    // The peer is bound to the server key of the interface it belongs to
+    let interface = wg_if_repository::read(&state.pool, new_peer.interface_id)
+        .await
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => PeerError::InterfaceNotFound(new_peer.interface_id),
+            _ => PeerError::InfraError(db_error),
+        })
+        .map_err(ApiError::from(&ctx))?;
+
+    if new_peer.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(ApiError::from(&ctx)(BaseError::BodyParsingError(
+            "expires_at must be in the future".to_string(),
+        )));
+    }
+
+    // both end up in the client config
+    validate_keepalive(new_peer.persistent_keepalive).map_err(ApiError::from(&ctx))?;
+    let allowed_ips = new_peer
+        .allowed_ips
+        .as_deref()
+        .map(normalize_allowed_ips)
+        .transpose()
+        .map_err(ApiError::from(&ctx))?;
+
+    if let Some(limit) = &new_peer.quota {
+        quota::validate(limit).map_err(ApiError::from(&ctx))?;
+    }
+
+    let keypair = WgKeyPair::generate();
     // Create a NewPeerDb instance with data from the JSON request
     let new_peer_db = peer_repository::NewPeerForm {
         name: new_peer.name,
-        if_pubkey: "new_peer.name".to_string(),
-        address: "address".to_string(),
-        private_key: "private_key".to_string(),
-        public_key: "public_key".to_string(),
-        preshared_key: None,
-        interface_id: 1,
+        persistent_keepalive: new_peer.persistent_keepalive,
+        allowed_ips,
+        if_pubkey: interface.pubkey,
+        // allocated from the interface subnet by the repository
+        address: String::new(),
+        private_key: Some(keypair.private_key),
+        public_key: keypair.public_key,
+        preshared_key: new_peer.with_psk.then(generate_preshared_key),
+        interface_id: interface.id,
+        expires_at: new_peer.expires_at.map(|expires_at| expires_at.naive_utc()),
+        quota_bytes: new_peer.quota.map(|limit| limit.bytes),
+        quota_period: new_peer.quota.map(|limit| limit.period.as_str().to_string()),
+        quota_period_start: new_peer.quota.map(|limit| {
+            quota::period_start(limit.period, state.quota.billing_day, Utc::now().naive_utc())
+        }),
+        owner_id,
     };
 
-    // Insert the new peer into the database using the repository
-    peer_repository::create(&state.pool, new_peer_db)
+    // Insert the new peer into the database and add it to the live interface
+    PeerService::new(&state)
+        .create(new_peer_db, new_peer.address, state.ownership.limit_for(&claims))
         .await
         .map(|created_peer|
-                Json(PeerResponse::from_db(created_peer))
+                Json(PeerResponse::from_db(created_peer, state.presence))
         )
         .map_err(ApiError::from(&ctx))
 }
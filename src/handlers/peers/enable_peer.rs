@@ -0,0 +1,95 @@
+use axum::extract::State;
+use axum::Json;
+use uuid::Uuid;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult, BaseError};
+use crate::handlers::peers::{BulkFailure, BulkPeersRequest, BulkPeersResponse, PeerResponse};
+use crate::domain::models::peer::PeerError;
+use crate::infra::peer_repository;
+use crate::service::ownership;
+use crate::service::peer::PeerService;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::{JsonExtractor, PathExtractor};
+
+// bounds the time a bulk request holds, every peer is a change on the interface
+const MAX_BULK_PEERS: usize = 500;
+
+pub async fn enable_peer(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+) -> ApiResult<Json<PeerResponse>> {
+    set_enabled(ctx, state, peer_id, true).await
+}
+
+pub async fn disable_peer(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+) -> ApiResult<Json<PeerResponse>> {
+    set_enabled(ctx, state, peer_id, false).await
+}
+
+pub async fn enable_peers(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    JsonExtractor(payload): JsonExtractor<BulkPeersRequest>,
+) -> ApiResult<Json<BulkPeersResponse>> {
+    set_enabled_many(ctx, state, payload, true).await
+}
+
+pub async fn disable_peers(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    JsonExtractor(payload): JsonExtractor<BulkPeersRequest>,
+) -> ApiResult<Json<BulkPeersResponse>> {
+    set_enabled_many(ctx, state, payload, false).await
+}
+
+async fn set_enabled(ctx: Ctx, state: AppState, peer_id: Uuid, enabled: bool) -> ApiResult<Json<PeerResponse>> {
+    ownership::check(&state, &ctx.claims()?, peer_id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    PeerService::new(&state)
+        .set_enabled(peer_id, enabled)
+        .await
+        .map(|peer| Json(PeerResponse::from_db(peer, state.presence)))
+        .map_err(ApiError::from(&ctx))
+}
+
+// Answers 200 with the peers that failed listed, the others are changed regardless
+async fn set_enabled_many(
+    ctx: Ctx,
+    state: AppState,
+    mut payload: BulkPeersRequest,
+    enabled: bool,
+) -> ApiResult<Json<BulkPeersResponse>> {
+    if payload.ids.is_empty() || payload.ids.len() > MAX_BULK_PEERS {
+        return Err(ApiError::from(&ctx)(BaseError::BodyParsingError(format!(
+            "ids must hold 1 to {MAX_BULK_PEERS} peer ids"
+        ))));
+    }
+    payload.ids.sort_unstable();
+    payload.ids.dedup();
+    // the peers of other users fail as unknown ones do
+    let mut others = vec![];
+    if let Some(owner_id) = ownership::scope(&ctx.claims()?) {
+        let owned = peer_repository::owned(&state.pool, payload.ids.clone(), owner_id)
+            .await
+            .map_err(|db_error| ApiError::from(&ctx)(PeerError::InfraError(db_error)))?;
+        (payload.ids, others) = payload.ids.into_iter().partition(|id| owned.contains(id));
+    }
+    let (updated, mut failed) = PeerService::new(&state).set_enabled_many(&payload.ids, enabled).await;
+    failed.extend(others.into_iter().map(|id| (id, PeerError::NotFound(id).into())));
+    Ok(Json(BulkPeersResponse {
+        peers: updated
+            .into_iter()
+            .map(|peer| PeerResponse::from_db(peer, state.presence))
+            .collect(),
+        failed: failed
+            .into_iter()
+            .map(|(id, err)| BulkFailure { id, error: err.to_string() })
+            .collect(),
+    }))
+}
@@ -0,0 +1,28 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use uuid::Uuid;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::peers::{EventsQuery, ListEventsResponse};
+use crate::service::event;
+use crate::service::ownership;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+// Events of a peer, for admins also after it was deleted
+pub async fn peer_events(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+    Query(query): Query<EventsQuery>,
+) -> ApiResult<Json<ListEventsResponse>> {
+    ownership::check(&state, &ctx.claims()?, peer_id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    event::list(&state, peer_id, query.limit)
+        .await
+        .map(ListEventsResponse::from_db)
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
@@ -8,17 +8,21 @@ use crate::domain::ctx::Ctx;
 use crate::errors::{ApiError, ApiResult};
 use crate::handlers::peers::{ListPeersResponse, PeersFilter};
 use crate::infra::peer_repository;
+use crate::service::ownership;
 
 
 
 pub async fn list_peers(
     ctx: Ctx,
     State(state): State<AppState>,
-    Query(params): Query<PeersFilter>,
+    Query(mut params): Query<PeersFilter>,
 ) -> ApiResult<Json<ListPeersResponse>> {
-    peer_repository::get_all(&state.pool, params)
+    if let Some(owner_id) = ownership::scope(&ctx.claims()?) {
+        params.owner_id = Some(owner_id);
+    }
+    peer_repository::get_all(&state.pool, params, state.presence)
         .await
-        .map(ListPeersResponse::from_db)
+        .map(|peers| ListPeersResponse::from_db(peers, state.presence))
         .map(Json)
         .map_err(
             |err| {
@@ -0,0 +1,127 @@
+use std::convert::Infallible;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use futures_util::stream::{self, Stream};
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
+use tracing::{debug, warn};
+
+use crate::core::{get_config, Claims};
+use crate::domain::ctx::Ctx;
+use crate::errors::ApiResult;
+use crate::handlers::peers::wg_dump::{resolve_interface, IfId};
+use crate::service::live::{LiveEvent, Subscription};
+use crate::service::{api_token, auth, ownership};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Server-Sent Events, one `snapshot` of every peer first and then `peer` and `removed`
+// events as they change
+pub async fn live_sse(
+    State(state): State<AppState>,
+    Query(payload): Query<IfId>,
+    ctx: Ctx,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let subscription = subscribe(&state, &ctx, payload.interface_id).await?;
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.next().await?;
+        let sse = Event::default().event(event.name()).json_data(&event).unwrap();
+        Some((Ok(sse), subscription))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// The same events as JSON text messages, tagged with their `type`
+pub async fn live_ws(
+    State(state): State<AppState>,
+    Query(payload): Query<IfId>,
+    ctx: Ctx,
+    upgrade: WebSocketUpgrade,
+) -> ApiResult<Response> {
+    let subscription = subscribe(&state, &ctx, payload.interface_id).await?;
+    Ok(upgrade.on_upgrade(move |socket| forward(socket, subscription)).into_response())
+}
+
+async fn forward(mut socket: WebSocket, mut subscription: AuthorizedSubscription) {
+    loop {
+        tokio::select! {
+            event = subscription.next() => {
+                let Some(event) = event else { break };
+                if socket.send(Message::Text(serde_json::to_string(&event).unwrap())).await.is_err() {
+                    break;
+                }
+            }
+            // nothing is expected from the client, it is read to notice it left
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+    debug!("live websocket closed");
+}
+
+async fn subscribe(
+    state: &AppState,
+    ctx: &Ctx,
+    interface_id: Option<i32>,
+) -> ApiResult<AuthorizedSubscription> {
+    let interface = match interface_id {
+        Some(_) => Some(resolve_interface(state, ctx, interface_id).await?),
+        None => None,
+    };
+    let claims = ctx.claims()?;
+    // everybody but the admins follows their own peers only
+    let owner = ownership::scope(&claims);
+    let every = get_config().await.wg_live_interval();
+    let mut recheck = interval_at(Instant::now() + every, every);
+    recheck.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    Ok(AuthorizedSubscription {
+        state: state.clone(),
+        claims,
+        api_token: ctx.is_api_token(),
+        subscription: state.live.subscribe(interface, owner),
+        recheck,
+    })
+}
+
+// Ends once the credentials it was opened with no longer hold: after a logout, a change of
+// role, the expiry of the token or the deletion of an API token. Checked on every poll tick.
+struct AuthorizedSubscription {
+    state: AppState,
+    claims: Claims,
+    api_token: bool,
+    subscription: Subscription,
+    recheck: Interval,
+}
+
+impl AuthorizedSubscription {
+    async fn next(&mut self) -> Option<LiveEvent> {
+        loop {
+            tokio::select! {
+                event = self.subscription.next() => return event,
+                _ = self.recheck.tick() => {
+                    if !self.still_valid().await {
+                        debug!("live subscription of {} ended, its token no longer holds", self.claims.name);
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn still_valid(&self) -> bool {
+        if !self.api_token {
+            return auth::session_valid(&self.state.revoked, &self.claims, Utc::now().naive_utc());
+        }
+        // the stream stays open while the database can not tell
+        api_token::still_valid(&self.state.pool, &self.claims)
+            .await
+            .unwrap_or_else(|err| {
+                warn!("can not check API token {}: {err}", self.claims.jti);
+                true
+            })
+    }
+}
@@ -1,57 +1,148 @@
-use axum::routing::{delete, get, patch, post};
-use axum::Router;
+use axum::routing::{get, patch, post};
+use axum::{middleware, Router};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::domain::models::peer::PeerModel;
+use crate::domain::models::peer::{PeerModel, PeerPresence, PresenceThresholds, QuotaLimit, QuotaPeriod};
+use crate::domain::models::user::{ApiScope, Role};
+use crate::infra::event_repository::PeerEvent;
+use crate::infra::session_repository::PeerSession;
+use crate::utils::deserialize_some;
 
-use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::middlewares::mw_ctx::{mw_require_role, mw_require_scope, AppState};
 
 mod create_peer;
+mod enable_peer;
+mod events;
 mod get_peer;
 mod list_peers;
+mod live;
+mod peer_config;
 mod update_peer;
 mod wg_dump;
 mod remove_peer;
+mod sessions;
+mod traffic;
 // Define a sub-router for handling peers-related routes
 pub fn peers_router(state: AppState) -> Router {
+    let manage = state.ownership.manage_role();
     // Create a new Router for peers-related routes
     Router::new()
+        // The live interfaces, with the peers and preshared keys of everybody
         .route("/dump", get(wg_dump::wg_dump))
+        .route_layer(middleware::from_fn_with_state(Role::Admin, mw_require_role))
+        // Suspend or restore many peers at once, {"ids": [...]}
+        .route("/enable", post(enable_peer::enable_peers))
+        .route("/disable", post(enable_peer::disable_peers))
+        .route_layer(middleware::from_fn_with_state(manage, mw_require_role))
+        // the dump holds the preshared keys, API tokens need peers:write for it as well
+        .route_layer(middleware::from_fn_with_state(ApiScope::PeersWrite, mw_require_scope))
         // Define a route for listing peers using the HTTP GET method
-        .route("/rxtx", get(wg_dump::wg_rxtx_lha))
         .route("/", get(list_peers::list_peers))
+        // Transfer counters and handshakes of the live interfaces
+        .route("/rxtx", get(wg_dump::wg_rxtx_lha))
+        // Live status changes of the peers, ?interface_id= to follow a single interface
+        .route("/events", get(live::live_sse))
+        .route("/ws", get(live::live_ws))
+        .route_layer(middleware::from_fn_with_state(ApiScope::PeersRead, mw_require_scope))
         .with_state(state)
 }
 
+// Changes and the client configurations, which hold the private keys, are for operators,
+// or for everybody in self-service mode. Only admins reach the peers of other users.
 pub fn peer_router(state: AppState) -> Router {
+    let manage = state.ownership.manage_role();
     // Create a new Router for peers-related routes
     Router::new()
         // Define a route for creating a new peer using the HTTP POST method
         .route("/", post(create_peer::create_peer))
+        .route("/:id", patch(update_peer::update_peer).delete(remove_peer::delete_peer))
+        // Add the peer to its interface or take it off, keeping its keys and address
+        .route("/:id/enable", post(enable_peer::enable_peer))
+        .route("/:id/disable", post(enable_peer::disable_peer))
+        // Download the wg-quick client configuration of a peer
+        .route("/:id/config", get(peer_config::download_config))
+        // Same configuration as a QR code, ?format=png|svg&size=512&ecc=M
+        .route("/:id/qr", get(peer_config::download_qr))
+        .route_layer(middleware::from_fn_with_state(manage, mw_require_role))
+        .route_layer(middleware::from_fn_with_state(ApiScope::PeersWrite, mw_require_scope))
         // Define a route for retrieving a specific peer by ID using the HTTP GET method
         .route("/:id", get(get_peer::get_peer))
-        .route("/:id", patch(update_peer::update_peer))
-        .route("/:id", delete(remove_peer::delete_peer))
+        // Bandwidth history, ?from=&to=&step=
+        .route("/:id/traffic", get(traffic::peer_traffic))
+        // Connection history, the newest first, ?limit=
+        .route("/:id/sessions", get(sessions::peer_sessions))
+        // What the server did to the peer on its own, e.g. disabling it when it expired
+        .route("/:id/events", get(events::peer_events))
+        .route_layer(middleware::from_fn_with_state(ApiScope::PeersRead, mw_require_scope))
         // Provide the application state to this sub-router
         .with_state(state)
 }
 
 // Define a struct for filtering peers
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct PeersFilter {
     pub enabled: bool,
     pub name_contains: Option<String>,
+    pub interface_id: Option<i32>,
+    pub status: Option<PeerPresence>,
+    // expires_at passed, or not set or still ahead
+    pub expired: Option<bool>,
+    // for admins, everybody else only lists their own peers
+    pub owner_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrafficQuery {
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    step: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    limit: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreatePeerRequest {
     name: String,
+    interface_id: i32,
+    // generate a preshared key for an extra layer of symmetric encryption
+    #[serde(default)]
+    with_psk: bool,
+    persistent_keepalive: Option<i32>,
+    allowed_ips: Option<String>,
+    // pick a specific tunnel address instead of the next free one
+    address: Option<String>,
+    // the peer is disabled once this passes
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    // the peer is disabled once it moved this many bytes in the period
+    quota: Option<QuotaLimit>,
+    // admins only, the caller when missing and null for a peer of nobody
+    #[serde(default, deserialize_with = "deserialize_some")]
+    owner_id: Option<Option<Uuid>>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdatePeerRequest {
     name: Option<String>,
+    // disabled peers stay in the database but are removed from the interface
+    enabled: Option<bool>,
+    // null removes the expiry
+    #[serde(default, deserialize_with = "deserialize_some")]
+    expires_at: Option<Option<chrono::DateTime<chrono::Utc>>>,
+    // null removes the quota
+    #[serde(default, deserialize_with = "deserialize_some")]
+    quota: Option<Option<QuotaLimit>>,
+    // admins only, hands the peer over to another user, null to nobody
+    #[serde(default, deserialize_with = "deserialize_some")]
+    owner_id: Option<Option<Uuid>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -59,31 +150,162 @@ pub struct PeerResponse {
     id: Uuid,
     name: String,
     enabled: bool,
+    public_key: String,
+    address: String,
+    interface_id: i32,
+    // usage as of the last poll of the interface, see usage_updated_at
+    transfer_rx: u64,
+    transfer_tx: u64,
+    last_handshake_at: Option<chrono::NaiveDateTime>,
+    endpoint_addr: Option<String>,
+    usage_updated_at: Option<chrono::NaiveDateTime>,
+    // from the age of last_handshake_at
+    status: PeerPresence,
+    expires_at: Option<chrono::NaiveDateTime>,
+    quota: Option<QuotaResponse>,
+    owner_id: Option<Uuid>,
     created_at: chrono::NaiveDateTime,
     updated_at: chrono::NaiveDateTime,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuotaResponse {
+    bytes: i64,
+    period: QuotaPeriod,
+    period_start: chrono::NaiveDateTime,
+    // rx and tx in the period, as of the last poll
+    used: i64,
+    remaining: i64,
+    // disabled for going over, until the next period or a larger quota
+    exceeded: bool,
+}
+
 impl PeerResponse {
-    pub fn from_db(peer: PeerModel) -> Self {
+    pub fn from_db(peer: PeerModel, presence: PresenceThresholds) -> Self {
+        let transfer_total = peer.transfer_total();
         Self {
+            status: peer.presence(presence, chrono::Utc::now().naive_utc()),
             id: peer.id,
             name: peer.name,
             enabled: peer.enabled,
+            public_key: peer.public_key,
+            address: peer.address,
+            interface_id: peer.interface_id,
+            transfer_rx: peer.transfer_rx,
+            transfer_tx: peer.transfer_tx,
+            last_handshake_at: peer.last_handshake_at,
+            endpoint_addr: peer.endpoint_addr,
+            usage_updated_at: peer.usage_updated_at,
+            expires_at: peer.expires_at,
+            quota: peer.quota.as_ref().map(|quota| QuotaResponse {
+                bytes: quota.bytes,
+                period: quota.period,
+                period_start: quota.period_start,
+                used: quota.used(transfer_total),
+                remaining: quota.remaining(transfer_total),
+                exceeded: quota.exceeded_at.is_some(),
+            }),
+            owner_id: peer.owner_id,
             created_at: peer.created_at,
             updated_at: peer.updated_at,
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BulkPeersRequest {
+    ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkPeersResponse {
+    // the peers as they are now
+    peers: Vec<PeerResponse>,
+    failed: Vec<BulkFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkFailure {
+    id: Uuid,
+    error: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ListPeersResponse {
     peers: Vec<PeerResponse>,
 }
 
 impl ListPeersResponse {
-    pub fn from_db(peers: Vec<PeerModel>) -> Self {
+    pub fn from_db(peers: Vec<PeerModel>, presence: PresenceThresholds) -> Self {
+        Self {
+            peers: peers.into_iter().map(|peer| PeerResponse::from_db(peer, presence)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    id: i64,
+    started_at: chrono::NaiveDateTime,
+    // None while the peer is still connected
+    ended_at: Option<chrono::NaiveDateTime>,
+    last_handshake_at: chrono::NaiveDateTime,
+    endpoint_addr: Option<String>,
+    rx: i64,
+    tx: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListSessionsResponse {
+    sessions: Vec<SessionResponse>,
+}
+
+impl ListSessionsResponse {
+    pub fn from_db(sessions: Vec<PeerSession>) -> Self {
+        Self {
+            sessions: sessions
+                .into_iter()
+                .map(|session| SessionResponse {
+                    id: session.id,
+                    started_at: session.started_at,
+                    ended_at: session.ended_at,
+                    last_handshake_at: session.last_handshake_at,
+                    endpoint_addr: session.endpoint_addr,
+                    rx: session.rx,
+                    tx: session.tx,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventResponse {
+    id: i64,
+    peer_name: String,
+    kind: String,
+    detail: Option<String>,
+    created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListEventsResponse {
+    events: Vec<EventResponse>,
+}
+
+impl ListEventsResponse {
+    pub fn from_db(events: Vec<PeerEvent>) -> Self {
         Self {
-            peers: peers.into_iter().map(PeerResponse::from_db).collect(),
+            events: events
+                .into_iter()
+                .map(|event| EventResponse {
+                    id: event.id,
+                    peer_name: event.peer_name,
+                    kind: event.kind,
+                    detail: event.detail,
+                    created_at: event.created_at,
+                })
+                .collect(),
         }
     }
 }
@@ -0,0 +1,98 @@
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::core::get_config;
+use crate::domain::ctx::Ctx;
+use crate::domain::models::peer::PeerModel;
+use crate::errors::{ApiError, ApiResult};
+use crate::infra::wg_if_repository;
+use crate::service::ownership;
+use crate::service::qr::{render_png, render_svg, QrEcc, QrFormat, MAX_SIZE, MIN_SIZE};
+use crate::service::wg_config::{config_file_name, render_client_config};
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+#[derive(Debug, Deserialize)]
+pub struct QrParams {
+    #[serde(default)]
+    format: QrFormat,
+    // minimum width and height of the image in pixels
+    size: Option<u32>,
+    // error correction level, L, M, Q or H
+    #[serde(default)]
+    ecc: QrEcc,
+}
+
+// wg-quick client configuration of a peer, as a downloadable .conf file
+pub async fn download_config(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+) -> ApiResult<impl IntoResponse> {
+    let (peer, conf) = client_config(&state, &ctx, peer_id).await?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", config_file_name(&peer.name)),
+            ),
+            // the file holds the private and preshared keys of the peer
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        conf,
+    ))
+}
+
+// The same configuration as a QR code, for the mobile apps to scan
+pub async fn download_qr(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+    Query(params): Query<QrParams>,
+) -> ApiResult<impl IntoResponse> {
+    let (_, conf) = client_config(&state, &ctx, peer_id).await?;
+    let size = params.size.unwrap_or(512).clamp(MIN_SIZE, MAX_SIZE);
+    let (content_type, body) = match params.format {
+        QrFormat::Png => ("image/png", render_png(&conf, params.ecc, size)),
+        QrFormat::Svg => (
+            "image/svg+xml",
+            render_svg(&conf, params.ecc, size).map(String::into_bytes),
+        ),
+    };
+    let body = body.map_err(ApiError::from(&ctx))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            // the image embeds the private key of the peer
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        body,
+    ))
+}
+
+async fn client_config(
+    state: &AppState,
+    ctx: &Ctx,
+    peer_id: Uuid,
+) -> ApiResult<(PeerModel, String)> {
+    let peer = ownership::peer(state, &ctx.claims()?, peer_id)
+        .await
+        .map_err(ApiError::from(ctx))?;
+    let interface = wg_if_repository::read(&state.pool, peer.interface_id)
+        .await
+        .map_err(ApiError::from(ctx))?;
+
+    let config = get_config().await;
+    let conf = render_client_config(
+        &peer,
+        &interface,
+        config.wg_endpoint_host(),
+        config.wg_client_dns(),
+    )
+    .map_err(ApiError::from(ctx))?;
+    Ok((peer, conf))
+}
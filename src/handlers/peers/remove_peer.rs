@@ -4,7 +4,8 @@ use serde_json::{json, Value};
 use uuid::Uuid;
 use crate::domain::ctx::Ctx;
 use crate::errors::{ApiError, ApiResult};
-use crate::infra::peer_repository::{remove_peer};
+use crate::service::ownership;
+use crate::service::peer::PeerService;
 use crate::utils::middlewares::mw_ctx::AppState;
 use crate::utils::PathExtractor;
 
@@ -13,13 +14,12 @@ pub async fn delete_peer(
     State(state): State<AppState>,
     PathExtractor(peer_id): PathExtractor<Uuid>,
 ) -> ApiResult<Json<Value>> {
-    remove_peer(&state.pool, peer_id)
+    ownership::check(&state, &ctx.claims()?, peer_id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    PeerService::new(&state)
+        .remove(peer_id)
         .await
         .map(|val|json!({"deleted_peer":val}).into())
-        .map_err(|err| {
-            ApiError {
-                req_id: ctx.req_id(),
-                error: err.into(),
-            }
-        })
+        .map_err(ApiError::from(&ctx))
 }
\ No newline at end of file
@@ -0,0 +1,28 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use uuid::Uuid;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::peers::{ListSessionsResponse, SessionsQuery};
+use crate::service::ownership;
+use crate::service::session;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+// Connections of a peer, as recorded by the usage poller
+pub async fn peer_sessions(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+    Query(query): Query<SessionsQuery>,
+) -> ApiResult<Json<ListSessionsResponse>> {
+    ownership::check(&state, &ctx.claims()?, peer_id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    session::list(&state, peer_id, query.limit)
+        .await
+        .map(ListSessionsResponse::from_db)
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
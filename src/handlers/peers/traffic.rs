@@ -0,0 +1,27 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use uuid::Uuid;
+
+use crate::domain::ctx::Ctx;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::peers::TrafficQuery;
+use crate::service::ownership;
+use crate::service::traffic::{self, TrafficSeries};
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::PathExtractor;
+
+// Bandwidth history of a peer, ?from=&to= as RFC 3339 and ?step=5m|1h|1d
+pub async fn peer_traffic(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(peer_id): PathExtractor<Uuid>,
+    Query(query): Query<TrafficQuery>,
+) -> ApiResult<Json<TrafficSeries>> {
+    ownership::check(&state, &ctx.claims()?, peer_id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    traffic::series(&state, peer_id, query.from, query.to, query.step.as_deref())
+        .await
+        .map(Json)
+        .map_err(ApiError::from(&ctx))
+}
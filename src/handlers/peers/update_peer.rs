@@ -1,11 +1,12 @@
-use crate::domain::models::peer::PeerError;
 use crate::handlers::peers::{PeerResponse, UpdatePeerRequest};
-use crate::infra::errors::InfraError;
-use crate::infra::peer_repository;
+use crate::domain::models::user::Role;
+use crate::service::ownership;
+use crate::service::peer::PeerService;
 use crate::utils::middlewares::mw_ctx::AppState;
 use crate::utils::{JsonExtractor, PathExtractor};
 use axum::extract::State;
 use axum::Json;
+use chrono::Utc;
 use uuid::Uuid;
 use crate::domain::ctx::Ctx;
 use crate::errors::{ApiError, ApiResult, BaseError};
@@ -17,20 +18,34 @@ pub async fn update_peer(
     PathExtractor(id): PathExtractor<Uuid>,
     JsonExtractor(payload): JsonExtractor<UpdatePeerRequest>,
 ) -> ApiResult<Json<PeerResponse>> {
+    ownership::check(&state, &ctx.claims()?, id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    // handing a peer over is for admins, who are not held to the limit of the new owner
+    if let Some(owner_id) = payload.owner_id {
+        ctx.require(Role::Admin)?;
+        if let Some(owner_id) = owner_id {
+            ownership::check_owner(&state, owner_id).await.map_err(ApiError::from(&ctx))?;
+        }
+    }
+    // null removes the expiry, a past one would disable the peer on the next check
+    if payload.expires_at.flatten().is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(ApiError::from(&ctx)(BaseError::BodyParsingError(
+            "expires_at must be in the future".to_string(),
+        )));
+    }
     let update_values = UpdatePeerForm{
         interface_id:None,
-        name:payload.name
+        name:payload.name,
+        enabled:payload.enabled,
+        expires_at: payload.expires_at.map(|expires_at| expires_at.map(|at| at.naive_utc())),
+        owner_id: payload.owner_id,
+        quota_exceeded_at: None,
     };
-    peer_repository::update_peer(&state.pool, id, update_values)
+    PeerService::new(&state)
+        .update(id, update_values, payload.quota)
         .await
-        .map(PeerResponse::from_db)
+        .map(|peer| PeerResponse::from_db(peer, state.presence))
         .map(Json)
-        .map_err(|db_error| match db_error {
-            // Map infrastructure errors to custom PeerError types
-            InfraError::InternalServerError => PeerError::InternalServerError,
-            InfraError::NotFound => PeerError::NotFound(id),
-        }).map_err(|e|ApiError{
-        req_id:ctx.req_id(),
-        error:BaseError::from(e)
-    })
+        .map_err(ApiError::from(&ctx))
 }
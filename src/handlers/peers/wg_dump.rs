@@ -1,70 +1,90 @@
-use axum::extract::Query;
+use std::collections::{HashMap, HashSet};
+
+use axum::extract::{Query, State};
 use axum::Json;
 use serde::Deserialize;
-use tracing::debug;
 
 use crate::domain::ctx::Ctx;
-use crate::errors::{ApiError, ApiResult, BaseError};
+use crate::domain::models::interface::InterfaceError;
+use crate::errors::{ApiError, ApiResult};
+use crate::infra::errors::InfraError;
+use crate::infra::{peer_repository, wg_if_repository};
+use crate::service::ownership;
 use crate::service::peer::{PeerFullDump, PeerRxTxDump};
-use crate::utils::sudo_exec;
+use crate::service::wg_dump::{WgDump, ALL_INTERFACES};
+use crate::utils::middlewares::mw_ctx::AppState;
 
 #[derive(Deserialize)]
 pub struct IfId {
-    interface_id: Option<usize>,
+    pub(super) interface_id: Option<i32>,
 }
 
 pub async fn wg_dump(
+    State(state): State<AppState>,
     Query(payload): Query<IfId>,
     ctx: Ctx,
 ) -> ApiResult<Json<Vec<PeerFullDump>>> {
-    debug!("{:?}",payload.interface_id);
-    let dump_output = sudo_exec(vec!["wg", "show", "wg0", "dump"])
+    let dump = show_dump(&state, &ctx, payload.interface_id).await?;
+    let public_keys = dump.peers.iter().map(|peer| peer.public_key.clone()).collect();
+    let mut names: HashMap<String, String> = peer_repository::get_by_public_keys(&state.pool, public_keys)
         .await
-        .map_err(ApiError::from(&ctx))
-        ?;
-    let dump = String::from_utf8(dump_output.stdout).unwrap();
-    let dump_vec: Vec<&str> = dump
-        .strip_suffix("\n")
-        .map(|v| v.split("\n").collect())
-        .ok_or(ApiError {
-            req_id: ctx.req_id(),
-            error: BaseError::PeerDumpError("wg show dump error"),
-        })?;
-    let mut dump_vec_skipped_if = dump_vec.iter();
-    dump_vec_skipped_if.next();
-    Ok(
-        Json(
-            dump_vec_skipped_if
-                .map(|v| PeerFullDump::from_dump_str(v))
-                .collect()
-        )
-    )
+        .map_err(ApiError::from(&ctx))?
+        .into_iter()
+        .map(|peer| (peer.public_key, peer.name))
+        .collect();
+    Ok(Json(
+        dump.peers
+            .into_iter()
+            .map(|peer| {
+                let name = names.remove(&peer.public_key);
+                PeerFullDump::from_dump(peer, name)
+            })
+            .collect(),
+    ))
 }
 
-pub async fn wg_rxtx_lha(ctx: Ctx) -> ApiResult<Json<Vec<PeerRxTxDump>>> {
-    let t_output = sudo_exec(vec!["wg", "show", "wg0", "transfer"])
-        .await
-        .map_err(ApiError::from(&ctx))?;
-    let t_string = String::from_utf8(t_output.stdout).unwrap();
-    let dump_transfer = t_string
-        .strip_suffix("\n")
-        .map(|v| v.split("\n"))
-        .ok_or(BaseError::PeerDumpError("wg show transfer error"),
-        ).map_err(ApiError::from(&ctx))?;
-    let lha_output = sudo_exec(vec!["wg", "show", "wg0", "latest-handshakes"])
+pub async fn wg_rxtx_lha(
+    State(state): State<AppState>,
+    Query(payload): Query<IfId>,
+    ctx: Ctx,
+) -> ApiResult<Json<Vec<PeerRxTxDump>>> {
+    // the dump carries the transfer counters and handshakes of every peer in a single snapshot
+    let mut dump = show_dump(&state, &ctx, payload.interface_id).await?;
+    // everybody but the admins sees their own peers only
+    if let Some(owner_id) = ownership::scope(&ctx.claims()?) {
+        let public_keys = dump.peers.iter().map(|peer| peer.public_key.clone()).collect();
+        let owned: HashSet<String> = peer_repository::get_by_public_keys(&state.pool, public_keys)
+            .await
+            .map_err(ApiError::from(&ctx))?
+            .into_iter()
+            .filter(|peer| peer.owner_id == Some(owner_id))
+            .map(|peer| peer.public_key)
+            .collect();
+        dump.peers.retain(|peer| owned.contains(&peer.public_key));
+    }
+    Ok(Json(dump.peers.into_iter().map(PeerRxTxDump::from).collect()))
+}
+
+async fn show_dump(state: &AppState, ctx: &Ctx, interface_id: Option<i32>) -> ApiResult<WgDump> {
+    let interface = resolve_interface(state, ctx, interface_id).await?;
+    state.wg.show(&interface).await.map_err(ApiError::from(ctx))
+}
+
+// Interface name to pass to `wg show`, every interface when no id is given
+pub(super) async fn resolve_interface(
+    state: &AppState,
+    ctx: &Ctx,
+    interface_id: Option<i32>,
+) -> ApiResult<String> {
+    let Some(if_id) = interface_id else {
+        return Ok(ALL_INTERFACES.to_string());
+    };
+    wg_if_repository::read(&state.pool, if_id)
         .await
-        .map_err(ApiError::from(&ctx))?;
-    let lha_string = String::from_utf8(lha_output.stdout).unwrap();
-    let dump_last_handshake =
-        lha_string
-            .strip_suffix("\n")
-            .map(|v| v.split("\n"))
-            .ok_or(ApiError {
-                req_id: ctx.req_id(),
-                error: BaseError::PeerDumpError("latest-handshakes"),
-            })?;
-    Ok(Json(dump_transfer
-        .zip(dump_last_handshake)
-        .map(PeerRxTxDump::from_rxtx_lha)
-        .collect()))
+        .map(|interface| interface.name)
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => InterfaceError::NotFound(if_id),
+            _ => InterfaceError::InfraError(db_error),
+        })
+        .map_err(ApiError::from(ctx))
 }
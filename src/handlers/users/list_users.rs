@@ -0,0 +1,23 @@
+use axum::extract::State;
+use axum::Json;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::user::UserError;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::auth::UserResponse;
+use crate::handlers::users::ListUsersResponse;
+use crate::infra::user_repository;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+pub async fn list_users(
+    ctx: Ctx,
+    State(state): State<AppState>,
+) -> ApiResult<Json<ListUsersResponse>> {
+    let users = user_repository::get_all(&state.pool)
+        .await
+        .map_err(UserError::InfraError)
+        .map_err(ApiError::from(&ctx))?;
+    Ok(Json(ListUsersResponse {
+        users: users.into_iter().map(UserResponse::from_db).collect(),
+    }))
+}
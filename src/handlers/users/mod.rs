@@ -0,0 +1,29 @@
+use axum::routing::{get, patch};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::models::user::Role;
+use crate::handlers::auth::UserResponse;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+mod list_users;
+mod update_user;
+
+// User management, for admins. New users are added through /auth/register
+pub fn users_router(state: AppState) -> Router {
+    Router::new()
+        .route("/", get(list_users::list_users))
+        // change the role, the user's sessions end so the new one applies at once
+        .route("/:id", patch(update_user::update_user))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRequest {
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListUsersResponse {
+    users: Vec<UserResponse>,
+}
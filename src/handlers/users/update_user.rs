@@ -0,0 +1,43 @@
+use axum::extract::State;
+use axum::Json;
+use uuid::Uuid;
+
+use crate::domain::ctx::Ctx;
+use crate::domain::models::user::UserError;
+use crate::errors::{ApiError, ApiResult};
+use crate::handlers::auth::UserResponse;
+use crate::handlers::users::UpdateUserRequest;
+use crate::infra::errors::InfraError;
+use crate::infra::user_repository;
+use crate::service::auth;
+use crate::utils::middlewares::mw_ctx::AppState;
+use crate::utils::{JsonExtractor, PathExtractor};
+
+pub async fn update_user(
+    ctx: Ctx,
+    State(state): State<AppState>,
+    PathExtractor(user_id): PathExtractor<Uuid>,
+    JsonExtractor(payload): JsonExtractor<UpdateUserRequest>,
+) -> ApiResult<Json<UserResponse>> {
+    let not_found = |err| match err {
+        InfraError::NotFound => UserError::NotFound(user_id),
+        err => UserError::InfraError(err),
+    };
+    let user = user_repository::read(&state.pool, user_id)
+        .await
+        .map_err(not_found)
+        .map_err(ApiError::from(&ctx))?;
+    if user.role == payload.role {
+        return Ok(Json(UserResponse::from_db(user)));
+    }
+    let user = user_repository::set_role(&state.pool, user_id, payload.role)
+        .await
+        .map_err(not_found)
+        .and_then(|user| user.ok_or(UserError::LastAdmin))
+        .map_err(ApiError::from(&ctx))?;
+    // tokens issued before carry the old role, a demotion has to take effect now
+    auth::logout_all(&state, user_id)
+        .await
+        .map_err(ApiError::from(&ctx))?;
+    Ok(Json(UserResponse::from_db(user)))
+}
@@ -1,5 +1,67 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        name -> Varchar,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        #[max_length = 16]
+        prefix -> Varchar,
+        scopes -> Array<Text>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    peer_events (id) {
+        id -> Int8,
+        peer_id -> Uuid,
+        peer_name -> Varchar,
+        #[max_length = 32]
+        kind -> Varchar,
+        detail -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    peer_sessions (id) {
+        id -> Int8,
+        peer_id -> Uuid,
+        started_at -> Timestamp,
+        last_handshake_at -> Timestamp,
+        ended_at -> Nullable<Timestamp>,
+        endpoint_addr -> Nullable<Varchar>,
+        rx -> Int8,
+        tx -> Int8,
+    }
+}
+
+diesel::table! {
+    peer_traffic_rollups (peer_id, resolution, bucket_start) {
+        peer_id -> Uuid,
+        #[max_length = 8]
+        resolution -> Varchar,
+        bucket_start -> Timestamp,
+        rx -> Int8,
+        tx -> Int8,
+    }
+}
+
+diesel::table! {
+    peer_traffic_samples (peer_id, sampled_at) {
+        peer_id -> Uuid,
+        sampled_at -> Timestamp,
+        rx -> Int8,
+        tx -> Int8,
+    }
+}
+
 diesel::table! {
     peers (id) {
         id -> Uuid,
@@ -8,17 +70,51 @@ diesel::table! {
         persistent_keepalive -> Int4,
         allowed_ips -> Varchar,
         preshared_key -> Nullable<Varchar>,
-        private_key -> Varchar,
+        private_key -> Nullable<Varchar>,
         public_key -> Varchar,
         if_pubkey -> Varchar,
         address -> Varchar,
-        transfer_rx -> Int4,
-        transfer_tx -> Int4,
+        transfer_rx -> Int8,
+        transfer_tx -> Int8,
         last_handshake_at -> Nullable<Timestamp>,
         endpoint_addr -> Nullable<Varchar>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
         interface_id -> Int4,
+        kernel_rx -> Int8,
+        kernel_tx -> Int8,
+        usage_updated_at -> Nullable<Timestamp>,
+        expires_at -> Nullable<Timestamp>,
+        quota_bytes -> Nullable<Int8>,
+        #[max_length = 8]
+        quota_period -> Nullable<Varchar>,
+        quota_used_from -> Int8,
+        quota_period_start -> Nullable<Timestamp>,
+        quota_warned_percent -> Int4,
+        quota_exceeded_at -> Nullable<Timestamp>,
+        owner_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        access_jti -> Uuid,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (jti) {
+        jti -> Uuid,
+        expires_at -> Timestamp,
     }
 }
 
@@ -31,6 +127,8 @@ diesel::table! {
         hashed_password -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        #[max_length = 16]
+        role -> Varchar,
     }
 }
 
@@ -48,13 +146,28 @@ diesel::table! {
         port -> Int4,
         mtu -> Nullable<Int4>,
         fwmark -> Nullable<Int4>,
+        post_up -> Nullable<Text>,
+        post_down -> Nullable<Text>,
     }
 }
 
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(peer_sessions -> peers (peer_id));
+diesel::joinable!(peer_traffic_rollups -> peers (peer_id));
+diesel::joinable!(peer_traffic_samples -> peers (peer_id));
+diesel::joinable!(peers -> users (owner_id));
 diesel::joinable!(peers -> wg_if (interface_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    peer_events,
+    peer_sessions,
+    peer_traffic_rollups,
+    peer_traffic_samples,
     peers,
+    refresh_tokens,
+    revoked_tokens,
     users,
     wg_if,
 );
@@ -1,11 +1,16 @@
 use deadpool_diesel::InteractError;
+use diesel::result::DatabaseErrorKind;
 use std::fmt;
 
+use crate::domain::models::interface::InterfaceError;
+use crate::domain::models::ipam::IpamError;
+
 // Define a custom error type for infrastructure-related errors
 #[derive(Debug)]
 pub enum InfraError {
     InternalServerError, // Represents an internal server error
     NotFound,            // Represents a resource not found error
+    UniqueViolation,     // Represents a unique constraint violation
                          // SerializationError,
 }
 
@@ -19,6 +24,7 @@ impl fmt::Display for InfraError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             InfraError::NotFound => write!(f, "Not found"), // Display "Not found" for NotFound variant
+            InfraError::UniqueViolation => write!(f, "Already exists"), // Display "Already exists" for UniqueViolation variant
             // InfraError::SerializationError => write!(f, "Serialization error"), // Display "Not found" for NotFound variant
             InfraError::InternalServerError => write!(f, "Internal server error"), // Display "Internal server error" for InternalServerError variant
         }
@@ -35,6 +41,9 @@ impl Error for diesel::result::Error {
     fn as_infra_error(&self) -> InfraError {
         match self {
             diesel::result::Error::NotFound => InfraError::NotFound, // Map NotFound to InfraError::NotFound
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                InfraError::UniqueViolation // Map unique constraint violations to InfraError::UniqueViolation
+            }
             // diesel::result::Error::SerializationError(_err)=>InfraError::SerializationError,
             _ => InfraError::InternalServerError, // Map other errors to InfraError::InternalServerError
         }
@@ -54,3 +63,17 @@ impl Error for InteractError {
         InfraError::InternalServerError // Map all InteractError instances to InfraError::InternalServerError
     }
 }
+
+// Lets the repositories use `?` on queries inside an address allocation
+impl From<diesel::result::Error> for IpamError {
+    fn from(value: diesel::result::Error) -> Self {
+        IpamError::Infra(adapt_infra_error(value))
+    }
+}
+
+// Same for the changes an interface with peers refuses
+impl From<diesel::result::Error> for InterfaceError {
+    fn from(value: diesel::result::Error) -> Self {
+        InterfaceError::InfraError(adapt_infra_error(value))
+    }
+}
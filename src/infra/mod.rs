@@ -1,4 +1,5 @@
 mod db;
 pub mod errors;
 mod repositories;
-pub use repositories::{peer_repository, user_repository};
+pub mod wireguard;
+pub use repositories::{api_token_repository, event_repository, peer_repository, session_repository, token_repository, traffic_repository, user_repository, wg_if_repository};
@@ -0,0 +1,161 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::domain::models::user::UserModel;
+use crate::infra::db::schema::{api_tokens, users};
+use crate::infra::errors::{adapt_infra_error, InfraError};
+use crate::infra::user_repository::{adapt_user_db_to_user, User};
+
+#[derive(Queryable, Selectable, Debug, Clone, PartialEq)]
+#[diesel(table_name = api_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = api_tokens)]
+pub struct NewApiToken {
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_hash: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+pub async fn create(
+    pool: &deadpool_diesel::postgres::Pool,
+    new_token: NewApiToken,
+) -> Result<ApiToken, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            diesel::insert_into(api_tokens::table)
+                .values(new_token)
+                .returning(ApiToken::as_returning())
+                .get_result(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to retrieve the tokens of a user, or of everybody, the newest first
+pub async fn get_all(
+    pool: &deadpool_diesel::postgres::Pool,
+    user_id: Option<Uuid>,
+) -> Result<Vec<ApiToken>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            let mut query = api_tokens::table.into_boxed::<diesel::pg::Pg>();
+            if let Some(user_id) = user_id {
+                query = query.filter(api_tokens::user_id.eq(user_id));
+            }
+            query
+                .order(api_tokens::created_at.desc())
+                .select(ApiToken::as_select())
+                .load(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to find a token along with its user, who may have changed role since it was created
+pub async fn read_by_hash(
+    pool: &deadpool_diesel::postgres::Pool,
+    token_hash: String,
+) -> Result<(ApiToken, UserModel), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let (token, user) = conn
+        .interact(move |conn| {
+            api_tokens::table
+                .inner_join(users::table)
+                .filter(api_tokens::token_hash.eq(token_hash))
+                .select((ApiToken::as_select(), User::as_select()))
+                .get_result::<(ApiToken, User)>(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok((token, adapt_user_db_to_user(user)))
+}
+
+// Function to read a token by its id, with its user
+pub async fn read(
+    pool: &deadpool_diesel::postgres::Pool,
+    token_id: Uuid,
+) -> Result<(ApiToken, UserModel), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let (token, user) = conn
+        .interact(move |conn| {
+            api_tokens::table
+                .inner_join(users::table)
+                .filter(api_tokens::id.eq(token_id))
+                .select((ApiToken::as_select(), User::as_select()))
+                .get_result::<(ApiToken, User)>(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok((token, adapt_user_db_to_user(user)))
+}
+
+// Function to record that a token was used
+pub async fn touch(
+    pool: &deadpool_diesel::postgres::Pool,
+    token_id: Uuid,
+    at: NaiveDateTime,
+) -> Result<(), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    conn.interact(move |conn| {
+        diesel::update(api_tokens::table)
+            .filter(api_tokens::id.eq(token_id))
+            .set(api_tokens::last_used_at.eq(at))
+            .execute(conn)
+    })
+    .await
+    .map_err(adapt_infra_error)?
+    .map_err(adapt_infra_error)?;
+    Ok(())
+}
+
+// Function to delete a token, only among the ones of `user_id` when given.
+// NotFound when there is no such token.
+pub async fn delete(
+    pool: &deadpool_diesel::postgres::Pool,
+    token_id: Uuid,
+    user_id: Option<Uuid>,
+) -> Result<(), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let deleted = conn
+        .interact(move |conn| {
+            let mut query = diesel::delete(api_tokens::table)
+                .filter(api_tokens::id.eq(token_id))
+                .into_boxed();
+            if let Some(user_id) = user_id {
+                query = query.filter(api_tokens::user_id.eq(user_id));
+            }
+            query.execute(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    if deleted == 0 {
+        return Err(InfraError::NotFound);
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::infra::db::schema::peer_events;
+use crate::infra::errors::{adapt_infra_error, InfraError};
+
+#[derive(Queryable, Selectable, Debug, Clone, PartialEq)]
+#[diesel(table_name = peer_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PeerEvent {
+    pub id: i64,
+    pub peer_id: Uuid,
+    // as it was named then, the peer may be gone
+    pub peer_name: String,
+    pub kind: String,
+    pub detail: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = peer_events)]
+pub struct NewPeerEvent {
+    pub peer_id: Uuid,
+    pub peer_name: String,
+    pub kind: String,
+    pub detail: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn create(pool: &deadpool_diesel::postgres::Pool, event: NewPeerEvent) -> Result<(), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    conn.interact(move |conn| {
+        diesel::insert_into(peer_events::table).values(event).execute(conn)
+    })
+    .await
+    .map_err(adapt_infra_error)?
+    .map_err(adapt_infra_error)?;
+    Ok(())
+}
+
+// Function to retrieve the latest events of a peer, the newest first
+pub async fn list(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_id: Uuid,
+    limit: i64,
+) -> Result<Vec<PeerEvent>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            peer_events::table
+                .filter(peer_events::peer_id.eq(peer_id))
+                .order_by((peer_events::created_at.desc(), peer_events::id.desc()))
+                .limit(limit)
+                .select(PeerEvent::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
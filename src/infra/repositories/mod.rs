@@ -1,3 +1,8 @@
+pub mod api_token_repository;
+pub mod event_repository;
 pub mod peer_repository;
+pub mod session_repository;
+pub mod token_repository;
+pub mod traffic_repository;
 pub mod user_repository;
-mod wg_if_repository;
+pub mod wg_if_repository;
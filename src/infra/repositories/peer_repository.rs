@@ -4,12 +4,15 @@ use diesel::prelude::*;
 use serde::{Deserialize};
 use uuid::Uuid;
 
-use crate::domain::models::peer::PeerModel;
+use crate::domain::models::peer::{PeerModel, PeerPresence, PeerQuota, PresenceThresholds, QuotaPeriod};
 use crate::handlers::PeersFilter;
-use crate::infra::db::schema::peers;
+use crate::infra::db::schema::{peer_traffic_samples, peers, users, wg_if};
 use crate::infra::db::schema::peers::{id, updated_at};
 use crate::infra::errors::{adapt_infra_error, InfraError};
+use crate::infra::repositories::session_repository::{self, SessionChange};
+use crate::infra::repositories::traffic_repository::NewTrafficSample;
 use crate::infra::repositories::wg_if_repository::Interface;
+use crate::domain::models::ipam::{self, IpamError};
 
 // Define a struct representing the database schema for peers
 #[derive(Queryable, Selectable, Identifiable, Associations, Debug, PartialEq)]
@@ -23,60 +26,210 @@ pub struct PeerDb {
     pub persistent_keepalive: i32,
     pub allowed_ips: String,
     pub preshared_key: Option<String>,
-    pub private_key: String,
+    pub private_key: Option<String>,
     pub public_key: String,
     pub if_pubkey: String,
     pub address: String,
-    pub transfer_rx: i32,
-    pub transfer_tx: i32,
+    pub transfer_rx: i64,
+    pub transfer_tx: i64,
     pub last_handshake_at: Option<NaiveDateTime>,
     pub endpoint_addr: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub interface_id: i32,
+    pub usage_updated_at: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub quota_bytes: Option<i64>,
+    pub quota_period: Option<String>,
+    pub quota_used_from: i64,
+    pub quota_period_start: Option<NaiveDateTime>,
+    pub quota_warned_percent: i32,
+    pub quota_exceeded_at: Option<NaiveDateTime>,
+    pub owner_id: Option<Uuid>,
 }
 
 #[derive(Deserialize, Insertable)]
 #[diesel(table_name = peers)]
 pub struct NewPeerForm {
     pub name: String,
+    pub persistent_keepalive: Option<i32>,
+    pub allowed_ips: Option<String>,
     pub preshared_key: Option<String>,
-    pub private_key: String,
+    pub private_key: Option<String>,
     pub public_key: String,
     pub if_pubkey: String,
     pub address: String,
     pub interface_id: i32,
+    pub expires_at: Option<NaiveDateTime>,
+    // a new peer has moved nothing yet, its period starts at zero
+    pub quota_bytes: Option<i64>,
+    pub quota_period: Option<String>,
+    pub quota_period_start: Option<NaiveDateTime>,
+    pub owner_id: Option<Uuid>,
 }
 
 #[derive(Deserialize, Insertable, AsChangeset)]
 #[diesel(table_name = peers)]
 pub struct UpdatePeerForm {
     pub name: Option<String>,
+    pub enabled: Option<bool>,
     pub interface_id: Option<i32>,
+    // Some(None) removes the expiry
+    pub expires_at: Option<Option<NaiveDateTime>>,
+    // Some(None) leaves the peer to the admins
+    pub owner_id: Option<Option<Uuid>>,
+    // Some(None) once the peer is no longer disabled for going over its quota
+    pub quota_exceeded_at: Option<Option<NaiveDateTime>>,
 }
 
+impl UpdatePeerForm {
+    // diesel refuses an update without changes
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.enabled.is_none()
+            && self.interface_id.is_none()
+            && self.expires_at.is_none()
+            && self.owner_id.is_none()
+            && self.quota_exceeded_at.is_none()
+    }
+}
+
+// Traffic totals of a peer, with the kernel counters they were last accumulated from
+#[derive(Queryable, Selectable, Debug, Clone, PartialEq)]
+#[diesel(table_name = peers)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PeerCounters {
+    pub id: Uuid,
+    pub interface_id: i32,
+    pub public_key: String,
+    pub transfer_rx: i64,
+    pub transfer_tx: i64,
+    pub kernel_rx: i64,
+    pub kernel_tx: i64,
+}
+
+#[derive(AsChangeset, Debug, Clone, PartialEq)]
+#[diesel(table_name = peers)]
+pub struct PeerUsageForm {
+    pub transfer_rx: i64,
+    pub transfer_tx: i64,
+    pub kernel_rx: i64,
+    pub kernel_tx: i64,
+    // None keeps the stored value, the kernel forgets both when the interface restarts
+    pub last_handshake_at: Option<NaiveDateTime>,
+    pub endpoint_addr: Option<String>,
+    pub usage_updated_at: Option<NaiveDateTime>,
+}
+
+// The whole quota of a peer, the limit along with where the enforcement stands.
+// None clears a column, all of them are None or 0 for a peer without a quota.
+#[derive(AsChangeset, Debug, Clone, PartialEq)]
+#[diesel(table_name = peers)]
+#[diesel(treat_none_as_null = true)]
+pub struct PeerQuotaForm {
+    pub quota_bytes: Option<i64>,
+    pub quota_period: Option<String>,
+    pub quota_used_from: i64,
+    pub quota_period_start: Option<NaiveDateTime>,
+    pub quota_warned_percent: i32,
+    pub quota_exceeded_at: Option<NaiveDateTime>,
+}
 
-// Function to insert a new peer into the database
+impl From<Option<&PeerQuota>> for PeerQuotaForm {
+    fn from(quota: Option<&PeerQuota>) -> Self {
+        Self {
+            quota_bytes: quota.map(|quota| quota.bytes),
+            quota_period: quota.map(|quota| quota.period.as_str().to_string()),
+            quota_used_from: quota.map_or(0, |quota| quota.used_from),
+            quota_period_start: quota.map(|quota| quota.period_start),
+            quota_warned_percent: quota.map_or(0, |quota| quota.warned_percent),
+            quota_exceeded_at: quota.and_then(|quota| quota.exceeded_at),
+        }
+    }
+}
+
+// Function to insert a new peer into the database, with the next free (or the requested) address of its interface.
+// None when its owner already has `max_owned` peers.
 pub async fn create(
     pool: &deadpool_diesel::postgres::Pool,
-    new_post: NewPeerForm,
-) -> Result<PeerModel, InfraError> {
-    // Get a database connection from the pool and handle any potential errors
+    mut new_peer: NewPeerForm,
+    requested_address: Option<String>,
+    max_owned: Option<i64>,
+) -> Result<Option<PeerModel>, IpamError> {
     let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction::<_, IpamError, _>(|conn| {
+                if let (Some(owner_id), Some(max_owned)) = (new_peer.owner_id, max_owned) {
+                    // Lock the owner, the peers of one user may be created on different interfaces
+                    users::table
+                        .filter(users::id.eq(owner_id))
+                        .select(users::id)
+                        .for_update()
+                        .get_result::<Uuid>(conn)?;
+                    let owned: i64 = peers::table
+                        .filter(peers::owner_id.eq(owner_id))
+                        .count()
+                        .get_result(conn)?;
+                    if owned >= max_owned {
+                        return Ok(None);
+                    }
+                }
+                // Lock the interface row, so concurrent creates on the same interface are serialized
+                let if_address: String = wg_if::table
+                    .filter(wg_if::id.eq(new_peer.interface_id))
+                    .select(wg_if::address)
+                    .for_update()
+                    .get_result(conn)?;
+                let used: Vec<String> = peers::table
+                    .filter(peers::interface_id.eq(new_peer.interface_id))
+                    .select(peers::address)
+                    .load(conn)?;
+                new_peer.address =
+                    ipam::allocate(&if_address, &used, requested_address.as_deref())?;
+                Ok(Some(
+                    diesel::insert_into(peers::table)
+                        .values(new_peer)
+                        .returning(PeerDb::as_returning())
+                        .get_result(conn)?,
+                ))
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)??;
 
-    // Insert the new peer into the 'peers' table, returning the inserted peer
+    Ok(res.map(adapt_peer_db_to_peer))
+}
+
+// Function to insert a peer adopted from an existing setup, keeping the addresses it already has
+pub async fn import(
+    pool: &deadpool_diesel::postgres::Pool,
+    new_peer: NewPeerForm,
+) -> Result<PeerModel, IpamError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
     let res = conn
         .interact(move |conn| {
-            diesel::insert_into(peers::table)
-                .values(new_post)
-                .returning(PeerDb::as_returning()) // Return the inserted peer
-                .get_result(conn)
+            conn.transaction::<_, IpamError, _>(|conn| {
+                // Same lock as create, so an allocation can not race the check below
+                wg_if::table
+                    .filter(wg_if::id.eq(new_peer.interface_id))
+                    .select(wg_if::id)
+                    .for_update()
+                    .get_result::<i32>(conn)?;
+                let used: Vec<String> = peers::table
+                    .filter(peers::interface_id.eq(new_peer.interface_id))
+                    .select(peers::address)
+                    .load(conn)?;
+                ipam::check_free(&new_peer.address, &used)?;
+                Ok(diesel::insert_into(peers::table)
+                    .values(new_peer)
+                    .returning(PeerDb::as_returning())
+                    .get_result(conn)?)
+            })
         })
         .await
-        .map_err(adapt_infra_error)?
-        .map_err(adapt_infra_error)?;
+        .map_err(adapt_infra_error)??;
 
-    // Adapt the database representation to the application's domain model
     Ok(adapt_peer_db_to_peer(res))
 }
 
@@ -108,6 +261,7 @@ pub async fn read(
 pub async fn get_all(
     pool: &deadpool_diesel::postgres::Pool,
     filter: PeersFilter,
+    presence: PresenceThresholds,
 ) -> Result<Vec<PeerModel>, InfraError> {
     // Get a database connection from the pool and handle any potential errors
     let conn = pool.get().await.map_err(adapt_infra_error)?;
@@ -126,6 +280,36 @@ pub async fn get_all(
                 query = query.filter(peers::name.ilike(format!("%{name_contains}%")));
             }
 
+            if let Some(interface_id) = filter.interface_id {
+                query = query.filter(peers::interface_id.eq(interface_id));
+            }
+
+            if let Some(owner_id) = filter.owner_id {
+                query = query.filter(peers::owner_id.eq(owner_id));
+            }
+
+            if let Some(expired) = filter.expired {
+                let now = chrono::Utc::now().naive_utc();
+                query = if expired {
+                    query.filter(peers::expires_at.le(now))
+                } else {
+                    query.filter(peers::expires_at.is_null().or(peers::expires_at.gt(now)))
+                };
+            }
+
+            // The status is the age of the last handshake, as of the last usage poll
+            if let Some(status) = filter.status {
+                let now = chrono::Utc::now().naive_utc();
+                let (online_since, idle_since) = (now - presence.online, now - presence.idle);
+                let handshake = peers::last_handshake_at;
+                query = match status {
+                    PeerPresence::Online => query.filter(handshake.ge(online_since)),
+                    PeerPresence::Idle => query.filter(handshake.lt(online_since).and(handshake.ge(idle_since))),
+                    PeerPresence::Offline => query.filter(handshake.lt(idle_since)),
+                    PeerPresence::NeverConnected => query.filter(handshake.is_null()),
+                };
+            }
+
             // Select the peers matching the query
             query.order_by(updated_at.desc()).select(PeerDb::as_select()).get_results(conn)
         })
@@ -139,17 +323,190 @@ pub async fn get_all(
     Ok(peers)
 }
 
+// Function to keep the ids of `peer_ids` that belong to `owner_id`
+pub async fn owned(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_ids: Vec<Uuid>,
+    owner_id: Uuid,
+) -> Result<Vec<Uuid>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            peers::table
+                .filter(peers::id.eq_any(peer_ids))
+                .filter(peers::owner_id.eq(owner_id))
+                .select(peers::id)
+                .load(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to retrieve the peers that expired at or before `at`
+pub async fn expired(
+    pool: &deadpool_diesel::postgres::Pool,
+    at: NaiveDateTime,
+) -> Result<Vec<PeerModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            peers::table
+                .filter(peers::expires_at.le(at))
+                .order_by(peers::expires_at)
+                .select(PeerDb::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res.into_iter().map(adapt_peer_db_to_peer).collect())
+}
+
+// Disables a peer only while it is still past its expires_at at `at`, None when it was
+// extended, or disabled, since the expiry read it
+pub async fn disable_expired(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_id: Uuid,
+    at: NaiveDateTime,
+) -> Result<Option<PeerModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            diesel::update(peers::table)
+                .filter(peers::id.eq(peer_id))
+                .filter(peers::enabled.eq(true))
+                .filter(peers::expires_at.le(at))
+                .set(peers::enabled.eq(false))
+                .returning(PeerDb::as_returning())
+                .get_result(conn)
+                .optional()
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res.map(adapt_peer_db_to_peer))
+}
+
+// Deletes a disabled peer only while it is still past its expires_at at `at`. A disabled
+// peer is not on its interface, the database is all there is to it
+pub async fn remove_expired(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_id: Uuid,
+    at: NaiveDateTime,
+) -> Result<Option<Uuid>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            diesel::delete(peers::table)
+                .filter(peers::id.eq(peer_id))
+                .filter(peers::enabled.eq(false))
+                .filter(peers::expires_at.le(at))
+                .returning(peers::id)
+                .get_result(conn)
+                .optional()
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to retrieve the peers with a quota, for the enforcement after a usage poll
+pub async fn with_quota(
+    pool: &deadpool_diesel::postgres::Pool,
+) -> Result<Vec<PeerModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            peers::table
+                .filter(peers::quota_bytes.is_not_null())
+                .select(PeerDb::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res.into_iter().map(adapt_peer_db_to_peer).collect())
+}
+
+// Function to retrieve the peers with the given public keys, e.g. to name the entries of a wg dump
+pub async fn get_by_public_keys(
+    pool: &deadpool_diesel::postgres::Pool,
+    public_keys: Vec<String>,
+) -> Result<Vec<PeerModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            peers::table
+                .filter(peers::public_key.eq_any(public_keys))
+                .select(PeerDb::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res.into_iter().map(adapt_peer_db_to_peer).collect())
+}
+
+pub async fn get_counters(
+    pool: &deadpool_diesel::postgres::Pool,
+) -> Result<Vec<PeerCounters>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| peers::table.select(PeerCounters::as_select()).get_results(conn))
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to store the usage of many peers at once, as read in one poll, with the traffic
+// each of them had since the previous poll and the sessions it opened, extended or closed
+pub async fn update_usage(
+    pool: &deadpool_diesel::postgres::Pool,
+    usages: Vec<(Uuid, PeerUsageForm)>,
+    samples: Vec<NewTrafficSample>,
+    sessions: Vec<SessionChange>,
+) -> Result<usize, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let mut updated = 0;
+                for (peer_id, usage) in usages {
+                    updated += diesel::update(peers::table)
+                        .filter(peers::id.eq(peer_id))
+                        .set(usage)
+                        .execute(conn)?;
+                }
+                diesel::insert_into(peer_traffic_samples::table)
+                    .values(samples)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                session_repository::apply(conn, sessions)?;
+                Ok::<_, diesel::result::Error>(updated)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
 pub async fn update_peer(
     pool: &deadpool_diesel::postgres::Pool,
     peer_id: Uuid,
     update_peer: UpdatePeerForm,
+    quota: Option<PeerQuotaForm>,
 ) -> Result<PeerModel, InfraError> {
     let conn = pool.get().await.map_err(adapt_infra_error)?;
     let res = conn
         .interact(move |conn| {
             diesel::update(peers::table)
                 .filter(peers::id.eq(peer_id))
-                .set(update_peer)
+                .set((update_peer, quota))
                 .returning(PeerDb::as_returning())
                 .get_result(conn)
         })
@@ -159,6 +516,36 @@ pub async fn update_peer(
     Ok(adapt_peer_db_to_peer(res))
 }
 
+// Writes the quota state only while it is still `expected`, None when it was changed since,
+// e.g. by a PATCH in between the enforcement reading and writing it
+pub async fn replace_quota(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_id: Uuid,
+    expected: PeerQuotaForm,
+    quota: PeerQuotaForm,
+) -> Result<Option<PeerModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            diesel::update(peers::table)
+                .filter(peers::id.eq(peer_id))
+                .filter(peers::quota_bytes.is_not_distinct_from(expected.quota_bytes))
+                .filter(peers::quota_period.is_not_distinct_from(expected.quota_period))
+                .filter(peers::quota_used_from.eq(expected.quota_used_from))
+                .filter(peers::quota_period_start.is_not_distinct_from(expected.quota_period_start))
+                .filter(peers::quota_warned_percent.eq(expected.quota_warned_percent))
+                .filter(peers::quota_exceeded_at.is_not_distinct_from(expected.quota_exceeded_at))
+                .set(quota)
+                .returning(PeerDb::as_returning())
+                .get_result(conn)
+                .optional()
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res.map(adapt_peer_db_to_peer))
+}
+
 pub async fn remove_peer(
     pool: &deadpool_diesel::postgres::Pool,
     peer_id: Uuid,
@@ -181,6 +568,16 @@ pub async fn remove_peer(
 
 // Function to adapt a database representation of a peer to the application's domain model
 fn adapt_peer_db_to_peer(post_db: PeerDb) -> PeerModel {
+    // the check constraint sets both or neither
+    let quota_period = post_db.quota_period.as_deref().and_then(QuotaPeriod::parse);
+    let quota = post_db.quota_bytes.zip(quota_period).map(|(bytes, period)| PeerQuota {
+        bytes,
+        period,
+        used_from: post_db.quota_used_from,
+        period_start: post_db.quota_period_start.unwrap_or(post_db.created_at),
+        warned_percent: post_db.quota_warned_percent,
+        exceeded_at: post_db.quota_exceeded_at,
+    });
     PeerModel {
         id: post_db.id,
         enabled: post_db.enabled,
@@ -190,8 +587,8 @@ fn adapt_peer_db_to_peer(post_db: PeerDb) -> PeerModel {
         preshared_key: post_db.preshared_key,
         public_key: post_db.public_key,
         private_key: post_db.private_key,
-        transfer_tx: post_db.transfer_tx as usize,
-        transfer_rx: post_db.transfer_rx as usize,
+        transfer_tx: post_db.transfer_tx as u64,
+        transfer_rx: post_db.transfer_rx as u64,
         last_handshake_at: post_db.last_handshake_at,
         endpoint_addr: post_db.endpoint_addr,
         address: post_db.address,
@@ -199,5 +596,9 @@ fn adapt_peer_db_to_peer(post_db: PeerDb) -> PeerModel {
         created_at: post_db.created_at,
         updated_at: post_db.updated_at,
         interface_id: post_db.interface_id,
+        usage_updated_at: post_db.usage_updated_at,
+        expires_at: post_db.expires_at,
+        quota,
+        owner_id: post_db.owner_id,
     }
 }
@@ -0,0 +1,105 @@
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::infra::db::schema::peer_sessions;
+use crate::infra::errors::{adapt_infra_error, InfraError};
+
+#[derive(Queryable, Selectable, Debug, Clone, PartialEq)]
+#[diesel(table_name = peer_sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PeerSession {
+    pub id: i64,
+    pub peer_id: Uuid,
+    pub started_at: NaiveDateTime,
+    pub last_handshake_at: NaiveDateTime,
+    pub ended_at: Option<NaiveDateTime>,
+    pub endpoint_addr: Option<String>,
+    pub rx: i64,
+    pub tx: i64,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = peer_sessions)]
+pub struct NewPeerSession {
+    pub peer_id: Uuid,
+    pub started_at: NaiveDateTime,
+    pub last_handshake_at: NaiveDateTime,
+    pub endpoint_addr: Option<String>,
+    pub rx: i64,
+    pub tx: i64,
+}
+
+#[derive(AsChangeset, Debug, Clone, PartialEq)]
+#[diesel(table_name = peer_sessions)]
+pub struct PeerSessionForm {
+    pub last_handshake_at: NaiveDateTime,
+    // None keeps the stored value
+    pub ended_at: Option<NaiveDateTime>,
+    pub endpoint_addr: Option<String>,
+    pub rx: i64,
+    pub tx: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionChange {
+    Open(NewPeerSession),
+    Update(i64, PeerSessionForm),
+}
+
+// Function to retrieve the sessions that have not ended, at most one per peer
+pub async fn get_open(pool: &deadpool_diesel::postgres::Pool) -> Result<Vec<PeerSession>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            peer_sessions::table
+                .filter(peer_sessions::ended_at.is_null())
+                .select(PeerSession::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to retrieve the latest sessions of a peer, the newest first
+pub async fn list(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_id: Uuid,
+    limit: i64,
+) -> Result<Vec<PeerSession>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            peer_sessions::table
+                .filter(peer_sessions::peer_id.eq(peer_id))
+                .order_by(peer_sessions::started_at.desc())
+                .limit(limit)
+                .select(PeerSession::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Applies the session changes of a poll, in the transaction that stores its usage
+pub(crate) fn apply(conn: &mut PgConnection, changes: Vec<SessionChange>) -> QueryResult<()> {
+    for change in changes {
+        match change {
+            SessionChange::Open(session) => {
+                diesel::insert_into(peer_sessions::table).values(session).execute(conn)?;
+            }
+            SessionChange::Update(session_id, form) => {
+                diesel::update(peer_sessions::table)
+                    .filter(peer_sessions::id.eq(session_id))
+                    .set(form)
+                    .execute(conn)?;
+            }
+        }
+    }
+    Ok(())
+}
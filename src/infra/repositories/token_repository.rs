@@ -0,0 +1,244 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::infra::db::schema::{refresh_tokens, revoked_tokens};
+use crate::infra::errors::{adapt_infra_error, InfraError};
+
+#[derive(Queryable, Selectable, Debug, Clone, PartialEq)]
+#[diesel(table_name = refresh_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub access_jti: Uuid,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    // set once it was exchanged for the next token of its family
+    pub used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshToken {
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub token_hash: String,
+    pub access_jti: Uuid,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = revoked_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RevokedToken {
+    pub jti: Uuid,
+    pub expires_at: NaiveDateTime,
+}
+
+// Which refresh tokens to revoke
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevokeScope {
+    Family(Uuid),
+    User(Uuid),
+}
+
+pub async fn create(
+    pool: &deadpool_diesel::postgres::Pool,
+    new_token: NewRefreshToken,
+) -> Result<RefreshToken, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            diesel::insert_into(refresh_tokens::table)
+                .values(new_token)
+                .returning(RefreshToken::as_returning())
+                .get_result(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+pub async fn read_by_hash(
+    pool: &deadpool_diesel::postgres::Pool,
+    token_hash: String,
+) -> Result<RefreshToken, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(token_hash))
+                .select(RefreshToken::as_select())
+                .get_result(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to find the family an access token was issued in
+pub async fn family_of(
+    pool: &deadpool_diesel::postgres::Pool,
+    access_jti: Uuid,
+) -> Result<Option<Uuid>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            refresh_tokens::table
+                .filter(refresh_tokens::access_jti.eq(access_jti))
+                .select(refresh_tokens::family_id)
+                .first(conn)
+                .optional()
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to use up a refresh token and store the next one of its family, in one
+// transaction. None when the token was used up or revoked in the meantime, of two
+// concurrent refreshes with the same token only one gets through.
+pub async fn rotate(
+    pool: &deadpool_diesel::postgres::Pool,
+    token_id: Uuid,
+    next: NewRefreshToken,
+) -> Result<Option<RefreshToken>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let used = diesel::update(refresh_tokens::table)
+                    .filter(refresh_tokens::id.eq(token_id))
+                    .filter(refresh_tokens::used_at.is_null())
+                    .filter(refresh_tokens::revoked_at.is_null())
+                    .set(refresh_tokens::used_at.eq(next.created_at))
+                    .execute(conn)?;
+                if used == 0 {
+                    return Ok(None);
+                }
+                diesel::insert_into(refresh_tokens::table)
+                    .values(next)
+                    .returning(RefreshToken::as_returning())
+                    .get_result(conn)
+                    .map(Some)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to revoke refresh tokens along with the access tokens issued with them that are
+// still within `access_ttl`. Returns the access tokens, for the in-memory list.
+pub async fn revoke(
+    pool: &deadpool_diesel::postgres::Pool,
+    scope: RevokeScope,
+    now: NaiveDateTime,
+    access_ttl: TimeDelta,
+) -> Result<Vec<RevokedToken>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let mut query = refresh_tokens::table.into_boxed::<diesel::pg::Pg>();
+                query = match scope {
+                    RevokeScope::Family(family_id) => query.filter(refresh_tokens::family_id.eq(family_id)),
+                    RevokeScope::User(user_id) => query.filter(refresh_tokens::user_id.eq(user_id)),
+                };
+                let issued: Vec<(Uuid, NaiveDateTime)> = query
+                    .filter(refresh_tokens::created_at.gt(now - access_ttl))
+                    .select((refresh_tokens::access_jti, refresh_tokens::created_at))
+                    .load(conn)?;
+                let revoked: Vec<RevokedToken> = issued
+                    .into_iter()
+                    .map(|(jti, created_at)| RevokedToken { jti, expires_at: created_at + access_ttl })
+                    .collect();
+
+                let update = diesel::update(refresh_tokens::table)
+                    .filter(refresh_tokens::revoked_at.is_null())
+                    .set(refresh_tokens::revoked_at.eq(now));
+                match scope {
+                    RevokeScope::Family(family_id) => {
+                        update.filter(refresh_tokens::family_id.eq(family_id)).execute(conn)?
+                    }
+                    RevokeScope::User(user_id) => update.filter(refresh_tokens::user_id.eq(user_id)).execute(conn)?,
+                };
+                diesel::insert_into(revoked_tokens::table)
+                    .values(&revoked)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                Ok::<_, diesel::result::Error>(revoked)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to revoke a single access token
+pub async fn revoke_access(
+    pool: &deadpool_diesel::postgres::Pool,
+    token: RevokedToken,
+) -> Result<(), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    conn.interact(move |conn| {
+        diesel::insert_into(revoked_tokens::table)
+            .values(token)
+            .on_conflict_do_nothing()
+            .execute(conn)
+    })
+    .await
+    .map_err(adapt_infra_error)?
+    .map_err(adapt_infra_error)?;
+    Ok(())
+}
+
+// Function to retrieve the access tokens revoked and not expired at `now`
+pub async fn revoked(
+    pool: &deadpool_diesel::postgres::Pool,
+    now: NaiveDateTime,
+) -> Result<Vec<RevokedToken>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            revoked_tokens::table
+                .filter(revoked_tokens::expires_at.gt(now))
+                .select(RevokedToken::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to drop the refresh tokens and revocations that expired
+pub async fn prune(pool: &deadpool_diesel::postgres::Pool, now: NaiveDateTime) -> Result<usize, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let refresh = diesel::delete(refresh_tokens::table)
+                    .filter(refresh_tokens::expires_at.le(now))
+                    .execute(conn)?;
+                let revoked = diesel::delete(revoked_tokens::table)
+                    .filter(revoked_tokens::expires_at.le(now))
+                    .execute(conn)?;
+                Ok::<_, diesel::result::Error>(refresh + revoked)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
@@ -0,0 +1,147 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Timestamp, Uuid as SqlUuid, Varchar};
+use uuid::Uuid;
+
+use crate::infra::db::schema::{peer_traffic_rollups, peer_traffic_samples};
+use crate::infra::errors::{adapt_infra_error, InfraError};
+
+// Traffic of a peer between two polls, in bytes
+#[derive(Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = peer_traffic_samples)]
+pub struct NewTrafficSample {
+    pub peer_id: Uuid,
+    pub sampled_at: NaiveDateTime,
+    pub rx: i64,
+    pub tx: i64,
+}
+
+// Where a series is summed from, the coarser the longer it is kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrafficSource {
+    Samples,
+    Hourly,
+    Daily,
+}
+
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct TrafficBucket {
+    #[diesel(sql_type = Timestamp)]
+    pub bucket_start: NaiveDateTime,
+    #[diesel(sql_type = BigInt)]
+    pub rx: i64,
+    #[diesel(sql_type = BigInt)]
+    pub tx: i64,
+}
+
+// Recomputes the buckets from the latest one on, so the current hour and day stay up to date
+// and a run that was missed is caught up by the next one
+const ROLL_UP_HOURS: &str = "
+    INSERT INTO peer_traffic_rollups (peer_id, resolution, bucket_start, rx, tx)
+    SELECT peer_id, 'hour', date_trunc('hour', sampled_at), sum(rx)::BIGINT, sum(tx)::BIGINT
+    FROM peer_traffic_samples
+    WHERE sampled_at >= COALESCE(
+        (SELECT max(bucket_start) FROM peer_traffic_rollups WHERE resolution = 'hour'),
+        '-infinity')
+    GROUP BY 1, 3
+    ON CONFLICT (peer_id, resolution, bucket_start) DO UPDATE SET rx = EXCLUDED.rx, tx = EXCLUDED.tx";
+
+const ROLL_UP_DAYS: &str = "
+    INSERT INTO peer_traffic_rollups (peer_id, resolution, bucket_start, rx, tx)
+    SELECT peer_id, 'day', date_trunc('day', bucket_start), sum(rx)::BIGINT, sum(tx)::BIGINT
+    FROM peer_traffic_rollups
+    WHERE resolution = 'hour' AND bucket_start >= COALESCE(
+        (SELECT max(bucket_start) FROM peer_traffic_rollups WHERE resolution = 'day'),
+        '-infinity')
+    GROUP BY 1, 3
+    ON CONFLICT (peer_id, resolution, bucket_start) DO UPDATE SET rx = EXCLUDED.rx, tx = EXCLUDED.tx";
+
+// Function to fold the samples into hourly buckets and those into daily ones
+pub async fn roll_up(pool: &deadpool_diesel::postgres::Pool) -> Result<(usize, usize), InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            conn.transaction(|conn| {
+                let hours = diesel::sql_query(ROLL_UP_HOURS).execute(conn)?;
+                let days = diesel::sql_query(ROLL_UP_DAYS).execute(conn)?;
+                Ok::<_, diesel::result::Error>((hours, days))
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to drop what is older than the retention of each resolution
+pub async fn prune(
+    pool: &deadpool_diesel::postgres::Pool,
+    samples_before: NaiveDateTime,
+    hours_before: NaiveDateTime,
+    days_before: NaiveDateTime,
+) -> Result<usize, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                let samples = diesel::delete(peer_traffic_samples::table)
+                    .filter(peer_traffic_samples::sampled_at.lt(samples_before))
+                    .execute(conn)?;
+                let mut rollups = 0;
+                for (resolution, before) in [("hour", hours_before), ("day", days_before)] {
+                    rollups += diesel::delete(peer_traffic_rollups::table)
+                        .filter(peer_traffic_rollups::resolution.eq(resolution))
+                        .filter(peer_traffic_rollups::bucket_start.lt(before))
+                        .execute(conn)?;
+                }
+                Ok::<_, diesel::result::Error>(samples + rollups)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
+
+// Function to sum the traffic of a peer into buckets of `step` seconds, aligned to the epoch.
+// Buckets without traffic are not returned.
+pub async fn series(
+    pool: &deadpool_diesel::postgres::Pool,
+    peer_id: Uuid,
+    source: TrafficSource,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    step: i64,
+) -> Result<Vec<TrafficBucket>, InfraError> {
+    let (table, column, resolution) = match source {
+        TrafficSource::Samples => ("peer_traffic_samples", "sampled_at", None),
+        TrafficSource::Hourly => ("peer_traffic_rollups", "bucket_start", Some("hour")),
+        TrafficSource::Daily => ("peer_traffic_rollups", "bucket_start", Some("day")),
+    };
+    let query = format!(
+        "SELECT to_timestamp((floor(extract(epoch FROM {column}) / $4) * $4)::double precision)
+            AT TIME ZONE 'UTC' AS bucket_start,
+            sum(rx)::BIGINT AS rx, sum(tx)::BIGINT AS tx
+        FROM {table}
+        WHERE peer_id = $1 AND {column} >= $2 AND {column} < $3 {}
+        GROUP BY 1 ORDER BY 1",
+        if resolution.is_some() { "AND resolution = $5" } else { "" }
+    );
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            let query = diesel::sql_query(query)
+                .bind::<SqlUuid, _>(peer_id)
+                .bind::<Timestamp, _>(from)
+                .bind::<Timestamp, _>(to)
+                .bind::<BigInt, _>(step);
+            match resolution {
+                Some(resolution) => query.bind::<Varchar, _>(resolution).load(conn),
+                None => query.load(conn),
+            }
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res)
+}
@@ -1,10 +1,11 @@
 use chrono::NaiveDateTime;
-use crate::domain::models::user::UserModel;
+use crate::domain::models::user::{Role, UserModel};
 use crate::infra::db::schema::users;
 use crate::infra::errors::{adapt_infra_error, InfraError};
 use deadpool_diesel::postgres::Pool;
 use diesel::{
-    ExpressionMethods, Insertable, QueryDsl, Queryable, RunQueryDsl, Selectable, SelectableHelper,
+    Connection, ExpressionMethods, Insertable, QueryDsl, Queryable, RunQueryDsl, Selectable,
+    SelectableHelper,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -16,6 +17,7 @@ pub struct User {
     pub id: Uuid,
     pub username: String,
     pub hashed_password: String,
+    pub role: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -25,6 +27,7 @@ pub struct User {
 pub struct NewUserDb {
     pub username: String,
     pub hashed_password: String,
+    pub role: String,
 }
 
 pub async fn create(pool: &Pool, new_user: NewUserDb) -> Result<UserModel, InfraError> {
@@ -43,6 +46,85 @@ pub async fn create(pool: &Pool, new_user: NewUserDb) -> Result<UserModel, Infra
 
     Ok(adapt_user_db_to_user(res))
 }
+
+// Function to create the first user, as an admin. None when there are users already: from
+// then on only admins add users. The table lock keeps two racing sign-ups from both
+// becoming the first.
+pub async fn create_first(pool: &Pool, mut new_user: NewUserDb) -> Result<Option<UserModel>, InfraError> {
+    new_user.role = Role::Admin.as_str().to_string();
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query("LOCK TABLE users IN EXCLUSIVE MODE").execute(conn)?;
+                let users: i64 = users::table.count().get_result(conn)?;
+                if users > 0 {
+                    return Ok(None);
+                }
+                diesel::insert_into(users::table)
+                    .values(new_user)
+                    .returning(User::as_returning())
+                    .get_result(conn)
+                    .map(Some)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+
+    Ok(res.map(adapt_user_db_to_user))
+}
+
+pub async fn get_all(pool: &Pool) -> Result<Vec<UserModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            users::table
+                .order(users::created_at)
+                .select(User::as_select())
+                .load(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+
+    Ok(res.into_iter().map(adapt_user_db_to_user).collect())
+}
+
+// Function to change the role of a user. None when that would leave no admin, checked in
+// the same transaction so two admins demoting each other can not both succeed.
+pub async fn set_role(pool: &Pool, id: Uuid, role: Role) -> Result<Option<UserModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query("LOCK TABLE users IN EXCLUSIVE MODE").execute(conn)?;
+                let user = diesel::update(users::table)
+                    .filter(users::id.eq(id))
+                    .set(users::role.eq(role.as_str()))
+                    .returning(User::as_returning())
+                    .get_result(conn)?;
+                let admins: i64 = users::table
+                    .filter(users::role.eq(Role::Admin.as_str()))
+                    .count()
+                    .get_result(conn)?;
+                if admins == 0 {
+                    // roll the update back
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+                Ok(user)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)?;
+
+    match res {
+        Ok(user) => Ok(Some(adapt_user_db_to_user(user))),
+        Err(diesel::result::Error::RollbackTransaction) => Ok(None),
+        Err(err) => Err(adapt_infra_error(err)),
+    }
+}
+
 pub async fn read(pool: &Pool, id: Uuid) -> Result<UserModel, InfraError> {
     // Get a database connection from the pool and handle any potential errors
     let conn = pool.get().await.map_err(adapt_infra_error)?;
@@ -80,11 +162,13 @@ pub async fn read_by_username(pool: &Pool, username: String) -> Result<UserModel
 }
 
 // Function to adapt a database representation of a user to the application's domain model
-fn adapt_user_db_to_user(db_user: User) -> UserModel {
+pub(crate) fn adapt_user_db_to_user(db_user: User) -> UserModel {
     UserModel {
         id: db_user.id,
         username: db_user.username,
         hashed_password: db_user.hashed_password,
+        // the column is checked, a value the code does not know is the least privilege
+        role: Role::parse(&db_user.role).unwrap_or(Role::Viewer),
         created_at:db_user.created_at,
         updated_at:db_user.updated_at,
     }
@@ -1,5 +1,10 @@
 use diesel::prelude::*;
-use crate::infra::db::schema::wg_if;
+use serde::Deserialize;
+
+use crate::domain::models::interface::{InterfaceError, InterfaceModel};
+use crate::infra::db::schema::{peers, wg_if};
+use crate::infra::errors::{adapt_infra_error, InfraError};
+
 #[derive(Queryable, Selectable, Identifiable, Debug, PartialEq)]
 #[diesel(table_name = wg_if)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -11,8 +16,219 @@ pub struct Interface{
     pub address :String,
     pub port :i32,
     pub mtu :Option<i32>,
-    pub fwmark: Option<i32>
+    pub fwmark: Option<i32>,
+    pub post_up: Option<String>,
+    pub post_down: Option<String>,
+}
+
+#[derive(Deserialize, Insertable)]
+#[diesel(table_name = wg_if)]
+pub struct NewInterfaceForm {
+    pub name: String,
+    pub pubkey: String,
+    pub privkey: String,
+    pub address: String,
+    pub port: i32,
+    pub mtu: Option<i32>,
+    pub fwmark: Option<i32>,
+    pub post_up: Option<String>,
+    pub post_down: Option<String>,
+}
+
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = wg_if)]
+pub struct UpdateInterfaceForm {
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub port: Option<i32>,
+    // Some(None) sets the column to NULL
+    pub mtu: Option<Option<i32>>,
+    pub fwmark: Option<Option<i32>>,
+    pub post_up: Option<Option<String>>,
+    pub post_down: Option<Option<String>>,
+}
+
+impl UpdateInterfaceForm {
+    // diesel refuses an update without changes
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.address.is_none()
+            && self.port.is_none()
+            && self.mtu.is_none()
+            && self.fwmark.is_none()
+            && self.post_up.is_none()
+            && self.post_down.is_none()
+    }
 }
 
+// Function to insert a new interface into the database
+pub async fn create(
+    pool: &deadpool_diesel::postgres::Pool,
+    new_interface: NewInterfaceForm,
+) -> Result<InterfaceModel, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            diesel::insert_into(wg_if::table)
+                .values(new_interface)
+                .returning(Interface::as_returning())
+                .get_result(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(adapt_interface_db_to_interface(res))
+}
 
+// Function to retrieve an interface from the database by its ID
+pub async fn read(
+    pool: &deadpool_diesel::postgres::Pool,
+    if_id: i32,
+) -> Result<InterfaceModel, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            wg_if::table
+                .filter(wg_if::id.eq(if_id))
+                .select(Interface::as_select())
+                .get_result(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(adapt_interface_db_to_interface(res))
+}
 
+// Function to retrieve an interface from the database by its name
+pub async fn read_by_name(
+    pool: &deadpool_diesel::postgres::Pool,
+    if_name: String,
+) -> Result<InterfaceModel, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            wg_if::table
+                .filter(wg_if::name.eq(if_name))
+                .select(Interface::as_select())
+                .get_result(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(adapt_interface_db_to_interface(res))
+}
+
+// Function to retrieve all interfaces from the database
+pub async fn get_all(
+    pool: &deadpool_diesel::postgres::Pool,
+) -> Result<Vec<InterfaceModel>, InfraError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(|conn| {
+            wg_if::table
+                .order_by(wg_if::id)
+                .select(Interface::as_select())
+                .get_results(conn)
+        })
+        .await
+        .map_err(adapt_infra_error)?
+        .map_err(adapt_infra_error)?;
+    Ok(res.into_iter().map(adapt_interface_db_to_interface).collect())
+}
+
+// Function to update an interface. Its name and address only change while no peer depends on
+// them, the row is locked so none is added meanwhile.
+pub async fn update(
+    pool: &deadpool_diesel::postgres::Pool,
+    if_id: i32,
+    update_interface: UpdateInterfaceForm,
+) -> Result<InterfaceModel, InterfaceError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction::<_, InterfaceError, _>(|conn| {
+                let current = wg_if::table
+                    .filter(wg_if::id.eq(if_id))
+                    .select(Interface::as_select())
+                    .for_update()
+                    .get_result(conn)?;
+                if update_interface.is_empty() {
+                    return Ok(current);
+                }
+                let renamed = update_interface.name.as_ref().is_some_and(|name| *name != current.name);
+                let readdressed = update_interface
+                    .address
+                    .as_ref()
+                    .is_some_and(|address| *address != current.address);
+                if renamed || readdressed {
+                    let peers = count_peers(conn, if_id)?;
+                    if peers > 0 {
+                        return Err(InterfaceError::PeersPinned(peers));
+                    }
+                }
+                Ok(diesel::update(wg_if::table)
+                    .filter(wg_if::id.eq(if_id))
+                    .set(update_interface)
+                    .returning(Interface::as_returning())
+                    .get_result(conn)?)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)??;
+    Ok(adapt_interface_db_to_interface(res))
+}
+
+// Function to delete an interface, refused while peers reference it. The row is locked like
+// peer creation locks it, so no peer can be added between the count and the delete.
+pub async fn remove(
+    pool: &deadpool_diesel::postgres::Pool,
+    if_id: i32,
+) -> Result<i32, InterfaceError> {
+    let conn = pool.get().await.map_err(adapt_infra_error)?;
+    let res = conn
+        .interact(move |conn| {
+            conn.transaction::<_, InterfaceError, _>(|conn| {
+                wg_if::table
+                    .filter(wg_if::id.eq(if_id))
+                    .select(wg_if::id)
+                    .for_update()
+                    .get_result::<i32>(conn)?;
+                let peers = count_peers(conn, if_id)?;
+                if peers > 0 {
+                    return Err(InterfaceError::HasPeers(peers));
+                }
+                Ok(diesel::delete(wg_if::table)
+                    .filter(wg_if::id.eq(if_id))
+                    .returning(wg_if::id)
+                    .get_result(conn)?)
+            })
+        })
+        .await
+        .map_err(adapt_infra_error)??;
+    Ok(res)
+}
+
+// Number of peers attached to an interface
+fn count_peers(conn: &mut PgConnection, if_id: i32) -> QueryResult<i64> {
+    peers::table
+        .filter(peers::interface_id.eq(if_id))
+        .count()
+        .get_result(conn)
+}
+
+// Function to adapt a database representation of an interface to the application's domain model
+fn adapt_interface_db_to_interface(interface_db: Interface) -> InterfaceModel {
+    InterfaceModel {
+        id: interface_db.id,
+        name: interface_db.name,
+        pubkey: interface_db.pubkey,
+        privkey: interface_db.privkey,
+        address: interface_db.address,
+        port: interface_db.port,
+        mtu: interface_db.mtu,
+        fwmark: interface_db.fwmark,
+        // an update stores an empty string to remove a hook
+        post_up: interface_db.post_up.filter(|hook| !hook.is_empty()),
+        post_down: interface_db.post_down.filter(|hook| !hook.is_empty()),
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use axum::async_trait;
+use chrono::Utc;
+
+use crate::errors::{BaseError, Result};
+use crate::infra::wireguard::{WgBackend, WgInterfaceConfig, WgLinkConfig, WgPeerConfig};
+use crate::service::keys::public_key_from_private;
+use crate::service::wg_config::{parse_config, ConfigSection};
+use crate::service::wg_dump::{InterfaceDump, PeerDump, WgDump, ALL_INTERFACES};
+
+// traffic every peer exchanges between two `show` calls
+const RX_PER_SHOW: u64 = 64 * 1024;
+const TX_PER_SHOW: u64 = 16 * 1024;
+
+// In-memory stand-in for the kernel module, for running without root or a wg interface.
+// Interfaces come to life on `add_interface`, and every `show` simulates
+// a fresh handshake and some traffic for each configured peer.
+#[derive(Default)]
+pub struct FakeWg {
+    interfaces: Mutex<BTreeMap<String, FakeInterface>>,
+}
+
+struct FakeInterface {
+    dump: InterfaceDump,
+    peers: Vec<PeerDump>,
+}
+
+impl FakeInterface {
+    fn set_peer(&mut self, peer: &WgPeerConfig) {
+        // like the kernel, an allowed ip routes to a single peer of the interface
+        for other in self.peers.iter_mut() {
+            other.allowed_ips.retain(|ip| !peer.allowed_ips.contains(ip));
+        }
+        let index = match self.peers.iter().position(|p| p.public_key == peer.public_key) {
+            Some(index) => index,
+            None => {
+                self.peers.push(PeerDump {
+                    interface: self.dump.interface.clone(),
+                    public_key: peer.public_key.clone(),
+                    preshared_key: None,
+                    endpoint_addr: None,
+                    allowed_ips: vec![],
+                    last_handshake_at: None,
+                    transfer_rx: 0,
+                    transfer_tx: 0,
+                    persistent_keepalive: None,
+                });
+                self.peers.len() - 1
+            }
+        };
+        let entry = &mut self.peers[index];
+        entry.preshared_key.clone_from(&peer.preshared_key);
+        entry.endpoint_addr = peer.endpoint.or(entry.endpoint_addr);
+        entry.allowed_ips.clone_from(&peer.allowed_ips);
+        entry.persistent_keepalive = peer.persistent_keepalive;
+    }
+}
+
+#[async_trait]
+impl WgBackend for FakeWg {
+    async fn show(&self, interface: &str) -> Result<WgDump> {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        if interface != ALL_INTERFACES && !interfaces.contains_key(interface) {
+            return Err(no_such_device(interface));
+        }
+        let mut dump = WgDump::default();
+        for fake in interfaces
+            .values_mut()
+            .filter(|fake| interface == ALL_INTERFACES || fake.dump.interface == interface)
+        {
+            for peer in fake.peers.iter_mut() {
+                peer.last_handshake_at = Some(Utc::now());
+                peer.transfer_rx += RX_PER_SHOW;
+                peer.transfer_tx += TX_PER_SHOW;
+            }
+            dump.interfaces.push(fake.dump.clone());
+            dump.peers.extend(fake.peers.iter().cloned());
+        }
+        Ok(dump)
+    }
+
+    async fn set_peer(&self, interface: &str, peer: &WgPeerConfig) -> Result<()> {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let fake = interfaces.get_mut(interface).ok_or_else(|| no_such_device(interface))?;
+        fake.set_peer(peer);
+        Ok(())
+    }
+
+    async fn remove_peer(&self, interface: &str, public_key: &str) -> Result<()> {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let fake = interfaces.get_mut(interface).ok_or_else(|| no_such_device(interface))?;
+        fake.peers.retain(|peer| peer.public_key != public_key);
+        Ok(())
+    }
+
+    async fn add_interface(&self, interface: &str, _link: &WgLinkConfig) -> Result<()> {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        if interfaces.contains_key(interface) {
+            return Err(BaseError::Execution {
+                source: "ip link: RTNETLINK answers: File exists".to_string(),
+            });
+        }
+        // a new link has no keys and a random port until it is configured
+        let dump = InterfaceDump {
+            interface: interface.to_string(),
+            private_key: String::new(),
+            public_key: String::new(),
+            listen_port: 0,
+            fwmark: None,
+        };
+        interfaces.insert(interface.to_string(), FakeInterface { dump, peers: vec![] });
+        Ok(())
+    }
+
+    async fn set_interface(&self, interface: &str, config: &WgInterfaceConfig) -> Result<()> {
+        let public_key = public_key_from_private(&config.private_key).ok_or_else(invalid_key)?;
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let fake = interfaces.get_mut(interface).ok_or_else(|| no_such_device(interface))?;
+        fake.dump = InterfaceDump {
+            interface: interface.to_string(),
+            private_key: config.private_key.clone(),
+            public_key,
+            listen_port: config.listen_port,
+            fwmark: config.fwmark,
+        };
+        Ok(())
+    }
+
+    async fn syncconf(&self, interface: &str, config: &str) -> Result<()> {
+        let sections = parse_config(config)?;
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let fake = interfaces.get_mut(interface).ok_or_else(|| no_such_device(interface))?;
+        let mut peers = vec![];
+        for section in &sections {
+            match section.name.as_str() {
+                "Interface" => apply_interface_section(&mut fake.dump, section)?,
+                "Peer" => peers.push(peer_section(section)?),
+                other => return Err(config_error(format!("unknown section [{other}]"))),
+            }
+        }
+        // peers that stay keep their counters and handshake, the rest are dropped
+        fake.peers.retain(|existing| peers.iter().any(|p| p.public_key == existing.public_key));
+        for peer in &peers {
+            fake.set_peer(peer);
+        }
+        Ok(())
+    }
+}
+
+fn apply_interface_section(dump: &mut InterfaceDump, section: &ConfigSection) -> Result<()> {
+    if let Some(private_key) = section.get("PrivateKey") {
+        dump.public_key = public_key_from_private(private_key).ok_or_else(invalid_key)?;
+        dump.private_key = private_key.to_string();
+    }
+    if let Some(port) = section.get("ListenPort") {
+        dump.listen_port = parse_value("ListenPort", port)?;
+    }
+    if let Some(fwmark) = section.get("FwMark") {
+        dump.fwmark = match fwmark {
+            "off" | "0" => None,
+            fwmark => Some(parse_value("FwMark", fwmark)?),
+        };
+    }
+    Ok(())
+}
+
+fn peer_section(section: &ConfigSection) -> Result<WgPeerConfig> {
+    Ok(WgPeerConfig {
+        public_key: section
+            .get("PublicKey")
+            .ok_or_else(|| config_error("[Peer] without a PublicKey".to_string()))?
+            .to_string(),
+        preshared_key: section.get("PresharedKey").map(str::to_string),
+        endpoint: section
+            .get("Endpoint")
+            .map(|endpoint| parse_value("Endpoint", endpoint))
+            .transpose()?,
+        allowed_ips: section
+            .get("AllowedIPs")
+            .map(|ips| {
+                ips.split(',')
+                    .map(|ip| parse_value("AllowedIPs", ip.trim()))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        persistent_keepalive: match section.get("PersistentKeepalive") {
+            None | Some("off") | Some("0") => None,
+            Some(keepalive) => Some(parse_value("PersistentKeepalive", keepalive)?),
+        },
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| config_error(format!("invalid {key} `{value}`")))
+}
+
+fn config_error(message: String) -> BaseError {
+    BaseError::BodyParsingError(format!("wg syncconf: {message}"))
+}
+
+// the same messages wg prints for these cases
+fn no_such_device(interface: &str) -> BaseError {
+    BaseError::Execution {
+        source: format!("Unable to access interface {interface}: No such device"),
+    }
+}
+
+fn invalid_key() -> BaseError {
+    BaseError::Execution {
+        source: "Key is not the correct length or format".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::keys::WgKeyPair;
+
+    fn interface_config() -> WgInterfaceConfig {
+        WgInterfaceConfig {
+            private_key: WgKeyPair::generate().private_key,
+            listen_port: 51820,
+            fwmark: None,
+        }
+    }
+
+    fn link() -> WgLinkConfig {
+        WgLinkConfig {
+            addresses: vec!["10.8.0.1/24".parse().unwrap()],
+            mtu: None,
+        }
+    }
+
+    fn peer(public_key: &str, allowed_ips: &str) -> WgPeerConfig {
+        WgPeerConfig {
+            public_key: public_key.to_string(),
+            preshared_key: None,
+            endpoint: None,
+            allowed_ips: vec![allowed_ips.parse().unwrap()],
+            persistent_keepalive: Some(25),
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_lifecycle() {
+        let wg = FakeWg::default();
+        assert!(wg.show("wg0").await.is_err());
+        assert!(wg.set_peer("wg0", &peer("a=", "10.8.0.2/32")).await.is_err());
+
+        let config = interface_config();
+        assert!(wg.set_interface("wg0", &config).await.is_err());
+        wg.add_interface("wg0", &link()).await.unwrap();
+        assert!(wg.add_interface("wg0", &link()).await.is_err());
+        wg.set_interface("wg0", &config).await.unwrap();
+        wg.set_peer("wg0", &peer("a=", "10.8.0.2/32")).await.unwrap();
+        wg.set_peer("wg0", &peer("b=", "10.8.0.3/32")).await.unwrap();
+
+        let dump = wg.show("wg0").await.unwrap();
+        assert_eq!(
+            dump.interfaces[0].public_key,
+            public_key_from_private(&config.private_key).unwrap()
+        );
+        assert_eq!(dump.peers.len(), 2);
+        assert!(dump.peers[0].last_handshake_at.is_some());
+        assert_eq!(dump.peers[0].transfer_rx, RX_PER_SHOW);
+        assert_eq!(dump.peers[0].persistent_keepalive, Some(25));
+
+        // updating keeps the counters, and takes the address away from the other peer
+        wg.set_peer("wg0", &peer("a=", "10.8.0.3/32")).await.unwrap();
+        let dump = wg.show(ALL_INTERFACES).await.unwrap();
+        assert_eq!(dump.peers[0].transfer_tx, 2 * TX_PER_SHOW);
+        assert_eq!(dump.peers[0].allowed_ips, vec!["10.8.0.3/32".parse().unwrap()]);
+        assert!(dump.peers[1].allowed_ips.is_empty());
+
+        wg.remove_peer("wg0", "b=").await.unwrap();
+        assert_eq!(wg.show("wg0").await.unwrap().peers.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn syncconf_replaces_peers() {
+        let wg = FakeWg::default();
+        wg.add_interface("wg0", &link()).await.unwrap();
+        wg.set_interface("wg0", &interface_config()).await.unwrap();
+        wg.set_peer("wg0", &peer("a=", "10.8.0.2/32")).await.unwrap();
+        wg.set_peer("wg0", &peer("b=", "10.8.0.3/32")).await.unwrap();
+        wg.show("wg0").await.unwrap();
+
+        let config = "[Interface]\nListenPort = 51821\n\n\
+            [Peer]\nPublicKey = a=\nAllowedIPs = 10.8.0.2/32, fd00::2/128\n\n\
+            [Peer]\nPublicKey = c=\nAllowedIPs = 10.8.0.4/32\nPersistentKeepalive = off\n";
+        wg.syncconf("wg0", config).await.unwrap();
+
+        let dump = wg.show("wg0").await.unwrap();
+        assert_eq!(dump.interfaces[0].listen_port, 51821);
+        let keys: Vec<_> = dump.peers.iter().map(|p| p.public_key.as_str()).collect();
+        assert_eq!(keys, vec!["a=", "c="]);
+        assert_eq!(dump.peers[0].transfer_rx, 2 * RX_PER_SHOW);
+        assert_eq!(dump.peers[0].allowed_ips.len(), 2);
+        assert_eq!(dump.peers[1].transfer_rx, RX_PER_SHOW);
+        assert_eq!(dump.peers[1].persistent_keepalive, None);
+
+        assert!(wg.syncconf("wg0", "[Peer]\nAllowedIPs = 10.8.0.5/32\n").await.is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::async_trait;
+use ipnet::IpNet;
+
+use crate::errors::Result;
+use crate::service::wg_dump::WgDump;
+
+mod fake;
+mod sudo;
+
+pub use fake::FakeWg;
+pub use sudo::SudoWg;
+
+// Settings of a peer on an interface, what `wg set <interface> peer ...` takes
+#[derive(Debug, Clone, PartialEq)]
+pub struct WgPeerConfig {
+    pub public_key: String,
+    pub preshared_key: Option<String>,
+    pub endpoint: Option<SocketAddr>,
+    pub allowed_ips: Vec<IpNet>,
+    pub persistent_keepalive: Option<u16>,
+}
+
+// Settings of the interface itself, what `wg set <interface> ...` takes
+#[derive(Debug, Clone, PartialEq)]
+pub struct WgInterfaceConfig {
+    pub private_key: String,
+    pub listen_port: u16,
+    pub fwmark: Option<u32>,
+}
+
+// The link itself, what wg-quick creates before configuring WireGuard on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct WgLinkConfig {
+    pub addresses: Vec<IpNet>,
+    pub mtu: Option<u32>,
+}
+
+// Everything the app asks of the kernel's WireGuard, so it can be swapped for a fake in tests
+#[async_trait]
+pub trait WgBackend: Send + Sync {
+    // live state of `interface`, or of every interface for ALL_INTERFACES
+    async fn show(&self, interface: &str) -> Result<WgDump>;
+    // adds the peer, or updates it when the public key is already configured
+    async fn set_peer(&self, interface: &str, peer: &WgPeerConfig) -> Result<()>;
+    async fn remove_peer(&self, interface: &str, public_key: &str) -> Result<()>;
+    // creates the interface with its addresses and brings it up, without any WireGuard settings
+    async fn add_interface(&self, interface: &str, link: &WgLinkConfig) -> Result<()>;
+    // the interface has to exist, see add_interface
+    async fn set_interface(&self, interface: &str, config: &WgInterfaceConfig) -> Result<()>;
+    // replaces the configuration with `config` (wg format, no wg-quick keys),
+    // without dropping the sessions of peers that stay
+    async fn syncconf(&self, interface: &str, config: &str) -> Result<()>;
+}
+
+// `kind` is WG_BACKEND, "sudo" for the kernel or "fake" for the in-memory simulation
+pub fn new_backend(kind: &str) -> Arc<dyn WgBackend> {
+    match kind {
+        "sudo" => Arc::new(SudoWg),
+        "fake" => Arc::new(FakeWg::default()),
+        other => panic!("WG_BACKEND must be sudo or fake, got {other}"),
+    }
+}
@@ -0,0 +1,128 @@
+use axum::async_trait;
+use tracing::error;
+
+use crate::errors::{BaseError, Result};
+use crate::infra::wireguard::{WgBackend, WgInterfaceConfig, WgLinkConfig, WgPeerConfig};
+use crate::service::wg_dump::{parse_dump, WgDump};
+use crate::utils::{sudo_exec, sudo_exec_stdin};
+
+// Keys are handed to wg through stdin, never as arguments everyone can read from the process list
+const STDIN: &str = "/dev/stdin";
+// an empty key file removes the preshared key
+const NO_KEY: &str = "/dev/null";
+const OFF: &str = "off";
+
+// Drives the kernel module through `sudo wg ...`, and the links through `sudo ip ...`
+pub struct SudoWg;
+
+#[async_trait]
+impl WgBackend for SudoWg {
+    async fn show(&self, interface: &str) -> Result<WgDump> {
+        let output = wg(vec!["show", interface, "dump"], None).await?;
+        parse_dump(interface, &output)
+    }
+
+    async fn set_peer(&self, interface: &str, peer: &WgPeerConfig) -> Result<()> {
+        let allowed_ips = peer
+            .allowed_ips
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let keepalive = peer
+            .persistent_keepalive
+            .map_or(OFF.to_string(), |keepalive| keepalive.to_string());
+        let endpoint = peer.endpoint.map(|endpoint| endpoint.to_string());
+        let preshared_key = if peer.preshared_key.is_some() { STDIN } else { NO_KEY };
+
+        let mut args = vec![
+            "set", interface,
+            "peer", &peer.public_key,
+            "preshared-key", preshared_key,
+            "allowed-ips", &allowed_ips,
+            "persistent-keepalive", &keepalive,
+        ];
+        if let Some(endpoint) = &endpoint {
+            args.extend(["endpoint", endpoint]);
+        }
+        wg(args, peer.preshared_key.as_deref()).await.map(|_| ())
+    }
+
+    async fn remove_peer(&self, interface: &str, public_key: &str) -> Result<()> {
+        wg(vec!["set", interface, "peer", public_key, "remove"], None)
+            .await
+            .map(|_| ())
+    }
+
+    async fn add_interface(&self, interface: &str, link: &WgLinkConfig) -> Result<()> {
+        ip(vec!["link", "add", "dev", interface, "type", "wireguard"]).await?;
+        let configured = configure_link(interface, link).await;
+        if configured.is_err() {
+            // a half configured link would make the next attempt fail on `link add`
+            if let Err(err) = ip(vec!["link", "delete", "dev", interface]).await {
+                error!("can not delete the half configured interface {interface}: {err:?}");
+            }
+        }
+        configured
+    }
+
+    async fn set_interface(&self, interface: &str, config: &WgInterfaceConfig) -> Result<()> {
+        let listen_port = config.listen_port.to_string();
+        let fwmark = config.fwmark.map_or(OFF.to_string(), |fwmark| fwmark.to_string());
+        let args = vec![
+            "set", interface,
+            "private-key", STDIN,
+            "listen-port", &listen_port,
+            "fwmark", &fwmark,
+        ];
+        wg(args, Some(&config.private_key)).await.map(|_| ())
+    }
+
+    async fn syncconf(&self, interface: &str, config: &str) -> Result<()> {
+        wg(vec!["syncconf", interface, STDIN], Some(config))
+            .await
+            .map(|_| ())
+    }
+}
+
+async fn configure_link(interface: &str, link: &WgLinkConfig) -> Result<()> {
+    for address in &link.addresses {
+        ip(vec!["address", "add", &address.to_string(), "dev", interface]).await?;
+    }
+    let mtu = link.mtu.map(|mtu| mtu.to_string());
+    let mut args = vec!["link", "set"];
+    if let Some(mtu) = &mtu {
+        args.extend(["mtu", mtu]);
+    }
+    args.extend(["up", "dev", interface]);
+    ip(args).await
+}
+
+async fn wg(args: Vec<&str>, stdin: Option<&str>) -> Result<String> {
+    exec("wg", args, stdin).await
+}
+
+async fn ip(args: Vec<&str>) -> Result<()> {
+    exec("ip", args, None).await.map(|_| ())
+}
+
+// Runs `sudo <program> <args>`, turning a non zero exit into an error carrying its message
+async fn exec(program: &str, args: Vec<&str>, stdin: Option<&str>) -> Result<String> {
+    let subcommand = args[0];
+    let cmd = [vec![program], args.clone()].concat();
+    let output = match stdin {
+        Some(input) => sudo_exec_stdin(cmd, input).await?,
+        None => sudo_exec(cmd).await?,
+    };
+    if !output.status.success() {
+        return Err(BaseError::Execution {
+            source: format!(
+                "{program} {subcommand}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| {
+        BaseError::PeerDumpError(format!("{program} {subcommand} output is not valid utf-8"))
+    })
+}
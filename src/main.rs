@@ -1,17 +1,29 @@
 // #![feature(duration_constructors)]
 
+use std::sync::Arc;
 use std::time::Duration;
 use axum::Router;
 use deadpool_diesel::postgres::{Manager, Pool};
 use jsonwebtoken::{DecodingKey, EncodingKey};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::core::{get_config, run_migrations};
+use crate::core::{get_config, run_migrations, RevokedTokens};
+use crate::domain::models::peer::PresenceThresholds;
 use crate::errors::{internal_error, Result};
+use crate::handlers::metrics_router;
+use crate::infra::wireguard::new_backend;
 use crate::routes::v1_router;
+use crate::service::auth::{load_revoked, spawn_token_cleanup};
+use crate::service::expiry::spawn_expiry;
+use crate::service::live::{spawn_live, LiveHub};
+use crate::service::ownership::OwnershipPolicy;
+use crate::service::quota::QuotaPolicy;
+use crate::service::reconcile::reconcile_all;
+use crate::service::traffic::{spawn_rollups, TrafficRetention};
+use crate::service::usage::spawn_poller;
 use crate::utils::middlewares::mw_ctx::AppState;
 use tower_http::trace::{HttpMakeClassifier, TraceLayer};
-use tracing::info;
+use tracing::{info, warn};
 use tokio::net::TcpListener;
 use tower_http::timeout::TimeoutLayer;
 // Import modules
@@ -40,13 +52,54 @@ async fn main() -> Result<()> {
         pool,
         key_enc,
         key_dec,
+        wg: new_backend(config.wg_backend()),
+        live: Arc::new(LiveHub::default()),
+        presence: PresenceThresholds {
+            online: config.peer_online_within(),
+            idle: config.peer_idle_within(),
+        },
+        quota: Arc::new(QuotaPolicy {
+            billing_day: config.quota_billing_day(),
+            warn_percents: config.quota_warn_percents().to_vec(),
+        }),
+        revoked: Arc::new(RevokedTokens::default()),
+        ownership: Arc::new(OwnershipPolicy {
+            self_service: config.peer_self_service(),
+            max_peers_per_user: config.max_peers_per_user(),
+        }),
     };
 
+    init_tracing(config.log_level());
+    // revoked access tokens stay refused across restarts
+    if let Err(err) = load_revoked(&state).await {
+        warn!("can not load the revoked tokens: {err}");
+    }
+    if config.wg_reconcile_on_startup() {
+        reconcile_all(&state).await;
+    }
+    if let Some(every) = config.wg_poll_interval() {
+        spawn_poller(state.clone(), every);
+    }
+    spawn_live(state.clone(), config.wg_live_interval());
+    let retention = TrafficRetention {
+        samples: config.traffic_sample_retention(),
+        hourly: config.traffic_hourly_retention(),
+        daily: config.traffic_daily_retention(),
+    };
+    spawn_rollups(state.clone(), retention);
+    spawn_expiry(state.clone(), config.peer_expired_delete_after());
+    spawn_token_cleanup(state.clone());
+
+    // /metrics has no authentication, it only listens on the internal address
+    let metrics_listener = TcpListener::bind(config.metrics_bind()).await.unwrap();
+    info!("->> METRICS on http://{}/metrics", config.metrics_bind());
+    let metrics = metrics_router(state.clone());
+    tokio::spawn(async move { axum::serve(metrics_listener, metrics).await });
+
     let app = Router::new()
         .nest("/api", v1_router(state.clone(), config))
         .layer(TimeoutLayer::new(Duration::from_secs(config.timeout_secs())))
         .layer(create_trace_layer());
-    init_tracing();
     let listener = TcpListener::bind(config.bind()).await.unwrap();
     // let socket_listener = UnixListener::bind("/tmp/axum.sock").unwrap();
     // let incoming = UnixListenerStream::new(socket_listener);
@@ -59,10 +112,10 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn init_tracing() {
+fn init_tracing(log_level: &str) {
     tracing_subscriber::registry()
         .with(
-            tracing_subscriber::EnvFilter::try_from_env("LOG_LEVEL").unwrap_or_else(|_| {
+            tracing_subscriber::EnvFilter::try_new(log_level).unwrap_or_else(|_| {
                 // axum logs rejections from built-in extractors with the `axum::rejection`
                 // target, at `TRACE` level. `axum::rejection=trace` enables showing those events
                 "tracing_aka_logging=debug,tower_http=debug,axum::rejection=trace,diesel_async_postgres=debug".into()
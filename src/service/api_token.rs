@@ -0,0 +1,131 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use deadpool_diesel::postgres::Pool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::core::Claims;
+use crate::domain::models::user::ApiScope;
+use crate::errors::{internal_error, BaseError, Result};
+use crate::infra::api_token_repository::{self, ApiToken, NewApiToken};
+use crate::infra::errors::InfraError;
+use crate::service::auth;
+
+// Long-lived tokens for scripts and service accounts, sent as `Authorization: Bearer rfy_...`.
+// Only the SHA-256 of a token is stored, it is shown once when created. A token acts as its
+// user, with the role the user has at the time, and only where one of its scopes reaches.
+
+pub const TOKEN_PREFIX: &str = "rfy_";
+// what is kept of a token in clear, to tell the tokens of a user apart
+const SHOWN_LEN: usize = 12;
+pub const MAX_NAME_LEN: usize = 64;
+// last_used_at is written at most this often, not on every request
+const TOUCH_EVERY_SECONDS: i64 = 60;
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+// The new token, in clear, with what is stored of it
+pub async fn create(
+    pool: &Pool,
+    user_id: Uuid,
+    name: String,
+    scopes: Vec<ApiScope>,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<(String, ApiToken)> {
+    let token = format!("{TOKEN_PREFIX}{}", auth::generate());
+    let new_token = NewApiToken {
+        user_id,
+        name,
+        token_hash: auth::hash(&token),
+        prefix: token[..SHOWN_LEN].to_string(),
+        scopes: scopes.iter().map(|scope| scope.as_str().to_string()).collect(),
+        expires_at,
+    };
+    let stored = api_token_repository::create(pool, new_token)
+        .await
+        .map_err(internal_error)?;
+    Ok((token, stored))
+}
+
+// The claims and scopes of the caller presenting `token`
+pub async fn authenticate(pool: &Pool, token: &str) -> Result<(Claims, Vec<ApiScope>)> {
+    let now = Utc::now().naive_utc();
+    let (stored, user) = api_token_repository::read_by_hash(pool, auth::hash(token))
+        .await
+        .map_err(|err| match err {
+            InfraError::NotFound => BaseError::AuthFailApiTokenInvalid,
+            err => internal_error(err),
+        })?;
+    if stored.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(BaseError::AuthFailApiTokenInvalid);
+    }
+    if needs_touch(stored.last_used_at, now) {
+        // a token that works is not refused for the bookkeeping
+        if let Err(err) = api_token_repository::touch(pool, stored.id, now).await {
+            warn!("can not record the use of API token {}: {err}", stored.id);
+        }
+    }
+    let claims = Claims {
+        sub: user.id,
+        name: user.username,
+        // 0 for a token without expiry
+        exp: stored.expires_at.map_or(0, |expires_at| expires_at.and_utc().timestamp() as usize),
+        iat: stored.created_at.and_utc().timestamp() as usize,
+        jti: stored.id,
+        role: user.role,
+    };
+    Ok((claims, scopes(&stored.scopes)))
+}
+
+// Whether the token the claims were made from still stands: it was not deleted, has not
+// expired, and its user still has the role the claims carry
+pub async fn still_valid(pool: &Pool, claims: &Claims) -> Result<bool> {
+    let now = Utc::now().naive_utc();
+    match api_token_repository::read(pool, claims.jti).await {
+        Ok((stored, user)) => Ok(stored.expires_at.is_none_or(|expires_at| expires_at > now)
+            && user.role == claims.role),
+        Err(InfraError::NotFound) => Ok(false),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+// The known scopes of a stored token, one the code does not know grants nothing
+pub fn scopes(stored: &[String]) -> Vec<ApiScope> {
+    stored.iter().filter_map(|scope| ApiScope::parse(scope)).collect()
+}
+
+fn needs_touch(last_used_at: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+    last_used_at.is_none_or(|at| now - at >= TimeDelta::seconds(TOUCH_EVERY_SECONDS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_of_stored_tokens() {
+        let stored = ["peers:write", "users:admin", "interfaces:admin"].map(String::from);
+        let granted = scopes(&stored);
+        assert_eq!(granted, vec![ApiScope::PeersWrite, ApiScope::InterfacesAdmin]);
+        assert!(ApiScope::PeersWrite.grants(ApiScope::PeersRead));
+        assert!(!ApiScope::PeersRead.grants(ApiScope::PeersWrite));
+        assert!(!ApiScope::InterfacesAdmin.grants(ApiScope::PeersRead));
+    }
+
+    #[test]
+    fn last_use_is_recorded_once_a_minute() {
+        let now = Utc::now().naive_utc();
+        assert!(needs_touch(None, now));
+        assert!(!needs_touch(Some(now - TimeDelta::seconds(10)), now));
+        assert!(needs_touch(Some(now - TimeDelta::seconds(60)), now));
+    }
+
+    #[test]
+    fn tokens_are_recognized_by_their_prefix() {
+        let token = format!("{TOKEN_PREFIX}{}", auth::generate());
+        assert!(is_api_token(&token));
+        assert_eq!(token.len(), 47);
+        assert!(!is_api_token("eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.e30.sig"));
+    }
+}
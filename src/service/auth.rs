@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::core::{create_token, get_config, Claims, RevokedTokens};
+use crate::domain::models::user::UserModel;
+use crate::errors::{internal_error, BaseError, Result};
+use crate::infra::errors::InfraError;
+use crate::infra::token_repository::{self, NewRefreshToken, RefreshToken, RevokeScope, RevokedToken};
+use crate::infra::user_repository;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Short-lived access tokens plus rotating refresh tokens. A login starts a token family,
+// each refresh uses up its token and hands out the next one of the family. A used token
+// coming back means it leaked: the whole family is revoked, along with the access tokens
+// issued in it.
+
+// the refresh and the API tokens are random bytes, only their SHA-256 is stored
+const TOKEN_LEN: usize = 32;
+const CLEANUP_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    // lifetime of the access token, in seconds
+    pub expires_in: i64,
+}
+
+pub async fn login(state: &AppState, user: UserModel) -> Result<TokenPair> {
+    issue(state, user, Uuid::new_v4(), None).await
+}
+
+pub async fn refresh(state: &AppState, refresh_token: &str) -> Result<TokenPair> {
+    let now = Utc::now().naive_utc();
+    let token = token_repository::read_by_hash(&state.pool, hash(refresh_token))
+        .await
+        .map_err(|err| match err {
+            InfraError::NotFound => BaseError::AuthFailRefreshInvalid,
+            err => internal_error(err),
+        })?;
+    match check(&token, now) {
+        TokenState::Valid => {}
+        TokenState::Reused => {
+            warn!(
+                "refresh token of family {} reused, revoking the family of user {}",
+                token.family_id, token.user_id
+            );
+            revoke(state, RevokeScope::Family(token.family_id)).await?;
+            return Err(BaseError::AuthFailRefreshInvalid);
+        }
+        TokenState::Invalid => return Err(BaseError::AuthFailRefreshInvalid),
+    }
+    // read again, the new access token carries the current role
+    let user = user_repository::read(&state.pool, token.user_id)
+        .await
+        .map_err(|_| BaseError::AuthFailRefreshInvalid)?;
+    issue(state, user, token.family_id, Some(&token)).await
+}
+
+// Ends the session of the access token: its family can not be refreshed any more
+pub async fn logout(state: &AppState, claims: &Claims) -> Result<()> {
+    let family = token_repository::family_of(&state.pool, claims.jti)
+        .await
+        .map_err(internal_error)?;
+    let revoked = match family {
+        Some(family_id) => revoke(state, RevokeScope::Family(family_id)).await?,
+        None => vec![],
+    };
+    // an access token from before the family was stored, or past the access TTL
+    if !revoked.iter().any(|token| token.jti == claims.jti) {
+        let token = RevokedToken {
+            jti: claims.jti,
+            expires_at: expires_at(claims.exp),
+        };
+        token_repository::revoke_access(&state.pool, token.clone())
+            .await
+            .map_err(internal_error)?;
+        state.revoked.extend([(token.jti, token.expires_at)]);
+    }
+    Ok(())
+}
+
+// Ends every session of the user, on all devices
+pub async fn logout_all(state: &AppState, user_id: Uuid) -> Result<()> {
+    revoke(state, RevokeScope::User(user_id)).await.map(|_| ())
+}
+
+// Whether an access token still holds, for connections that outlive the request it came with:
+// logging out, a new role or its exp end it
+pub fn session_valid(revoked: &RevokedTokens, claims: &Claims, now: NaiveDateTime) -> bool {
+    !revoked.is_revoked(&claims.jti) && claims.exp as i64 > now.and_utc().timestamp()
+}
+
+// Loads the revocations made before a restart or by other instances, and drops the
+// tokens that expired
+pub fn spawn_token_cleanup(state: AppState) {
+    tokio::spawn(async move {
+        let mut ticker = interval(CLEANUP_EVERY);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = cleanup(&state).await {
+                warn!("token cleanup failed: {err}");
+            }
+        }
+    });
+}
+
+async fn cleanup(state: &AppState) -> core::result::Result<(), InfraError> {
+    let pruned = token_repository::prune(&state.pool, Utc::now().naive_utc()).await?;
+    debug!("token cleanup: {pruned} expired token(s) deleted");
+    load_revoked(state).await
+}
+
+pub async fn load_revoked(state: &AppState) -> core::result::Result<(), InfraError> {
+    let revoked = token_repository::revoked(&state.pool, Utc::now().naive_utc()).await?;
+    state.revoked.replace(revoked.into_iter().map(|token| (token.jti, token.expires_at)));
+    Ok(())
+}
+
+// A new access token with its refresh token, in the family of `used` when rotating it
+async fn issue(
+    state: &AppState,
+    user: UserModel,
+    family_id: Uuid,
+    used: Option<&RefreshToken>,
+) -> Result<TokenPair> {
+    let config = get_config().await;
+    let now = Utc::now().naive_utc();
+    let refresh_token = generate();
+    let access_jti = Uuid::new_v4();
+    let next = NewRefreshToken {
+        user_id: user.id,
+        family_id,
+        token_hash: hash(&refresh_token),
+        access_jti,
+        created_at: now,
+        expires_at: now + TimeDelta::days(config.refresh_token_days()),
+    };
+    match used {
+        Some(used) => {
+            let rotated = token_repository::rotate(&state.pool, used.id, next)
+                .await
+                .map_err(internal_error)?;
+            // another request used the token first, one of the two is not its owner
+            if rotated.is_none() {
+                warn!("refresh token of family {family_id} used concurrently, revoking the family");
+                revoke(state, RevokeScope::Family(family_id)).await?;
+                return Err(BaseError::AuthFailRefreshInvalid);
+            }
+        }
+        None => {
+            token_repository::create(&state.pool, next)
+                .await
+                .map_err(internal_error)?;
+        }
+    }
+    Ok(TokenPair {
+        access_token: create_token(user.id, user.username, user.role, access_jti, state.key_enc.clone()).await,
+        refresh_token,
+        expires_in: config.jwt_exp_minutes() * 60,
+    })
+}
+
+async fn revoke(state: &AppState, scope: RevokeScope) -> Result<Vec<RevokedToken>> {
+    let config = get_config().await;
+    let now = Utc::now().naive_utc();
+    let access_ttl = TimeDelta::minutes(config.jwt_exp_minutes());
+    let revoked = token_repository::revoke(&state.pool, scope, now, access_ttl)
+        .await
+        .map_err(internal_error)?;
+    state.revoked.extend(revoked.iter().map(|token| (token.jti, token.expires_at)));
+    Ok(revoked)
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenState {
+    Valid,
+    Reused,
+    Invalid,
+}
+
+fn check(token: &RefreshToken, now: NaiveDateTime) -> TokenState {
+    if token.revoked_at.is_some() || token.expires_at <= now {
+        TokenState::Invalid
+    } else if token.used_at.is_some() {
+        TokenState::Reused
+    } else {
+        TokenState::Valid
+    }
+}
+
+// Random bytes in URL-safe base64, for the refresh and the API tokens
+pub fn generate() -> String {
+    let mut bytes = [0u8; TOKEN_LEN];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// What is stored of a token, in hex
+pub fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn expires_at(exp: usize) -> NaiveDateTime {
+    chrono::DateTime::from_timestamp(exp as i64, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::user::Role;
+
+    fn token(used: bool, revoked: bool, expires_in: TimeDelta, now: NaiveDateTime) -> RefreshToken {
+        RefreshToken {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            family_id: Uuid::new_v4(),
+            token_hash: hash("token"),
+            access_jti: Uuid::new_v4(),
+            created_at: now - TimeDelta::minutes(5),
+            expires_at: now + expires_in,
+            used_at: used.then_some(now - TimeDelta::minutes(1)),
+            revoked_at: revoked.then_some(now - TimeDelta::minutes(1)),
+        }
+    }
+
+    #[test]
+    fn token_states() {
+        let now = Utc::now().naive_utc();
+        let day = TimeDelta::days(1);
+        assert_eq!(check(&token(false, false, day, now), now), TokenState::Valid);
+        assert_eq!(check(&token(true, false, day, now), now), TokenState::Reused);
+        assert_eq!(check(&token(false, true, day, now), now), TokenState::Invalid);
+        assert_eq!(check(&token(false, false, -day, now), now), TokenState::Invalid);
+        // a revoked family is not revoked again on reuse
+        assert_eq!(check(&token(true, true, day, now), now), TokenState::Invalid);
+    }
+
+    #[test]
+    fn sessions() {
+        let now = Utc::now().naive_utc();
+        let claims = Claims {
+            sub: Uuid::new_v4(),
+            name: "someone".to_string(),
+            exp: (now + TimeDelta::minutes(15)).and_utc().timestamp() as usize,
+            iat: now.and_utc().timestamp() as usize,
+            jti: Uuid::new_v4(),
+            role: Role::Viewer,
+        };
+        let revoked = RevokedTokens::default();
+        assert!(session_valid(&revoked, &claims, now));
+        assert!(!session_valid(&revoked, &claims, now + TimeDelta::minutes(15)));
+        revoked.extend([(claims.jti, now + TimeDelta::minutes(15))]);
+        assert!(!session_valid(&revoked, &claims, now));
+    }
+
+    #[test]
+    fn refresh_tokens() {
+        let token = generate();
+        assert_eq!(token.len(), 43);
+        assert_ne!(token, generate());
+        let hashed = hash(&token);
+        assert_eq!(hashed.len(), 64);
+        assert_eq!(hashed, hash(&token));
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
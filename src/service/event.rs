@@ -0,0 +1,47 @@
+use chrono::Utc;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::domain::models::peer::{PeerError, PeerModel};
+use crate::errors::{BaseError, Result};
+use crate::infra::event_repository::{self, NewPeerEvent, PeerEvent};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// What the server did to a peer on its own, and why. The events outlive the peer, so a
+// deleted peer can still be looked up.
+
+pub const EXPIRED: &str = "expired";
+pub const DELETED: &str = "deleted";
+pub const QUOTA_WARNING: &str = "quota_warning";
+pub const QUOTA_EXCEEDED: &str = "quota_exceeded";
+pub const QUOTA_RESET: &str = "quota_reset";
+// enabled again, by a new period or a larger quota
+pub const QUOTA_RESTORED: &str = "quota_restored";
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+// A lost event is only logged, the action it describes already happened
+pub async fn record(state: &AppState, peer: &PeerModel, kind: &str, detail: Option<String>) {
+    let event = NewPeerEvent {
+        peer_id: peer.id,
+        peer_name: peer.name.clone(),
+        kind: kind.to_string(),
+        detail,
+        created_at: Utc::now().naive_utc(),
+    };
+    if let Err(err) = event_repository::create(&state.pool, event).await {
+        warn!("can not record the {kind} event of peer {}: {err}", peer.id);
+    }
+}
+
+// The latest events of a peer, the newest first
+pub async fn list(state: &AppState, peer_id: Uuid, limit: Option<i64>) -> Result<Vec<PeerEvent>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(BaseError::BodyParsingError(format!("limit must be between 1 and {MAX_LIMIT}")));
+    }
+    event_repository::list(&state.pool, peer_id, limit)
+        .await
+        .map_err(|db_error| PeerError::InfraError(db_error).into())
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{info, warn};
+
+use crate::domain::models::peer::{PeerError, PeerModel};
+use crate::errors::Result;
+use crate::infra::peer_repository;
+use crate::service::event;
+use crate::service::peer::PeerService;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Temporary access. Peers past their expires_at are disabled, in the database and on the
+// interface, and deleted once `delete_after` passed as well when that is set. Both are
+// recorded as peer events.
+
+const CHECK_EVERY: Duration = Duration::from_secs(60);
+
+pub fn spawn_expiry(state: AppState, delete_after: Option<TimeDelta>) {
+    tokio::spawn(async move {
+        let mut ticker = interval(CHECK_EVERY);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = expire(&state, delete_after).await {
+                warn!("peer expiry failed: {err:?}");
+            }
+        }
+    });
+}
+
+async fn expire(state: &AppState, delete_after: Option<TimeDelta>) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    let expired = peer_repository::expired(&state.pool, now)
+        .await
+        .map_err(PeerError::InfraError)?;
+    let service = PeerService::new(state);
+    // one failing peer does not hold back the others
+    for peer in expired {
+        match expire_peer(&service, &peer, delete_after, now).await {
+            Ok(Some(kind)) => {
+                info!("peer {} ({}) is past its expires_at: {kind}", peer.id, peer.name);
+                let detail = peer.expires_at.map(|expires_at| format!("expired at {expires_at}"));
+                event::record(state, &peer, kind, detail).await;
+            }
+            Ok(None) => {}
+            Err(err) => warn!("can not expire peer {}: {err:?}", peer.id),
+        }
+    }
+    Ok(())
+}
+
+// The expires_at is checked again as the peer is disabled and as it is deleted, it may have
+// been extended since it was read. None when there was nothing left to do.
+async fn expire_peer(
+    service: &PeerService<'_>,
+    peer: &PeerModel,
+    delete_after: Option<TimeDelta>,
+    now: NaiveDateTime,
+) -> Result<Option<&'static str>> {
+    // off the interface first, a disabled peer is only in the database
+    if peer.enabled && service.disable_expired(peer.id, now).await?.is_none() {
+        return Ok(None);
+    }
+    match delete_after.filter(|_| is_due_for_deletion(peer, delete_after, now)) {
+        Some(delete_after) => {
+            let removed = peer_repository::remove_expired(service.pool, peer.id, now - delete_after)
+                .await
+                .map_err(PeerError::InfraError)?;
+            Ok(removed.map(|_| event::DELETED))
+        }
+        None => Ok(peer.enabled.then_some(event::EXPIRED)),
+    }
+}
+
+fn is_due_for_deletion(peer: &PeerModel, delete_after: Option<TimeDelta>, now: NaiveDateTime) -> bool {
+    match (peer.expires_at, delete_after) {
+        (Some(expires_at), Some(delete_after)) => expires_at + delete_after <= now,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::infra::peer_repository::{NewPeerForm, UpdatePeerForm};
+    use crate::service::peer::tests::{interface, live_keys, new_peer, rename};
+
+    fn at(seconds: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(1_720_000_000 + seconds, 0).unwrap().naive_utc()
+    }
+
+    #[test]
+    fn deleted_after_the_grace_period() {
+        let peer = PeerModel {
+            enabled: false,
+            expires_at: Some(at(0)),
+            ..PeerModel::for_tests()
+        };
+        let grace = Some(TimeDelta::hours(24));
+        assert!(!is_due_for_deletion(&peer, grace, at(3600)));
+        assert!(is_due_for_deletion(&peer, grace, at(24 * 3600)));
+        // kept, disabled, when no grace period is set
+        assert!(!is_due_for_deletion(&peer, None, at(365 * 24 * 3600)));
+    }
+
+    // extended by an operator between the expiry reading and changing them
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database in TEST_DATABASE_URL"]
+    async fn leaves_extended_peers_alone() {
+        let state = AppState::migrated().await;
+        let interface = interface(&state, true).await;
+        let service = PeerService::new(&state);
+        let now = Utc::now().naive_utc();
+        let grace = Some(TimeDelta::hours(24));
+        let expired = |name, days| NewPeerForm {
+            expires_at: Some(now - TimeDelta::days(days)),
+            ..new_peer(&interface, name)
+        };
+        let extend = || UpdatePeerForm {
+            expires_at: Some(Some(now + TimeDelta::days(30))),
+            ..rename("extended")
+        };
+
+        let enabled = service.create(expired("extended", 0), None, None).await.unwrap();
+        service.update(enabled.id, extend(), None).await.unwrap();
+        assert_eq!(expire_peer(&service, &enabled, grace, now).await.unwrap(), None);
+        assert!(peer_repository::read(&state.pool, enabled.id).await.unwrap().enabled);
+        assert_eq!(live_keys(&state, &interface).await, vec![enabled.public_key.clone()]);
+
+        let disabled = service.create(expired("extended", 2), None, None).await.unwrap();
+        let disabled = service.set_enabled(disabled.id, false).await.unwrap();
+        service.update(disabled.id, extend(), None).await.unwrap();
+        assert_eq!(expire_peer(&service, &disabled, grace, now).await.unwrap(), None);
+        assert!(peer_repository::read(&state.pool, disabled.id).await.is_ok());
+
+        // and still expires the others
+        let due = service.create(expired("due", 2), None, None).await.unwrap();
+        assert_eq!(expire_peer(&service, &due, grace, now).await.unwrap(), Some(event::DELETED));
+        assert!(peer_repository::read(&state.pool, due.id).await.is_err());
+        assert_eq!(live_keys(&state, &interface).await, vec![enabled.public_key]);
+    }
+}
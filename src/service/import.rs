@@ -0,0 +1,366 @@
+use std::collections::HashSet;
+
+use ipnet::IpNet;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::domain::models::interface::{
+    validate_address, validate_fwmark, validate_hook, validate_mtu, validate_name,
+    validate_port, InterfaceError, InterfaceModel,
+};
+use crate::domain::models::peer::PeerError;
+use crate::errors::{BaseError, Result};
+use crate::infra::errors::InfraError;
+use crate::infra::peer_repository::{self, NewPeerForm};
+use crate::infra::wg_if_repository::{self, NewInterfaceForm};
+use crate::service::keys::public_key_from_private;
+use crate::service::peer::apply_error_source;
+use crate::service::wg_config::{parse_config, ConfigSection};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Adopts interfaces and peers configured before this service, from the live interface or
+// from a wg-quick config file. Existing rows are never overwritten, clashes are reported.
+
+// An interface as found on the host, with the settings wg_if needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedInterface {
+    pub name: String,
+    pub private_key: String,
+    pub address: String,
+    pub port: i32,
+    pub mtu: Option<i32>,
+    pub fwmark: Option<i32>,
+    pub post_up: Option<String>,
+    pub post_down: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedPeer {
+    pub name: Option<String>,
+    pub public_key: String,
+    pub preshared_key: Option<String>,
+    pub allowed_ips: Vec<IpNet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportReport {
+    pub interface_id: Option<i32>,
+    pub interface: String,
+    // false when the interface was already stored with the same key
+    pub interface_created: bool,
+    pub imported: Vec<ImportedPeerRef>,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportedPeerRef {
+    pub peer_id: Uuid,
+    pub name: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportConflict {
+    // public key of the peer, or the interface name
+    pub target: String,
+    pub reason: String,
+}
+
+// Reads the interface as the kernel runs it. The dump does not carry the tunnel address,
+// so the caller provides it.
+pub async fn from_live(state: &AppState, name: &str, address: &str) -> Result<(ImportedInterface, Vec<ImportedPeer>)> {
+    validate_name(name)?;
+    let dump = state.wg.show(name).await.map_err(|err| {
+        InterfaceError::Invalid(format!("can not read interface {name}: {}", apply_error_source(err)))
+    })?;
+    let live = dump
+        .interfaces
+        .first()
+        .ok_or_else(|| InterfaceError::Invalid(format!("interface {name} not found in the dump")))?;
+    let interface = ImportedInterface {
+        name: name.to_string(),
+        private_key: live.private_key.clone(),
+        address: address.to_string(),
+        port: live.listen_port as i32,
+        mtu: None,
+        fwmark: live.fwmark.map(|fwmark| fwmark as i32),
+        post_up: None,
+        post_down: None,
+    };
+    let peers = dump
+        .peers
+        .into_iter()
+        .map(|peer| ImportedPeer {
+            name: None,
+            public_key: peer.public_key,
+            preshared_key: peer.preshared_key,
+            allowed_ips: peer.allowed_ips,
+        })
+        .collect();
+    Ok((interface, peers))
+}
+
+// Reads a server side wg-quick file such as /etc/wireguard/wg0.conf, the comment above each
+// [Peer] names it
+pub fn from_config(name: &str, text: &str) -> Result<(ImportedInterface, Vec<ImportedPeer>)> {
+    validate_name(name)?;
+    let sections = parse_config(text)?;
+    let mut interfaces = sections.iter().filter(|s| s.name.eq_ignore_ascii_case("Interface"));
+    let (Some(section), None) = (interfaces.next(), interfaces.next()) else {
+        return Err(invalid("the config must have exactly one [Interface] section"));
+    };
+    let interface = ImportedInterface {
+        name: name.to_string(),
+        private_key: required(section, "PrivateKey")?.to_string(),
+        address: required(section, "Address")?.to_string(),
+        port: parse_value(section, "ListenPort")?
+            .ok_or_else(|| invalid("[Interface] has no ListenPort"))?,
+        mtu: parse_value(section, "MTU")?,
+        fwmark: match section.get("FwMark") {
+            None | Some("off") => None,
+            Some(fwmark) => Some(parse_fwmark(fwmark)?),
+        },
+        post_up: hooks(section, "PostUp"),
+        post_down: hooks(section, "PostDown"),
+    };
+
+    let peers = sections
+        .iter()
+        .filter(|s| s.name.eq_ignore_ascii_case("Peer"))
+        .map(|section| {
+            let allowed_ips = section
+                .get("AllowedIPs")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|ip| !ip.is_empty())
+                .map(|ip| ip.parse().map_err(|_| invalid(&format!("invalid AllowedIPs `{ip}`"))))
+                .collect::<Result<Vec<IpNet>>>()?;
+            Ok(ImportedPeer {
+                name: section.comment.clone(),
+                public_key: required(section, "PublicKey")?.to_string(),
+                preshared_key: section.get("PresharedKey").map(str::to_string),
+                allowed_ips,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((interface, peers))
+}
+
+// Stores what is missing, leaving existing interfaces and peers untouched
+pub async fn import(
+    state: &AppState,
+    interface: ImportedInterface,
+    peers: Vec<ImportedPeer>,
+) -> Result<ImportReport> {
+    validate_address(&interface.address)
+        .and(validate_port(interface.port))
+        .and(validate_mtu(interface.mtu))
+        .and(validate_fwmark(interface.fwmark))
+        .and(validate_hook(interface.post_up.as_deref()))
+        .and(validate_hook(interface.post_down.as_deref()))?;
+    let public_key = public_key_from_private(&interface.private_key)
+        .ok_or_else(|| invalid("the interface private key is not a valid WireGuard key"))?;
+
+    let mut report = ImportReport {
+        interface_id: None,
+        interface: interface.name.clone(),
+        interface_created: false,
+        imported: vec![],
+        conflicts: vec![],
+    };
+
+    let stored = match wg_if_repository::read_by_name(&state.pool, interface.name.clone()).await {
+        Ok(stored) if stored.pubkey == public_key => stored,
+        Ok(_) => {
+            // the peers were set up against another key, they do not belong to the stored interface
+            report.conflicts.push(ImportConflict {
+                target: interface.name.clone(),
+                reason: "an interface with this name is already stored with a different key, nothing was imported".to_string(),
+            });
+            return Ok(report);
+        }
+        Err(InfraError::NotFound) => {
+            report.interface_created = true;
+            create_interface(state, interface, public_key).await?
+        }
+        Err(db_error) => return Err(InterfaceError::InfraError(db_error).into()),
+    };
+    report.interface_id = Some(stored.id);
+
+    let public_keys = peers.iter().map(|peer| peer.public_key.clone()).collect();
+    let known: HashSet<String> = peer_repository::get_by_public_keys(&state.pool, public_keys)
+        .await
+        .map_err(PeerError::InfraError)?
+        .into_iter()
+        .map(|peer| peer.public_key)
+        .collect();
+
+    for peer in peers {
+        if known.contains(&peer.public_key) {
+            report.conflicts.push(ImportConflict {
+                target: peer.public_key,
+                reason: "a peer with this public key is already stored".to_string(),
+            });
+            continue;
+        }
+        if peer.allowed_ips.is_empty() {
+            report.conflicts.push(ImportConflict {
+                target: peer.public_key,
+                reason: "the peer has no allowed ips to use as its address".to_string(),
+            });
+            continue;
+        }
+        match import_peer(state, &stored, &peer).await {
+            Ok(imported) => report.imported.push(imported),
+            Err(reason) => report.conflicts.push(ImportConflict {
+                target: peer.public_key,
+                reason,
+            }),
+        }
+    }
+    Ok(report)
+}
+
+async fn create_interface(
+    state: &AppState,
+    interface: ImportedInterface,
+    public_key: String,
+) -> Result<InterfaceModel> {
+    let name = interface.name.clone();
+    let new_interface = NewInterfaceForm {
+        name: interface.name,
+        pubkey: public_key,
+        privkey: interface.private_key,
+        address: interface.address,
+        port: interface.port,
+        mtu: interface.mtu,
+        fwmark: interface.fwmark,
+        post_up: interface.post_up,
+        post_down: interface.post_down,
+    };
+    wg_if_repository::create(&state.pool, new_interface)
+        .await
+        .map_err(|db_error| match db_error {
+            InfraError::UniqueViolation => InterfaceError::NameExists(name).into(),
+            _ => InterfaceError::InfraError(db_error).into(),
+        })
+}
+
+// The allowed ips of the server side of a peer are its tunnel addresses
+async fn import_peer(
+    state: &AppState,
+    interface: &InterfaceModel,
+    peer: &ImportedPeer,
+) -> std::result::Result<ImportedPeerRef, String> {
+    let address = peer
+        .allowed_ips
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let new_peer = NewPeerForm {
+        name: peer
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("imported-{}", peer.public_key.chars().take(8).collect::<String>())),
+        persistent_keepalive: None,
+        allowed_ips: None,
+        preshared_key: peer.preshared_key.clone(),
+        private_key: None,
+        public_key: peer.public_key.clone(),
+        if_pubkey: interface.pubkey.clone(),
+        address,
+        interface_id: interface.id,
+        expires_at: None,
+        quota_bytes: None,
+        quota_period: None,
+        quota_period_start: None,
+        owner_id: None,
+    };
+    peer_repository::import(&state.pool, new_peer)
+        .await
+        .map(|stored| ImportedPeerRef {
+            peer_id: stored.id,
+            name: stored.name,
+            public_key: stored.public_key,
+        })
+        .map_err(|err| err.to_string())
+}
+
+fn required<'a>(section: &'a ConfigSection, key: &str) -> Result<&'a str> {
+    section
+        .get(key)
+        .ok_or_else(|| invalid(&format!("[{}] has no {key}", section.name)))
+}
+
+fn parse_value<T: std::str::FromStr>(section: &ConfigSection, key: &str) -> Result<Option<T>> {
+    section
+        .get(key)
+        .map(|value| value.parse().map_err(|_| invalid(&format!("invalid {key} `{value}`"))))
+        .transpose()
+}
+
+// wg-quick runs repeated hooks in order, as one shell line they keep doing so
+fn hooks(section: &ConfigSection, key: &str) -> Option<String> {
+    let hooks = section.get_all(key);
+    (!hooks.is_empty()).then(|| hooks.join("; "))
+}
+
+fn parse_fwmark(value: &str) -> Result<i32> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| invalid(&format!("invalid FwMark `{value}`")))
+}
+
+fn invalid(reason: &str) -> BaseError {
+    InterfaceError::Invalid(reason.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WG0_CONF: &str = "[Interface]\n\
+        Address = 10.8.0.1/24, fd00::1/64\n\
+        ListenPort = 51820\n\
+        PrivateKey = dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=\n\
+        PostUp = iptables -A FORWARD -i %i -j ACCEPT\n\
+        PostUp = sysctl -q net.ipv4.ip_forward=1\n\
+        \n\
+        ### Client alice\n\
+        [Peer]\n\
+        PublicKey = alice=\n\
+        PresharedKey = psk=\n\
+        AllowedIPs = 10.8.0.2/32, fd00::2/128\n\
+        \n\
+        [Peer]\n\
+        PublicKey = bob=\n\
+        AllowedIPs = 10.8.0.3/32\n";
+
+    #[test]
+    fn server_config() {
+        let (interface, peers) = from_config("wg0", WG0_CONF).unwrap();
+        assert_eq!(interface.name, "wg0");
+        assert_eq!(interface.address, "10.8.0.1/24, fd00::1/64");
+        assert_eq!(interface.port, 51820);
+        assert_eq!(interface.fwmark, None);
+        assert_eq!(interface.post_up.as_deref(), Some("iptables -A FORWARD -i %i -j ACCEPT; sysctl -q net.ipv4.ip_forward=1"));
+        assert_eq!(interface.post_down, None);
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].name.as_deref(), Some("Client alice"));
+        assert_eq!(peers[0].preshared_key.as_deref(), Some("psk="));
+        assert_eq!(peers[0].allowed_ips.len(), 2);
+        assert_eq!(peers[1].name, None);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert!(from_config("wg0", "[Peer]\nPublicKey = a=\n").is_err());
+        assert!(from_config("wg0", &WG0_CONF.replace("ListenPort = 51820\n", "")).is_err());
+        assert!(from_config("wg0", &WG0_CONF.replace("10.8.0.3/32", "10.8.0.3/99")).is_err());
+        assert!(from_config("not a name", WG0_CONF).is_err());
+    }
+}
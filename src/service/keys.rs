@@ -0,0 +1,81 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+// WireGuard keys are 32 bytes, exchanged as standard base64 (44 chars with padding)
+const KEY_LEN: usize = 32;
+
+#[derive(Clone, Debug)]
+pub struct WgKeyPair {
+    pub private_key: String,
+    pub public_key: String,
+}
+
+impl WgKeyPair {
+    // Same as `wg genkey | tee private | wg pubkey`, without leaving the process
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut bytes);
+        // clamp the scalar the way `wg genkey` does, so the stored key is byte-identical
+        bytes[0] &= 248;
+        bytes[31] &= 127;
+        bytes[31] |= 64;
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+        Self {
+            private_key: STANDARD.encode(secret.to_bytes()),
+            public_key: STANDARD.encode(public.as_bytes()),
+        }
+    }
+}
+
+// Same as `wg genpsk`
+pub fn generate_preshared_key() -> String {
+    let mut bytes = [0u8; KEY_LEN];
+    OsRng.fill_bytes(&mut bytes);
+    STANDARD.encode(bytes)
+}
+
+// Same as `wg pubkey`, None when `private_key` is not a base64 encoded 32 byte key
+pub fn public_key_from_private(private_key: &str) -> Option<String> {
+    let bytes: [u8; KEY_LEN] = STANDARD.decode(private_key).ok()?.try_into().ok()?;
+    let public = PublicKey::from(&StaticSecret::from(bytes));
+    Some(STANDARD.encode(public.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(key: &str) -> [u8; KEY_LEN] {
+        STANDARD.decode(key).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn generated_keypair_is_consistent() {
+        let pair = WgKeyPair::generate();
+        assert_eq!(pair.private_key.len(), 44);
+        assert_eq!(pair.public_key.len(), 44);
+        let private = decode(&pair.private_key);
+        // clamped like `wg genkey`
+        assert_eq!(private[0] & 7, 0);
+        assert_eq!(private[31] & 0xC0, 0x40);
+        let public = PublicKey::from(&StaticSecret::from(private));
+        assert_eq!(STANDARD.encode(public.as_bytes()), pair.public_key);
+        assert_ne!(pair.public_key, WgKeyPair::generate().public_key);
+    }
+
+    #[test]
+    fn public_key_of_rfc7748_vector() {
+        assert_eq!(
+            public_key_from_private("dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo=").as_deref(),
+            Some("hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo=")
+        );
+        assert_eq!(public_key_from_private("not a key"), None);
+    }
+
+    #[test]
+    fn preshared_key_is_32_bytes() {
+        assert_eq!(decode(&generate_preshared_key()).len(), KEY_LEN);
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::domain::models::peer::{PeerError, PeerModel, PeerPresence, PresenceThresholds};
+use crate::errors::Result;
+use crate::infra::peer_repository;
+use crate::service::wg_dump::{PeerDump, WgDump, ALL_INTERFACES};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Live status of the peers for the dashboards. One poller reads the interfaces while anyone
+// is subscribed and fans out what changed since its previous read, every subscriber starts
+// from a snapshot of all peers.
+
+// events a subscriber may fall behind by before it is resynced with a snapshot
+const CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerStatus {
+    pub interface: String,
+    pub public_key: String,
+    // None for peers on the interface that are not in the database
+    pub peer_id: Option<Uuid>,
+    pub name: Option<String>,
+    pub online: bool,
+    pub status: PeerPresence,
+    pub last_handshake_at: Option<DateTime<Utc>>,
+    pub endpoint_addr: Option<SocketAddr>,
+    pub transfer_rx: u64,
+    pub transfer_tx: u64,
+    // bytes per second since the previous read
+    pub rx_rate: f64,
+    pub tx_rate: f64,
+    // decides who is sent the peer, see Subscription
+    #[serde(skip)]
+    pub owner_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerChange {
+    Added,
+    Handshake,
+    Endpoint,
+    Rates,
+    Online,
+    Offline,
+    // any other change of the status, e.g. from idle to offline
+    Status,
+    // handed over to another user
+    Owner,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Snapshot { peers: Vec<PeerStatus> },
+    Peer { changes: Vec<PeerChange>, peer: PeerStatus },
+    Removed { interface: String, public_key: String },
+}
+
+impl LiveEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Snapshot { .. } => "snapshot",
+            LiveEvent::Peer { .. } => "peer",
+            LiveEvent::Removed { .. } => "removed",
+        }
+    }
+
+    // The part of the event a subscriber of `interface` sees, None when it is all elsewhere
+    fn on_interface(self, interface: Option<&str>) -> Option<LiveEvent> {
+        let Some(interface) = interface else {
+            return Some(self);
+        };
+        match self {
+            LiveEvent::Snapshot { peers } => Some(LiveEvent::Snapshot {
+                peers: peers.into_iter().filter(|peer| peer.interface == interface).collect(),
+            }),
+            LiveEvent::Peer { ref peer, .. } if peer.interface != interface => None,
+            LiveEvent::Removed { interface: ref name, .. } if name != interface => None,
+            event => Some(event),
+        }
+    }
+
+    // The part of the event the owner of the peers in `seen` sees. A peer handed over to
+    // them is added, one handed over to somebody else is removed.
+    fn for_owner(self, owner: Uuid, seen: &mut HashSet<(String, String)>) -> Option<LiveEvent> {
+        match self {
+            LiveEvent::Snapshot { peers } => {
+                let peers: Vec<_> = peers.into_iter().filter(|peer| peer.owner_id == Some(owner)).collect();
+                *seen = peers
+                    .iter()
+                    .map(|peer| (peer.interface.clone(), peer.public_key.clone()))
+                    .collect();
+                Some(LiveEvent::Snapshot { peers })
+            }
+            LiveEvent::Peer { changes, peer } => {
+                let key = (peer.interface.clone(), peer.public_key.clone());
+                if peer.owner_id == Some(owner) {
+                    let changes = if seen.insert(key) { vec![PeerChange::Added] } else { changes };
+                    Some(LiveEvent::Peer { changes, peer })
+                } else if seen.remove(&key) {
+                    Some(LiveEvent::Removed { interface: key.0, public_key: key.1 })
+                } else {
+                    None
+                }
+            }
+            LiveEvent::Removed { interface, public_key } => {
+                let key = (interface, public_key);
+                seen.remove(&key)
+                    .then_some(LiveEvent::Removed { interface: key.0, public_key: key.1 })
+            }
+        }
+    }
+}
+
+struct Snapshot {
+    taken_at: Instant,
+    peers: Vec<PeerStatus>,
+}
+
+pub struct LiveHub {
+    events: broadcast::Sender<LiveEvent>,
+    // the last read, None while nobody listens
+    latest: Mutex<Option<Snapshot>>,
+}
+
+impl Default for LiveHub {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(CAPACITY);
+        Self { events, latest: Mutex::new(None) }
+    }
+}
+
+impl LiveHub {
+    // Events of every interface, or only of `interface`, about the peers of `owner` or of everybody
+    pub fn subscribe(self: &Arc<Self>, interface: Option<String>, owner: Option<Uuid>) -> Subscription {
+        // under the lock, so no event is published between the snapshot and the receiver
+        let latest = self.latest.lock().unwrap();
+        Subscription {
+            hub: self.clone(),
+            events: self.events.subscribe(),
+            pending: latest.as_ref().map(|snapshot| LiveEvent::Snapshot { peers: snapshot.peers.clone() }),
+            interface,
+            owner,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn publish(
+        &self,
+        dump: &WgDump,
+        known: &HashMap<&str, &PeerModel>,
+        presence: PresenceThresholds,
+        taken_at: Instant,
+    ) {
+        let mut latest = self.latest.lock().unwrap();
+        let peers = statuses(dump, known, presence, latest.as_ref(), Utc::now(), taken_at);
+        let events = match latest.as_ref() {
+            // the first read after an idle period, subscribers so far got no snapshot
+            None => vec![LiveEvent::Snapshot { peers: peers.clone() }],
+            Some(previous) => diff(&previous.peers, &peers),
+        };
+        for event in events {
+            // fails only when the last subscriber just left
+            let _ = self.events.send(event);
+        }
+        *latest = Some(Snapshot { taken_at, peers });
+    }
+}
+
+pub struct Subscription {
+    hub: Arc<LiveHub>,
+    events: broadcast::Receiver<LiveEvent>,
+    pending: Option<LiveEvent>,
+    interface: Option<String>,
+    owner: Option<Uuid>,
+    // the peers of `owner` the subscriber was told about
+    seen: HashSet<(String, String)>,
+}
+
+impl Subscription {
+    pub async fn next(&mut self) -> Option<LiveEvent> {
+        loop {
+            let event = match self.pending.take() {
+                Some(event) => event,
+                None => match self.events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("live subscriber fell {skipped} event(s) behind, resyncing");
+                        let interface = self.interface.take();
+                        *self = self.hub.subscribe(interface, self.owner);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            let Some(event) = event.on_interface(self.interface.as_deref()) else {
+                continue;
+            };
+            match self.owner {
+                Some(owner) => {
+                    if let Some(event) = event.for_owner(owner, &mut self.seen) {
+                        return Some(event);
+                    }
+                }
+                None => return Some(event),
+            }
+        }
+    }
+}
+
+pub fn spawn_live(state: AppState, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = interval(every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if state.live.events.receiver_count() == 0 {
+                // rates and changes are relative to the previous read, start over
+                state.live.latest.lock().unwrap().take();
+                continue;
+            }
+            if let Err(err) = refresh(&state).await {
+                warn!("live status poll failed: {err:?}");
+            }
+        }
+    });
+}
+
+async fn refresh(state: &AppState) -> Result<()> {
+    let dump = state.wg.show(ALL_INTERFACES).await?;
+    let taken_at = Instant::now();
+    let public_keys = dump.peers.iter().map(|peer| peer.public_key.clone()).collect();
+    let stored = peer_repository::get_by_public_keys(&state.pool, public_keys)
+        .await
+        .map_err(PeerError::InfraError)?;
+    let known = stored.iter().map(|peer| (peer.public_key.as_str(), peer)).collect();
+    state.live.publish(&dump, &known, state.presence, taken_at);
+    Ok(())
+}
+
+fn statuses(
+    dump: &WgDump,
+    known: &HashMap<&str, &PeerModel>,
+    presence: PresenceThresholds,
+    previous: Option<&Snapshot>,
+    now: DateTime<Utc>,
+    taken_at: Instant,
+) -> Vec<PeerStatus> {
+    let (before, elapsed): (HashMap<_, _>, f64) = match previous {
+        Some(previous) => (
+            previous
+                .peers
+                .iter()
+                .map(|peer| ((peer.interface.as_str(), peer.public_key.as_str()), peer))
+                .collect(),
+            (taken_at - previous.taken_at).as_secs_f64(),
+        ),
+        None => (HashMap::new(), 0.0),
+    };
+    dump.peers
+        .iter()
+        .map(|peer| {
+            let before = before.get(&(peer.interface.as_str(), peer.public_key.as_str())).copied();
+            let stored = known.get(peer.public_key.as_str()).copied();
+            status(peer, stored, presence, before, elapsed, now)
+        })
+        .collect()
+}
+
+fn status(
+    peer: &PeerDump,
+    stored: Option<&PeerModel>,
+    presence: PresenceThresholds,
+    before: Option<&PeerStatus>,
+    elapsed: f64,
+    now: DateTime<Utc>,
+) -> PeerStatus {
+    let (rx_rate, tx_rate) = match before {
+        Some(before) if elapsed > 0.0 => (
+            rate(before.transfer_rx, peer.transfer_rx, elapsed),
+            rate(before.transfer_tx, peer.transfer_tx, elapsed),
+        ),
+        _ => (0.0, 0.0),
+    };
+    let status = peer.presence(presence, now);
+    PeerStatus {
+        interface: peer.interface.clone(),
+        public_key: peer.public_key.clone(),
+        peer_id: stored.map(|stored| stored.id),
+        name: stored.map(|stored| stored.name.clone()),
+        online: status == PeerPresence::Online,
+        status,
+        last_handshake_at: peer.last_handshake_at,
+        endpoint_addr: peer.endpoint_addr,
+        transfer_rx: peer.transfer_rx,
+        transfer_tx: peer.transfer_tx,
+        rx_rate,
+        tx_rate,
+        owner_id: stored.and_then(|stored| stored.owner_id),
+    }
+}
+
+// a counter below the previous reading restarted from zero in between
+fn rate(before: u64, now: u64, elapsed: f64) -> f64 {
+    let bytes = if now >= before { now - before } else { now };
+    bytes as f64 / elapsed
+}
+
+// One event per peer that changed, appeared or disappeared between two reads
+fn diff(before: &[PeerStatus], after: &[PeerStatus]) -> Vec<LiveEvent> {
+    let key = |peer: &PeerStatus| (peer.interface.clone(), peer.public_key.clone());
+    let mut before: HashMap<_, _> = before.iter().map(|peer| (key(peer), peer)).collect();
+    let mut events = vec![];
+    for peer in after {
+        let changes = match before.remove(&key(peer)) {
+            Some(previous) => changes(previous, peer),
+            None => vec![PeerChange::Added],
+        };
+        if !changes.is_empty() {
+            events.push(LiveEvent::Peer { changes, peer: peer.clone() });
+        }
+    }
+    let mut removed: Vec<_> = before.into_keys().collect();
+    removed.sort();
+    events.extend(
+        removed
+            .into_iter()
+            .map(|(interface, public_key)| LiveEvent::Removed { interface, public_key }),
+    );
+    events
+}
+
+fn changes(before: &PeerStatus, after: &PeerStatus) -> Vec<PeerChange> {
+    let mut changes = vec![];
+    if before.last_handshake_at != after.last_handshake_at {
+        changes.push(PeerChange::Handshake);
+    }
+    if before.endpoint_addr != after.endpoint_addr {
+        changes.push(PeerChange::Endpoint);
+    }
+    if before.rx_rate != after.rx_rate || before.tx_rate != after.tx_rate {
+        changes.push(PeerChange::Rates);
+    }
+    match (before.online, after.online) {
+        (false, true) => changes.push(PeerChange::Online),
+        (true, false) => changes.push(PeerChange::Offline),
+        _ if before.status != after.status => changes.push(PeerChange::Status),
+        _ => {}
+    }
+    if before.owner_id != after.owner_id {
+        changes.push(PeerChange::Owner);
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    const PRESENCE: PresenceThresholds =
+        PresenceThresholds { online: TimeDelta::seconds(180), idle: TimeDelta::seconds(900) };
+
+    fn dump_peer(public_key: &str, handshake: Option<TimeDelta>, transfer_rx: u64) -> PeerDump {
+        PeerDump {
+            interface: "wg0".to_string(),
+            public_key: public_key.to_string(),
+            preshared_key: None,
+            endpoint_addr: Some("203.0.113.5:51820".parse().unwrap()),
+            allowed_ips: vec![],
+            last_handshake_at: handshake.map(|ago| DateTime::from_timestamp(1_720_000_000, 0).unwrap() - ago),
+            transfer_rx,
+            transfer_tx: 0,
+            persistent_keepalive: None,
+        }
+    }
+
+    #[test]
+    fn rates_since_the_previous_read() {
+        let now = DateTime::from_timestamp(1_720_000_000, 0).unwrap();
+        let first = Instant::now();
+        let dump = WgDump { interfaces: vec![], peers: vec![dump_peer("a=", None, 1000)] };
+        let peers = statuses(&dump, &HashMap::new(), PRESENCE, None, now, first);
+        assert_eq!((peers[0].rx_rate, peers[0].online), (0.0, false));
+
+        let previous = Snapshot { taken_at: first, peers };
+        let dump = WgDump { interfaces: vec![], peers: vec![dump_peer("a=", Some(TimeDelta::seconds(5)), 3000)] };
+        let peers = statuses(&dump, &HashMap::new(), PRESENCE, Some(&previous), now, first + Duration::from_secs(4));
+        assert_eq!((peers[0].rx_rate, peers[0].online), (500.0, true));
+        // the interface restarted between the reads
+        assert_eq!(rate(3000, 400, 4.0), 100.0);
+    }
+
+    #[test]
+    fn only_changes_are_sent() {
+        let now = DateTime::from_timestamp(1_720_000_000, 0).unwrap();
+        let peer = |public_key: &str, handshake: Option<TimeDelta>| {
+            status(&dump_peer(public_key, handshake, 0), None, PRESENCE, None, 0.0, now)
+        };
+        let before = vec![peer("a=", None), peer("b=", Some(TimeDelta::seconds(10))), peer("c=", None)];
+        let mut moved = peer("b=", Some(TimeDelta::seconds(10)));
+        moved.endpoint_addr = Some("198.51.100.7:4500".parse().unwrap());
+        let after = vec![peer("a=", Some(TimeDelta::seconds(1))), moved, peer("d=", None)];
+
+        let events = diff(&before, &after);
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                LiveEvent::Peer { changes, peer } => (peer.public_key.as_str(), changes.clone()),
+                LiveEvent::Removed { public_key, .. } => (public_key.as_str(), vec![]),
+                LiveEvent::Snapshot { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a=", vec![PeerChange::Handshake, PeerChange::Online]),
+                ("b=", vec![PeerChange::Endpoint]),
+                ("d=", vec![PeerChange::Added]),
+                ("c=", vec![]),
+            ]
+        );
+        assert!(diff(&after, &after).is_empty());
+
+        // nothing happened but time passing
+        let idle = dump_peer("e=", Some(TimeDelta::seconds(600)), 0);
+        let offline = status(&idle, None, PRESENCE, None, 0.0, now + TimeDelta::seconds(600));
+        let idle = status(&idle, None, PRESENCE, None, 0.0, now);
+        assert_eq!((idle.status, offline.status), (PeerPresence::Idle, PeerPresence::Offline));
+        assert_eq!(changes(&idle, &offline), vec![PeerChange::Status]);
+    }
+
+    #[test]
+    fn events_of_other_interfaces_are_filtered() {
+        let now = Utc::now();
+        let mut other = status(&dump_peer("b=", None, 0), None, PRESENCE, None, 0.0, now);
+        other.interface = "wg1".to_string();
+        let snapshot = LiveEvent::Snapshot {
+            peers: vec![status(&dump_peer("a=", None, 0), None, PRESENCE, None, 0.0, now), other.clone()],
+        };
+        let Some(LiveEvent::Snapshot { peers }) = snapshot.clone().on_interface(Some("wg0")) else {
+            panic!("snapshot filtered out")
+        };
+        assert_eq!(peers.len(), 1);
+        let event = LiveEvent::Peer { changes: vec![PeerChange::Added], peer: other };
+        assert_eq!(event.clone().on_interface(Some("wg0")), None);
+        assert_eq!(event.clone().on_interface(Some("wg1")), Some(event.clone()));
+        assert_eq!(snapshot.clone().on_interface(None), Some(snapshot));
+    }
+
+    #[test]
+    fn subscribers_only_see_their_own_peers() {
+        let now = Utc::now();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let peer = |public_key: &str, owner_id: Uuid| PeerStatus {
+            owner_id: Some(owner_id),
+            ..status(&dump_peer(public_key, None, 0), None, PRESENCE, None, 0.0, now)
+        };
+        let mut seen = HashSet::new();
+        let snapshot = LiveEvent::Snapshot { peers: vec![peer("a=", alice), peer("b=", bob)] };
+        let Some(LiveEvent::Snapshot { peers }) = snapshot.for_owner(alice, &mut seen) else {
+            panic!("snapshot filtered out")
+        };
+        assert_eq!(peers, vec![peer("a=", alice)]);
+
+        let changed = |peer: PeerStatus| LiveEvent::Peer { changes: vec![PeerChange::Owner], peer };
+        assert_eq!(changed(peer("b=", bob)).for_owner(alice, &mut seen), None);
+        let removed = |public_key: &str| LiveEvent::Removed {
+            interface: "wg0".to_string(),
+            public_key: public_key.to_string(),
+        };
+        assert_eq!(removed("b=").for_owner(alice, &mut seen), None);
+        // handed over from bob to alice, and back
+        assert_eq!(
+            changed(peer("b=", alice)).for_owner(alice, &mut seen),
+            Some(LiveEvent::Peer { changes: vec![PeerChange::Added], peer: peer("b=", alice) })
+        );
+        assert_eq!(changed(peer("b=", bob)).for_owner(alice, &mut seen), Some(removed("b=")));
+        assert_eq!(removed("a=").for_owner(alice, &mut seen), Some(removed("a=")));
+        assert!(seen.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use chrono::Utc;
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tracing::warn;
+
+use crate::domain::models::peer::{PeerPresence, PresenceThresholds};
+use crate::infra::peer_repository;
+use crate::service::wg_dump::{WgDump, ALL_INTERFACES};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Prometheus metrics. Requests are counted as they complete, the pool and the WireGuard
+// state are read when scraped, so a removed peer is gone from the next scrape.
+
+const NAMESPACE: &str = "rustify";
+// route label of requests no route matched, the raw paths would be unbounded
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+struct HttpMetrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    duration: HistogramVec,
+}
+
+static HTTP: LazyLock<HttpMetrics> = LazyLock::new(|| {
+    let labels = &["method", "route", "status"];
+    let requests = IntCounterVec::new(
+        Opts::new("http_requests_total", "HTTP requests handled").namespace(NAMESPACE),
+        labels,
+    )
+    .unwrap();
+    let duration = HistogramVec::new(
+        HistogramOpts::new("http_request_duration_seconds", "Time to respond to HTTP requests")
+            .namespace(NAMESPACE),
+        labels,
+    )
+    .unwrap();
+    let registry = Registry::new();
+    registry.register(Box::new(requests.clone())).unwrap();
+    registry.register(Box::new(duration.clone())).unwrap();
+    HttpMetrics { registry, requests, duration }
+});
+
+pub fn observe_request(method: &str, route: &str, status: u16, elapsed: Duration) {
+    let status = status.to_string();
+    let labels = [method, route, status.as_str()];
+    HTTP.requests.with_label_values(&labels).inc();
+    HTTP.duration.with_label_values(&labels).observe(elapsed.as_secs_f64());
+}
+
+// The text exposition format served to Prometheus
+pub async fn render(state: &AppState) -> String {
+    let registry = Registry::new();
+    register_pool(&registry, state);
+    let dump = match state.wg.show(ALL_INTERFACES).await {
+        Ok(dump) => Some(dump),
+        Err(err) => {
+            warn!("metrics: can not show the interfaces: {err:?}");
+            None
+        }
+    };
+    let up = IntGauge::with_opts(
+        Opts::new("wireguard_up", "Whether the WireGuard interfaces could be read").namespace(NAMESPACE),
+    )
+    .unwrap();
+    up.set(dump.is_some() as i64);
+    registry.register(Box::new(up)).unwrap();
+    if let Some(dump) = dump {
+        let names = peer_names(state, &dump).await;
+        register_peers(&registry, &dump, &names, state.presence);
+    }
+
+    let mut families = HTTP.registry.gather();
+    families.extend(registry.gather());
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&families, &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+fn register_pool(registry: &Registry, state: &AppState) {
+    let status = state.pool.status();
+    let gauges = [
+        ("db_pool_max_size", "Maximum number of database connections", status.max_size),
+        ("db_pool_size", "Open database connections", status.size),
+        ("db_pool_available", "Idle database connections", status.available),
+        ("db_pool_waiting", "Requests waiting for a database connection", status.waiting),
+    ];
+    for (name, help, value) in gauges {
+        let gauge = IntGauge::with_opts(Opts::new(name, help).namespace(NAMESPACE)).unwrap();
+        gauge.set(value as i64);
+        registry.register(Box::new(gauge)).unwrap();
+    }
+}
+
+fn register_peers(
+    registry: &Registry,
+    dump: &WgDump,
+    names: &HashMap<String, String>,
+    presence: PresenceThresholds,
+) {
+    let labels = &["interface", "public_key", "name"];
+    let gauge = |name: &str, help: &str| {
+        let gauge = GaugeVec::new(Opts::new(name, help).namespace(NAMESPACE), labels).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge
+    };
+    let rx = gauge("wireguard_peer_rx_bytes", "Bytes received from the peer since its interface came up");
+    let tx = gauge("wireguard_peer_tx_bytes", "Bytes sent to the peer since its interface came up");
+    let handshake_age = gauge(
+        "wireguard_peer_last_handshake_age_seconds",
+        "Seconds since the latest handshake, absent before the first one",
+    );
+    let online = IntGaugeVec::new(
+        Opts::new("wireguard_peer_online", "Whether the peer had a recent handshake").namespace(NAMESPACE),
+        labels,
+    )
+    .unwrap();
+    registry.register(Box::new(online.clone())).unwrap();
+
+    let now = Utc::now();
+    for peer in &dump.peers {
+        let name = names.get(&peer.public_key).map_or("", String::as_str);
+        let labels = [peer.interface.as_str(), peer.public_key.as_str(), name];
+        rx.with_label_values(&labels).set(peer.transfer_rx as f64);
+        tx.with_label_values(&labels).set(peer.transfer_tx as f64);
+        if let Some(age) = peer.handshake_age(now) {
+            handshake_age.with_label_values(&labels).set(age.num_milliseconds() as f64 / 1000.0);
+        }
+        let is_online = peer.presence(presence, now) == PeerPresence::Online;
+        online.with_label_values(&labels).set(is_online as i64);
+    }
+}
+
+// Peers unknown to the database are still exported, with an empty name
+async fn peer_names(state: &AppState, dump: &WgDump) -> HashMap<String, String> {
+    let public_keys = dump.peers.iter().map(|peer| peer.public_key.clone()).collect();
+    match peer_repository::get_by_public_keys(&state.pool, public_keys).await {
+        Ok(peers) => peers.into_iter().map(|peer| (peer.public_key, peer.name)).collect(),
+        Err(err) => {
+            warn!("metrics: can not load the peer names: {err}");
+            HashMap::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::service::wg_dump::PeerDump;
+
+    #[test]
+    fn peer_gauges() {
+        let peer = |public_key: &str, handshake: Option<TimeDelta>| PeerDump {
+            interface: "wg0".to_string(),
+            public_key: public_key.to_string(),
+            preshared_key: None,
+            endpoint_addr: None,
+            allowed_ips: vec![],
+            last_handshake_at: handshake.map(|ago| Utc::now() - ago),
+            transfer_rx: 5 << 30,
+            transfer_tx: 42,
+            persistent_keepalive: None,
+        };
+        let dump = WgDump {
+            interfaces: vec![],
+            peers: vec![peer("a=", Some(TimeDelta::seconds(10))), peer("b=", None)],
+        };
+        let names = HashMap::from([("a=".to_string(), "laptop".to_string())]);
+        let registry = Registry::new();
+        register_peers(&registry, &dump, &names, PresenceThresholds::default());
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&registry.gather(), &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(text.contains(
+            "rustify_wireguard_peer_rx_bytes{interface=\"wg0\",name=\"laptop\",public_key=\"a=\"} 5368709120\n"
+        ));
+        assert!(text.contains("rustify_wireguard_peer_online{interface=\"wg0\",name=\"laptop\",public_key=\"a=\"} 1\n"));
+        assert!(text.contains("rustify_wireguard_peer_online{interface=\"wg0\",name=\"\",public_key=\"b=\"} 0\n"));
+        // no handshake yet, no age
+        assert!(!text.contains("rustify_wireguard_peer_last_handshake_age_seconds{interface=\"wg0\",name=\"\""));
+    }
+}
@@ -1,2 +1,20 @@
 // pub core ticket;
+pub mod api_token;
+pub mod auth;
+pub mod event;
+pub mod expiry;
+pub mod import;
+pub mod keys;
+pub mod live;
+pub mod metrics;
+pub mod ownership;
 pub mod peer;
+pub mod qr;
+pub mod quota;
+pub mod reconcile;
+pub mod server_config;
+pub mod session;
+pub mod traffic;
+pub mod usage;
+pub mod wg_config;
+pub mod wg_dump;
@@ -0,0 +1,113 @@
+use uuid::Uuid;
+
+use crate::core::Claims;
+use crate::domain::models::peer::{PeerError, PeerModel};
+use crate::domain::models::user::Role;
+use crate::errors::Result;
+use crate::infra::errors::InfraError;
+use crate::infra::{peer_repository, user_repository};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Peers belong to the user who created them, or to the one an admin assigned them to.
+// Admins reach every peer, everybody else only their own: the peers of others answer 404,
+// as if they did not exist.
+
+#[derive(Debug, Clone)]
+pub struct OwnershipPolicy {
+    // viewers manage their own peers, otherwise it takes an operator
+    pub self_service: bool,
+    // peers a user other than an admin may own, None for no limit
+    pub max_peers_per_user: Option<i64>,
+}
+
+impl OwnershipPolicy {
+    // The role needed to create and change peers
+    pub fn manage_role(&self) -> Role {
+        if self.self_service {
+            Role::Viewer
+        } else {
+            Role::Operator
+        }
+    }
+
+    // The limit on the peers created by `claims`, admins create as many as they need
+    pub fn limit_for(&self, claims: &Claims) -> Option<i64> {
+        scope(claims).and(self.max_peers_per_user)
+    }
+}
+
+// The owner the caller is limited to, None for admins
+pub fn scope(claims: &Claims) -> Option<Uuid> {
+    (claims.role < Role::Admin).then_some(claims.sub)
+}
+
+pub fn can_reach(claims: &Claims, peer: &PeerModel) -> bool {
+    scope(claims).is_none_or(|owner_id| peer.owner_id == Some(owner_id))
+}
+
+// The peer, when the caller may reach it
+pub async fn peer(state: &AppState, claims: &Claims, peer_id: Uuid) -> Result<PeerModel> {
+    let peer = peer_repository::read(&state.pool, peer_id)
+        .await
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => PeerError::NotFound(peer_id),
+            _ => PeerError::InfraError(db_error),
+        })?;
+    if !can_reach(claims, &peer) {
+        return Err(PeerError::NotFound(peer_id).into());
+    }
+    Ok(peer)
+}
+
+// Ok when the caller may reach the peer, admins also the history of deleted peers
+pub async fn check(state: &AppState, claims: &Claims, peer_id: Uuid) -> Result<()> {
+    if scope(claims).is_none() {
+        return Ok(());
+    }
+    peer(state, claims, peer_id).await.map(|_| ())
+}
+
+// Fails with PeerError::OwnerNotFound for an unknown user
+pub async fn check_owner(state: &AppState, owner_id: Uuid) -> Result<()> {
+    user_repository::read(&state.pool, owner_id)
+        .await
+        .map(|_| ())
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => PeerError::OwnerNotFound(owner_id),
+            _ => PeerError::InfraError(db_error),
+        }
+        .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(role: Role) -> Claims {
+        Claims {
+            sub: Uuid::new_v4(),
+            name: "alice".to_string(),
+            exp: 0,
+            iat: 0,
+            jti: Uuid::new_v4(),
+            role,
+        }
+    }
+
+    #[test]
+    fn only_admins_reach_every_peer() {
+        let admin = claims(Role::Admin);
+        let operator = claims(Role::Operator);
+        assert_eq!(scope(&admin), None);
+        assert_eq!(scope(&operator), Some(operator.sub));
+        assert!(scope(&claims(Role::Viewer)).is_some());
+
+        let policy = OwnershipPolicy { self_service: false, max_peers_per_user: Some(3) };
+        assert_eq!(policy.limit_for(&admin), None);
+        assert_eq!(policy.limit_for(&operator), Some(3));
+        assert_eq!(policy.manage_role(), Role::Operator);
+        let portal = OwnershipPolicy { self_service: true, max_peers_per_user: None };
+        assert_eq!(portal.limit_for(&operator), None);
+        assert_eq!(portal.manage_role(), Role::Viewer);
+    }
+}
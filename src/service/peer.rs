@@ -1,4 +1,24 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use deadpool_diesel::postgres::Pool;
+use ipnet::IpNet;
+use serde::Serialize;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::domain::models::peer::{PeerError, PeerModel, PresenceThresholds, QuotaLimit};
+use crate::errors::{BaseError, Result};
+use crate::handlers::PeersFilter;
+use crate::infra::errors::InfraError;
+use crate::infra::peer_repository::{self, NewPeerForm, PeerQuotaForm, UpdatePeerForm};
+use crate::infra::wg_if_repository;
+use crate::infra::wireguard::{WgBackend, WgPeerConfig};
+use crate::domain::models::ipam::{self, IpamError};
+use crate::service::quota;
+use crate::service::wg_dump::PeerDump;
+use crate::utils::middlewares::mw_ctx::AppState;
+
 // #[derive(Clone, Debug, Serialize, Deserialize)]
 // pub struct Peer{
 //     name:Option<String>,
@@ -20,118 +40,515 @@ use serde::{Deserialize, Serialize};
 //     // interface_id:Option<Interface>
 // }
 
-#[derive(Debug, Serialize, Clone, Hash)]
+// A peer of the live `wg show` dump, with the name it is stored under.
+// `name` is None for peers configured on the interface but unknown to the database.
+#[derive(Debug, Serialize, Clone)]
 pub struct PeerFullDump {
     name: Option<String>,
-    public_key: String,
-    preshared_key: Option<String>,
-    endpoint_addr: Option<String>,
-    allowed_ips: Option<String>,
-    last_handshake_at: u16,
-    transfer_rx: u16,
-    transfer_tx: u16,
-    persistent_keepalive: Option<u32>,
+    #[serde(flatten)]
+    dump: PeerDump,
 }
 
 impl PeerFullDump {
-    pub fn from_dump_str(dump: &str) -> Self {
-        let mut values = dump.split_whitespace();
-        Self {
-            name: Some("test".to_string()),
-            public_key: values.next().unwrap().to_string(),
-            preshared_key: values.next().and_then(|v| {
-                if v == "(none)" {
-                    None
-                } else {
-                    Some(v.to_string())
-                }
-            }),
-            endpoint_addr: values.next().and_then(|v| {
-                if v == "(none)" {
-                    None
-                } else {
-                    Some(v.to_string())
-                }
-            }),
-            allowed_ips: values.next().map(|v| v.to_string()),
-            last_handshake_at: values.next().unwrap().parse().unwrap(),
-            transfer_rx: values.next().unwrap().parse().unwrap(),
-            transfer_tx: values.next().unwrap().parse().unwrap(),
-            persistent_keepalive: values.next().map(|v| v.parse().unwrap()),
-        }
+    pub fn from_dump(dump: PeerDump, name: Option<String>) -> Self {
+        Self { name, dump }
     }
 }
 
-#[derive(Debug, Serialize, Clone, Hash)]
+#[derive(Debug, Serialize, Clone)]
 pub struct PeerRxTxDump {
+    interface: String,
     public_key: String,
-    last_handshake_at: u16,
-    transfer_rx: u16,
-    transfer_tx: u16,
+    last_handshake_at: Option<DateTime<Utc>>,
+    transfer_rx: u64,
+    transfer_tx: u64,
 }
 
-impl PeerRxTxDump {
-    pub fn from_rxtx_lha(value: (&str, &str)) -> Self {
-        // t_dump.split("\n").zip( lha_dump.split("\n"))
-        //     .map(
-        //         |v| {
-        let mut transfer = value.0.split_whitespace();
-        let mut last_handshake = value.1.split_whitespace();
-        last_handshake.next().unwrap();
+impl From<PeerDump> for PeerRxTxDump {
+    fn from(dump: PeerDump) -> Self {
         Self {
-            public_key: transfer.next().unwrap_or("").parse().unwrap(),
-            last_handshake_at: last_handshake.next().unwrap_or("").parse().unwrap(),
-            transfer_rx: transfer.next().unwrap_or("").parse().unwrap(),
-            transfer_tx: transfer.next().unwrap_or("").parse().unwrap(),
+            interface: dump.interface,
+            public_key: dump.public_key,
+            last_handshake_at: dump.last_handshake_at,
+            transfer_rx: dump.transfer_rx,
+            transfer_tx: dump.transfer_tx,
         }
-        //     }
-        // )
-        //         .collect::<Vec<PeerRxTxDump>>()
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Hash)]
-pub struct PeerCreate {
-    name: String,
-    // interface_id:u32,
-    persistent_keepalive: Option<u16>,
-    allowed_ips: String,
-    with_psk: bool,
+// Keeps the peers table and the live interface in step. Every change is written to the
+// database and applied through the WireGuard backend as one operation: when the second
+// step fails, the first one is rolled back and the failure surfaces as BaseError::WgApply.
+pub struct PeerService<'a> {
+    pub pool: &'a Pool,
+    pub wg: &'a dyn WgBackend,
+    // of monthly quotas, see QuotaPolicy
+    pub billing_day: u32,
 }
 
-// pub struct PeerService<'a> {
-//     // pub db: &'a Pool,
-//     pub ctx: &'a Ctx,
-// }
+impl<'a> PeerService<'a> {
+    pub fn new(state: &'a AppState) -> Self {
+        Self {
+            pool: &state.pool,
+            wg: state.wg.as_ref(),
+            billing_day: state.quota.billing_day,
+        }
+    }
 
-// impl<'a> PeerService<'a> {
-// pub async fn sudo_exec(&self, cmd: Vec<&str>) -> ApiResult<Output> {
-//     Command::new("sudo")
-//         .args(cmd.clone())
-//         .output().await.map_err(|err| ApiError {
-//         req_id: self.ctx.req_id(),
-//         error: Error::Execution { source: "here".to_string() },
-//     })
-// }
-// pub async fn peer_full_dump(&self) -> ApiResult<Vec<PeerFullDump>> {
-//     let dump_output = self.sudo_exec(vec!["wg", "show", "wg0", "dump"]).await?;
-//     let dump = String::from_utf8(dump_output.stdout).unwrap();
-//     let dump_str = dump.strip_suffix("\n");
-//     let dump_vec: Vec<&str> = dump_str.map_or(vec![], |v| v.split("\n").collect());
-//     let mut dump_vec_skipped_if = dump_vec.iter();
-//     dump_vec_skipped_if.next();
-//     Ok(dump_vec_skipped_if.map(|v| PeerFullDump::from_dump_str(v)).collect())
-// }
+    // `max_owned` bounds the peers of the owner of the new peer, None for no limit
+    pub async fn create(
+        &self,
+        new_peer: NewPeerForm,
+        requested_address: Option<String>,
+        max_owned: Option<i64>,
+    ) -> Result<PeerModel> {
+        let peer = peer_repository::create(self.pool, new_peer, requested_address, max_owned)
+            .await
+            .map_err(|err| match err {
+                // The unique index on public_key is the last line of defence against duplicates
+                IpamError::Infra(InfraError::UniqueViolation) => PeerError::PublicKeyExists.into(),
+                IpamError::Infra(db_error) => PeerError::InfraError(db_error).into(),
+                _ => BaseError::from(err),
+            })?
+            .ok_or(PeerError::LimitReached(max_owned.unwrap_or_default()))?;
+        if !peer.enabled {
+            return Ok(peer);
+        }
+        if let Err(err) = self.set_peer(&peer).await {
+            let rollback = peer_repository::remove_peer(self.pool, peer.id).await;
+            return Err(apply_failed(err, rollback));
+        }
+        Ok(peer)
+    }
 
-// pub async fn wg_rxtx_lha(&self) -> ApiResult<Vec<PeerRxTxDump>> {
-// let t_output = self.sudo_exec(vec!["wg", "show", "wg0", "transfer"]).await?;
-// let lha_output = self.sudo_exec(vec!["wg", "show", "wg0", "latest-handshakes"]).await?;
-// if !(t_output.stdout.is_empty() && lha_output.stdout.is_empty()) {
-//     let t_to_string = String::from_utf8(t_output.stdout).unwrap();
-//     let lha_to_string = String::from_utf8(lha_output.stdout).unwrap();
-//     let dump_transfer = t_to_string.strip_suffix("\n").unwrap();
-//     let dump_last_handshake = lha_to_string.strip_suffix("\n").unwrap();
-//     Ok(PeerRxTxDump::from_rxtx_lha_dump(dump_transfer, dump_last_handshake))
-// } else { Ok(vec![]) }
-// }
-// }
+    // `quota` replaces the quota of the peer in the same update, Some(None) removes it
+    pub async fn update(
+        &self,
+        peer_id: Uuid,
+        mut update: UpdatePeerForm,
+        quota: Option<Option<QuotaLimit>>,
+    ) -> Result<PeerModel> {
+        let before = self.read(peer_id).await?;
+        let now = Utc::now().naive_utc();
+        let mut new_quota = match quota {
+            Some(Some(limit)) => {
+                quota::validate(&limit)?;
+                Some(Some(quota::limited(&before, limit, self.billing_day, now)))
+            }
+            Some(None) => Some(None),
+            None => None,
+        };
+        // the expiry scheduler would disable it again
+        let expires_at = update.expires_at.unwrap_or(before.expires_at);
+        if update.enabled == Some(true) && expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(PeerError::Expired(peer_id).into());
+        }
+        // nor would the quota enforcement let it back on, unless the new quota allows it
+        let enabling = update.enabled == Some(true) && !before.enabled;
+        let quota_after = new_quota.as_ref().unwrap_or(&before.quota).as_ref();
+        if enabling && quota_after.is_some_and(|quota| quota.used(before.transfer_total()) >= quota.bytes) {
+            return Err(PeerError::QuotaExceeded(peer_id).into());
+        }
+        // back under a raised quota: the enforcement disables it again only once this is cleared
+        if enabling && quota_after.is_some_and(|quota| quota.exceeded_at.is_some()) {
+            match &mut new_quota {
+                Some(Some(quota)) => quota.exceeded_at = None,
+                _ => update.quota_exceeded_at = Some(None),
+            }
+        }
+        if update.is_empty() && new_quota.is_none() {
+            return Ok(before);
+        }
+        let quota_form = new_quota.as_ref().map(|quota| quota.as_ref().into());
+        let after = peer_repository::update_peer(self.pool, peer_id, update, quota_form)
+            .await
+            .map_err(|db_error| peer_error(peer_id, db_error))?;
+        // only enabling and disabling changes what the interface knows about the peer
+        let applied = match (before.enabled, after.enabled) {
+            (false, true) => self.set_peer(&after).await,
+            (true, false) => self.remove_from_interface(&after).await,
+            _ => Ok(()),
+        };
+        if let Err(err) = applied {
+            // the quota form carries exceeded_at as well
+            let restore_quota: Option<PeerQuotaForm> =
+                new_quota.is_some().then(|| before.quota.as_ref().into());
+            let exceeded_at = before.quota.as_ref().and_then(|quota| quota.exceeded_at);
+            let restore = UpdatePeerForm {
+                name: Some(before.name),
+                enabled: Some(before.enabled),
+                interface_id: None,
+                expires_at: Some(before.expires_at),
+                owner_id: Some(before.owner_id),
+                quota_exceeded_at: restore_quota.is_none().then_some(exceeded_at),
+            };
+            let rollback = peer_repository::update_peer(self.pool, peer_id, restore, restore_quota).await;
+            return Err(apply_failed(err, rollback));
+        }
+        Ok(after)
+    }
+
+    // Suspends or restores the access of a peer, its keys and address stay reserved
+    pub async fn set_enabled(&self, peer_id: Uuid, enabled: bool) -> Result<PeerModel> {
+        let update = UpdatePeerForm {
+            name: None,
+            enabled: Some(enabled),
+            interface_id: None,
+            expires_at: None,
+            owner_id: None,
+            quota_exceeded_at: None,
+        };
+        self.update(peer_id, update, None).await
+    }
+
+    // One peer after the other, a failing peer does not hold back the rest
+    pub async fn set_enabled_many(
+        &self,
+        peer_ids: &[Uuid],
+        enabled: bool,
+    ) -> (Vec<PeerModel>, Vec<(Uuid, BaseError)>) {
+        let (mut updated, mut failed) = (vec![], vec![]);
+        for &peer_id in peer_ids {
+            match self.set_enabled(peer_id, enabled).await {
+                Ok(peer) => updated.push(peer),
+                Err(err) => failed.push((peer_id, err)),
+            }
+        }
+        (updated, failed)
+    }
+
+    // For the expiry: disables the peer only while it is still past its expires_at at `now`,
+    // None when it was extended or disabled in the meantime
+    pub async fn disable_expired(&self, peer_id: Uuid, now: NaiveDateTime) -> Result<Option<PeerModel>> {
+        let Some(after) = peer_repository::disable_expired(self.pool, peer_id, now)
+            .await
+            .map_err(|db_error| peer_error(peer_id, db_error))?
+        else {
+            return Ok(None);
+        };
+        if let Err(err) = self.remove_from_interface(&after).await {
+            let restore = UpdatePeerForm {
+                name: None,
+                enabled: Some(true),
+                interface_id: None,
+                expires_at: None,
+                owner_id: None,
+                quota_exceeded_at: None,
+            };
+            let rollback = peer_repository::update_peer(self.pool, peer_id, restore, None).await;
+            return Err(apply_failed(err, rollback));
+        }
+        Ok(Some(after))
+    }
+
+    // The peer leaves the interface first, a peer left behind in the kernel would keep its access
+    pub async fn remove(&self, peer_id: Uuid) -> Result<Uuid> {
+        let peer = self.read(peer_id).await?;
+        if peer.enabled {
+            self.remove_from_interface(&peer)
+                .await
+                .map_err(|err| apply_failed(err, Ok::<_, InfraError>(())))?;
+        }
+        match peer_repository::remove_peer(self.pool, peer_id).await {
+            Ok(removed) => Ok(removed),
+            Err(db_error) => {
+                if peer.enabled {
+                    if let Err(err) = self.set_peer(&peer).await {
+                        error!("peer {peer_id} was removed from its interface but not from the database: {err:?}");
+                    }
+                }
+                Err(peer_error(peer_id, db_error))
+            }
+        }
+    }
+
+    async fn read(&self, peer_id: Uuid) -> Result<PeerModel> {
+        peer_repository::read(self.pool, peer_id)
+            .await
+            .map_err(|db_error| peer_error(peer_id, db_error))
+    }
+
+    async fn set_peer(&self, peer: &PeerModel) -> Result<()> {
+        let interface = self.interface_name(peer).await?;
+        self.wg.set_peer(&interface, &wg_peer_config(peer)).await
+    }
+
+    async fn remove_from_interface(&self, peer: &PeerModel) -> Result<()> {
+        let interface = self.interface_name(peer).await?;
+        self.wg.remove_peer(&interface, &peer.public_key).await
+    }
+
+    async fn interface_name(&self, peer: &PeerModel) -> Result<String> {
+        wg_if_repository::read(self.pool, peer.interface_id)
+            .await
+            .map(|interface| interface.name)
+            .map_err(|db_error| PeerError::InfraError(db_error).into())
+    }
+}
+
+// Every peer of an interface, enabled or not
+pub async fn interface_peers(pool: &Pool, interface_id: i32) -> Result<Vec<PeerModel>> {
+    let filter = PeersFilter {
+        interface_id: Some(interface_id),
+        ..Default::default()
+    };
+    // no status filter, the thresholds do not matter
+    peer_repository::get_all(pool, filter, PresenceThresholds::default())
+        .await
+        .map_err(|db_error| PeerError::InfraError(db_error).into())
+}
+
+// What the interface should hold for a peer: the server side only routes the tunnel
+// addresses of the peer to it, the client side settings stay in its own config
+pub fn wg_peer_config(peer: &PeerModel) -> WgPeerConfig {
+    WgPeerConfig {
+        public_key: peer.public_key.clone(),
+        preshared_key: peer.preshared_key.clone(),
+        endpoint: None,
+        allowed_ips: ipam::parse_addresses(&peer.address)
+            .into_iter()
+            .map(IpNet::from)
+            .collect(),
+        persistent_keepalive: None,
+    }
+}
+
+fn peer_error(peer_id: Uuid, db_error: InfraError) -> BaseError {
+    match db_error {
+        InfraError::NotFound => PeerError::NotFound(peer_id),
+        InfraError::UniqueViolation => PeerError::PublicKeyExists,
+        _ => PeerError::InfraError(db_error),
+    }
+    .into()
+}
+
+// wg's own message for backend errors, which the client does not get from Execution
+pub fn apply_error_source(err: BaseError) -> String {
+    match err {
+        BaseError::Execution { source } => source,
+        err => err.to_string(),
+    }
+}
+
+// Wraps the error of the failed step, noting when the rollback failed as well
+fn apply_failed<T, E: fmt::Debug>(err: BaseError, rollback: std::result::Result<T, E>) -> BaseError {
+    let cause = apply_error_source(err);
+    let source = match rollback {
+        Ok(_) => cause,
+        Err(rollback_error) => {
+            error!("rollback after a failed WireGuard apply failed: {rollback_error:?}");
+            format!("{cause}, and the rollback failed, the database and the interface are out of sync")
+        }
+    };
+    BaseError::WgApply { source }
+}
+
+// These run against a database, see AppState::for_tests. The quota and expiry tests
+// build on the helpers.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::domain::models::interface::InterfaceModel;
+    use crate::domain::models::peer::QuotaPeriod;
+    use crate::infra::wg_if_repository::NewInterfaceForm;
+    use crate::infra::wireguard::FakeWg;
+    use crate::service::keys::WgKeyPair;
+    use crate::service::reconcile;
+
+    // `up` brings it up on the fake backend, the way the reconcile endpoint does
+    pub(crate) async fn interface(state: &AppState, up: bool) -> InterfaceModel {
+        let keypair = WgKeyPair::generate();
+        let new_interface = NewInterfaceForm {
+            // the tests share the database, names have to be unique
+            name: format!("t{}", &Uuid::new_v4().simple().to_string()[..8]),
+            pubkey: keypair.public_key,
+            privkey: keypair.private_key,
+            address: "10.8.0.1/24".to_string(),
+            port: 51820,
+            mtu: None,
+            fwmark: None,
+            post_up: None,
+            post_down: None,
+        };
+        let interface = wg_if_repository::create(&state.pool, new_interface).await.unwrap();
+        if up {
+            reconcile::reconcile(state, &interface).await.unwrap();
+        }
+        interface
+    }
+
+    pub(crate) fn new_peer(interface: &InterfaceModel, name: &str) -> NewPeerForm {
+        let keypair = WgKeyPair::generate();
+        NewPeerForm {
+            name: name.to_string(),
+            persistent_keepalive: None,
+            allowed_ips: None,
+            preshared_key: None,
+            private_key: Some(keypair.private_key),
+            public_key: keypair.public_key,
+            if_pubkey: interface.pubkey.clone(),
+            address: String::new(),
+            interface_id: interface.id,
+            expires_at: None,
+            quota_bytes: None,
+            quota_period: None,
+            quota_period_start: None,
+            owner_id: None,
+        }
+    }
+
+    pub(crate) async fn live_keys(state: &AppState, interface: &InterfaceModel) -> Vec<String> {
+        let dump = state.wg.show(&interface.name).await.unwrap();
+        dump.peers.into_iter().map(|peer| peer.public_key).collect()
+    }
+
+    pub(crate) fn rename(name: &str) -> UpdatePeerForm {
+        UpdatePeerForm {
+            name: Some(name.to_string()),
+            enabled: None,
+            interface_id: None,
+            expires_at: None,
+            owner_id: None,
+            quota_exceeded_at: None,
+        }
+    }
+
+    // as the enforcement finds it after a poll
+    pub(crate) async fn set_quota(state: &AppState, peer: &PeerModel, quota: PeerQuotaForm) {
+        peer_repository::update_peer(&state.pool, peer.id, rename(&peer.name), Some(quota))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database in TEST_DATABASE_URL"]
+    async fn lifecycle() {
+        let state = AppState::migrated().await;
+        let interface = interface(&state, true).await;
+        let service = PeerService::new(&state);
+
+        let peer = service.create(new_peer(&interface, "laptop"), None, None).await.unwrap();
+        assert_eq!(peer.address, "10.8.0.2/32");
+        assert_eq!(live_keys(&state, &interface).await, vec![peer.public_key.clone()]);
+
+        let renamed = service.update(peer.id, rename("phone"), None).await.unwrap();
+        assert_eq!(renamed.name, "phone");
+        assert_eq!(live_keys(&state, &interface).await, vec![peer.public_key.clone()]);
+
+        assert_eq!(service.remove(peer.id).await.unwrap(), peer.id);
+        assert!(live_keys(&state, &interface).await.is_empty());
+        assert!(interface_peers(&state.pool, interface.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database in TEST_DATABASE_URL"]
+    async fn failed_apply_rolls_back() {
+        let state = AppState::migrated().await;
+        // the interface is not up, `wg set` fails with no such device
+        let down = interface(&state, false).await;
+        let service = PeerService::new(&state);
+        let err = service.create(new_peer(&down, "laptop"), None, None).await.unwrap_err();
+        assert!(matches!(err, BaseError::WgApply { .. }), "{err:?}");
+        assert!(interface_peers(&state.pool, down.id).await.unwrap().is_empty());
+
+        let up = interface(&state, true).await;
+        let peer = service.create(new_peer(&up, "laptop"), None, None).await.unwrap();
+        // a backend that lost the interface, e.g. after someone ran `ip link del`
+        let lost = FakeWg::default();
+        let broken = PeerService { wg: &lost, ..PeerService::new(&state) };
+        // a quota set along with it is rolled back too
+        let disable = UpdatePeerForm { enabled: Some(false), ..rename("phone") };
+        let limit = QuotaLimit { bytes: 1000, period: QuotaPeriod::Total };
+        let err = broken.update(peer.id, disable, Some(Some(limit))).await.unwrap_err();
+        assert!(matches!(err, BaseError::WgApply { .. }), "{err:?}");
+        let stored = peer_repository::read(&state.pool, peer.id).await.unwrap();
+        assert!(stored.enabled);
+        assert_eq!(stored.name, "laptop");
+        assert_eq!(stored.quota, None);
+        assert_eq!(live_keys(&state, &up).await, vec![peer.public_key.clone()]);
+
+        // removing leaves the database alone when the peer can not leave the interface
+        let err = broken.remove(peer.id).await.unwrap_err();
+        assert!(matches!(err, BaseError::WgApply { .. }), "{err:?}");
+        assert!(peer_repository::read(&state.pool, peer.id).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database in TEST_DATABASE_URL"]
+    async fn enable_and_disable() {
+        let state = AppState::migrated().await;
+        let interface = interface(&state, true).await;
+        let service = PeerService::new(&state);
+        let peer = service.create(new_peer(&interface, "laptop"), None, None).await.unwrap();
+
+        let disabled = service.set_enabled(peer.id, false).await.unwrap();
+        assert!(!disabled.enabled);
+        assert!(live_keys(&state, &interface).await.is_empty());
+
+        // a backend that lost the interface, e.g. after someone ran `ip link del`
+        let lost = FakeWg::default();
+        let broken = PeerService { wg: &lost, ..PeerService::new(&state) };
+        let err = broken.set_enabled(peer.id, true).await.unwrap_err();
+        assert!(matches!(err, BaseError::WgApply { .. }), "{err:?}");
+        assert!(!peer_repository::read(&state.pool, peer.id).await.unwrap().enabled);
+        assert!(live_keys(&state, &interface).await.is_empty());
+
+        let (enabled, failed) = service.set_enabled_many(&[peer.id, Uuid::new_v4()], true).await;
+        assert_eq!(enabled.len(), 1);
+        assert!(matches!(failed[..], [(_, BaseError::NotFound { .. })]), "{failed:?}");
+        assert_eq!(live_keys(&state, &interface).await, vec![peer.public_key.clone()]);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a PostgreSQL database in TEST_DATABASE_URL"]
+    async fn enabling_respects_expiry_and_quota() {
+        let state = AppState::migrated().await;
+        let interface = interface(&state, true).await;
+        let service = PeerService::new(&state);
+        let now = Utc::now().naive_utc();
+
+        let expires_at = Some(now - chrono::TimeDelta::hours(1));
+        let expired = NewPeerForm { expires_at, ..new_peer(&interface, "expired") };
+        let expired = service.create(expired, None, None).await.unwrap();
+        service.set_enabled(expired.id, false).await.unwrap();
+
+        let over = service.create(new_peer(&interface, "over"), None, None).await.unwrap();
+        let quota = |bytes, exceeded_at| PeerQuotaForm {
+            quota_bytes: Some(bytes),
+            quota_period: Some("total".to_string()),
+            // 1000 bytes used
+            quota_used_from: -1000,
+            quota_period_start: Some(now),
+            quota_warned_percent: 0,
+            quota_exceeded_at: exceeded_at,
+        };
+        set_quota(&state, &over, quota(500, Some(now))).await;
+        service.set_enabled(over.id, false).await.unwrap();
+
+        let err = service.set_enabled(expired.id, true).await.unwrap_err();
+        assert!(err.to_string().contains("has expired"), "{err}");
+        let err = service.set_enabled(over.id, true).await.unwrap_err();
+        assert!(err.to_string().contains("used up its quota"), "{err}");
+        let (enabled, failed) = service.set_enabled_many(&[expired.id, over.id], true).await;
+        assert!(enabled.is_empty());
+        let failed: Vec<_> = failed.iter().map(|(id, _)| *id).collect();
+        assert_eq!(failed, vec![expired.id, over.id]);
+        assert!(live_keys(&state, &interface).await.is_empty());
+
+        // once raised, the peer may be enabled, and goes over again from a clean state
+        set_quota(&state, &over, quota(5000, Some(now))).await;
+        let enabled = service.set_enabled(over.id, true).await.unwrap();
+        assert!(enabled.enabled);
+        assert_eq!(enabled.quota.unwrap().exceeded_at, None);
+
+        // or raised and enabled in one update
+        service.set_enabled(over.id, false).await.unwrap();
+        set_quota(&state, &over, quota(500, Some(now))).await;
+        let enable = UpdatePeerForm { enabled: Some(true), ..rename("over") };
+        let limit = QuotaLimit { bytes: 5000, period: QuotaPeriod::Total };
+        let enabled = service.update(over.id, enable, Some(Some(limit))).await.unwrap();
+        assert!(enabled.enabled);
+        let quota = enabled.quota.unwrap();
+        assert_eq!((quota.bytes, quota.exceeded_at), (5000, None));
+    }
+}
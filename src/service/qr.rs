@@ -0,0 +1,131 @@
+use qrcode::render::svg;
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+
+use crate::errors::{BaseError, Result};
+
+// The QR spec asks for 4 light modules around the symbol, scanners rely on it
+const QUIET_ZONE: u32 = 4;
+pub const MIN_SIZE: u32 = 64;
+pub const MAX_SIZE: u32 = 2048;
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+pub enum QrEcc {
+    #[serde(alias = "l")]
+    L,
+    #[default]
+    #[serde(alias = "m")]
+    M,
+    #[serde(alias = "q")]
+    Q,
+    #[serde(alias = "h")]
+    H,
+}
+
+impl From<QrEcc> for EcLevel {
+    fn from(value: QrEcc) -> Self {
+        match value {
+            QrEcc::L => EcLevel::L,
+            QrEcc::M => EcLevel::M,
+            QrEcc::Q => EcLevel::Q,
+            QrEcc::H => EcLevel::H,
+        }
+    }
+}
+
+// `size` is the minimum width/height in pixels, the symbol is scaled by whole modules
+pub fn render_svg(data: &str, ecc: QrEcc, size: u32) -> Result<String> {
+    let code = encode(data, ecc)?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(size, size)
+        .quiet_zone(true)
+        .build())
+}
+
+// Grayscale PNG, encoded by hand to stay clear of a full image processing stack
+pub fn render_png(data: &str, ecc: QrEcc, size: u32) -> Result<Vec<u8>> {
+    let code = encode(data, ecc)?;
+    let width = code.width() as u32;
+    let modules = width + 2 * QUIET_ZONE;
+    let scale = size.div_ceil(modules).max(1);
+    let side = modules * scale;
+
+    let mut pixels = vec![u8::MAX; (side * side) as usize];
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Light {
+            continue;
+        }
+        let (x, y) = (i as u32 % width + QUIET_ZONE, i as u32 / width + QUIET_ZONE);
+        for row in y * scale..(y + 1) * scale {
+            let start = (row * side + x * scale) as usize;
+            pixels[start..start + scale as usize].fill(0);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, side, side);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|err| BaseError::Execution {
+            source: err.to_string(),
+        })?;
+    Ok(out)
+}
+
+fn encode(data: &str, ecc: QrEcc) -> Result<QrCode> {
+    QrCode::with_error_correction_level(data, ecc.into()).map_err(|err| {
+        BaseError::BodyParsingError(format!("can not encode the config as a QR code: {err}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = "[Interface]\nPrivateKey = GItj22ScL4Raqow6LORx/t1i++S3YIzLtCO7y3Y+pHA=\n";
+
+    #[test]
+    fn png_is_scaled_to_the_requested_size() {
+        let bytes = render_png(CONF, QrEcc::M, 256).unwrap();
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.width, info.height);
+        assert!(info.width >= 256 && info.width < 512);
+
+        let modules = QrCode::with_error_correction_level(CONF, EcLevel::M).unwrap().width() as u32
+            + 2 * QUIET_ZONE;
+        assert_eq!(info.width % modules, 0);
+        let scale = info.width / modules;
+        // quiet zone is light, the top left finder pattern starts right after it
+        assert_eq!(pixels[0], u8::MAX);
+        let finder = QUIET_ZONE * scale;
+        assert_eq!(pixels[(finder * info.width + finder) as usize], 0);
+    }
+
+    #[test]
+    fn svg_document() {
+        let svg = render_svg(CONF, QrEcc::H, 128).unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<svg"));
+    }
+
+    #[test]
+    fn higher_correction_needs_more_modules() {
+        let low = QrCode::with_error_correction_level(CONF, QrEcc::L.into()).unwrap();
+        let high = QrCode::with_error_correction_level(CONF, QrEcc::H.into()).unwrap();
+        assert!(high.width() > low.width());
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::{BTreeSet, HashMap};
+
+use ipnet::IpNet;
+use serde::Serialize;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::domain::models::interface::InterfaceModel;
+use crate::domain::models::peer::PeerModel;
+use crate::errors::{BaseError, Result};
+use crate::domain::models::ipam;
+use crate::infra::wireguard::{WgBackend, WgInterfaceConfig, WgLinkConfig};
+use crate::infra::wg_if_repository;
+use crate::service::peer::{apply_error_source, interface_peers, wg_peer_config};
+use crate::service::wg_dump::WgDump;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Compares what the database says an interface should hold with what the kernel holds,
+// and converges the kernel to the database. The database always wins.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftReport {
+    pub interface_id: i32,
+    pub interface: String,
+    pub in_sync: bool,
+    // false when the interface does not exist in the kernel, e.g. after a reboot
+    pub interface_up: bool,
+    // interface settings that differ from wg_if
+    pub settings: Vec<FieldDrift>,
+    // enabled peers the interface does not know about
+    pub missing: Vec<PeerRef>,
+    // peers on the interface that are unknown or disabled in the database
+    pub extra: Vec<ExtraPeer>,
+    pub mismatched: Vec<MismatchedPeer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDrift {
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerRef {
+    pub peer_id: Uuid,
+    pub name: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtraPeer {
+    // set when the peer exists in the database but is disabled
+    pub peer_id: Option<Uuid>,
+    pub public_key: String,
+    pub allowed_ips: Vec<IpNet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MismatchedPeer {
+    #[serde(flatten)]
+    pub peer: PeerRef,
+    pub fields: Vec<FieldDrift>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReconcileReport {
+    // the drift found before converging
+    pub drift: DriftReport,
+    pub applied: usize,
+    pub failed: Vec<ReconcileFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReconcileFailure {
+    // public key of the peer, or the interface name for its settings
+    pub target: String,
+    pub error: String,
+}
+
+impl DriftReport {
+    pub fn new(interface: &InterfaceModel, peers: &[PeerModel], live: Option<&WgDump>) -> Self {
+        let empty = WgDump::default();
+        let dump = live.unwrap_or(&empty);
+        let live_peers: HashMap<&str, _> = dump
+            .peers
+            .iter()
+            .map(|peer| (peer.public_key.as_str(), peer))
+            .collect();
+        let stored: HashMap<&str, &PeerModel> = peers
+            .iter()
+            .map(|peer| (peer.public_key.as_str(), peer))
+            .collect();
+
+        let settings = dump
+            .interfaces
+            .first()
+            .map(|live_if| {
+                let mut fields = vec![];
+                compare(&mut fields, "public_key", &interface.pubkey, &live_if.public_key);
+                compare(&mut fields, "listen_port", &interface.port, &live_if.listen_port);
+                compare(
+                    &mut fields,
+                    "fwmark",
+                    &format_option(interface.fwmark),
+                    &format_option(live_if.fwmark),
+                );
+                fields
+            })
+            .unwrap_or_default();
+
+        let mut missing = vec![];
+        let mut mismatched = vec![];
+        for peer in peers.iter().filter(|peer| peer.enabled) {
+            let Some(live_peer) = live_peers.get(peer.public_key.as_str()) else {
+                missing.push(PeerRef::from(peer));
+                continue;
+            };
+            let expected = wg_peer_config(peer);
+            let mut fields = vec![];
+            compare(
+                &mut fields,
+                "allowed_ips",
+                &format_ips(&expected.allowed_ips),
+                &format_ips(&live_peer.allowed_ips),
+            );
+            // preshared keys are secret, only whether they match is reported
+            if expected.preshared_key != live_peer.preshared_key {
+                fields.push(FieldDrift {
+                    field: "preshared_key",
+                    expected: psk_state(&expected.preshared_key),
+                    actual: match (&expected.preshared_key, &live_peer.preshared_key) {
+                        (Some(_), Some(_)) => "different key".to_string(),
+                        (_, actual) => psk_state(actual),
+                    },
+                });
+            }
+            compare(
+                &mut fields,
+                "persistent_keepalive",
+                &format_option(expected.persistent_keepalive),
+                &format_option(live_peer.persistent_keepalive),
+            );
+            if !fields.is_empty() {
+                mismatched.push(MismatchedPeer {
+                    peer: PeerRef::from(peer),
+                    fields,
+                });
+            }
+        }
+
+        let extra: Vec<ExtraPeer> = dump
+            .peers
+            .iter()
+            .filter_map(|live_peer| match stored.get(live_peer.public_key.as_str()) {
+                Some(peer) if peer.enabled => None,
+                stored_peer => Some(ExtraPeer {
+                    peer_id: stored_peer.map(|peer| peer.id),
+                    public_key: live_peer.public_key.clone(),
+                    allowed_ips: live_peer.allowed_ips.clone(),
+                }),
+            })
+            .collect();
+
+        let interface_up = live.is_some();
+        Self {
+            interface_id: interface.id,
+            interface: interface.name.clone(),
+            in_sync: interface_up
+                && settings.is_empty()
+                && missing.is_empty()
+                && extra.is_empty()
+                && mismatched.is_empty(),
+            interface_up,
+            settings,
+            missing,
+            extra,
+            mismatched,
+        }
+    }
+}
+
+impl From<&PeerModel> for PeerRef {
+    fn from(peer: &PeerModel) -> Self {
+        Self {
+            peer_id: peer.id,
+            name: peer.name.clone(),
+            public_key: peer.public_key.clone(),
+        }
+    }
+}
+
+// Drift between the database and the live interface, without changing anything
+pub async fn drift(state: &AppState, interface: &InterfaceModel) -> Result<DriftReport> {
+    let peers = interface_peers(&state.pool, interface.id).await?;
+    let live = match state.wg.show(&interface.name).await {
+        Ok(dump) => Some(dump),
+        Err(err) => {
+            warn!("can not show interface {}: {err:?}", interface.name);
+            None
+        }
+    };
+    Ok(DriftReport::new(interface, &peers, live.as_ref()))
+}
+
+// Converges the live interface to the database, bringing it up first when it is missing.
+// Failing peers do not stop the others, they are listed in the report.
+pub async fn reconcile(state: &AppState, interface: &InterfaceModel) -> Result<ReconcileReport> {
+    let wg = state.wg.as_ref();
+    let peers = interface_peers(&state.pool, interface.id).await?;
+    let mut applied = 0;
+    let mut failed = vec![];
+
+    let live = match wg.show(&interface.name).await {
+        Ok(dump) => Some(dump),
+        Err(_) => {
+            bring_up(wg, interface)
+                .await
+                .map_err(|err| BaseError::WgApply { source: apply_error_source(err) })?;
+            applied += 1;
+            None
+        }
+    };
+    let drift = DriftReport::new(interface, &peers, live.as_ref());
+    let mut record = |target: &str, result: Result<()>| match result {
+        Ok(()) => applied += 1,
+        Err(err) => failed.push(ReconcileFailure {
+            target: target.to_string(),
+            error: apply_error_source(err),
+        }),
+    };
+
+    if drift.interface_up && !drift.settings.is_empty() {
+        record(&interface.name, set_interface(wg, interface).await);
+    }
+    for extra in &drift.extra {
+        record(&extra.public_key, wg.remove_peer(&interface.name, &extra.public_key).await);
+    }
+    let to_set = drift.missing.iter().chain(drift.mismatched.iter().map(|m| &m.peer));
+    for peer_ref in to_set {
+        if let Some(peer) = peers.iter().find(|peer| peer.id == peer_ref.peer_id) {
+            record(&peer.public_key, wg.set_peer(&interface.name, &wg_peer_config(peer)).await);
+        }
+    }
+
+    Ok(ReconcileReport { drift, applied, failed })
+}
+
+// Restores every interface after a start, e.g. following a reboot of the host
+pub async fn reconcile_all(state: &AppState) {
+    let interfaces = match wg_if_repository::get_all(&state.pool).await {
+        Ok(interfaces) => interfaces,
+        Err(err) => {
+            error!("reconcile on startup: can not load the interfaces: {err}");
+            return;
+        }
+    };
+    for interface in interfaces {
+        match reconcile(state, &interface).await {
+            Ok(report) if report.failed.is_empty() => info!(
+                "reconciled interface {}: {} change(s) applied",
+                interface.name, report.applied
+            ),
+            Ok(report) => error!(
+                "reconciled interface {} partially, failures: {:?}",
+                interface.name, report.failed
+            ),
+            Err(err) => error!("reconcile of interface {} failed: {err}", interface.name),
+        }
+    }
+}
+
+// Creates the missing interface, then configures it like an existing one
+async fn bring_up(wg: &dyn WgBackend, interface: &InterfaceModel) -> Result<()> {
+    let link = WgLinkConfig {
+        addresses: ipam::parse_networks(&interface.address)?,
+        mtu: interface.mtu.map(|mtu| mtu as u32),
+    };
+    wg.add_interface(&interface.name, &link).await?;
+    set_interface(wg, interface).await
+}
+
+async fn set_interface(wg: &dyn WgBackend, interface: &InterfaceModel) -> Result<()> {
+    let config = WgInterfaceConfig {
+        private_key: interface.privkey.clone(),
+        listen_port: interface.port as u16,
+        fwmark: interface.fwmark.map(|fwmark| fwmark as u32),
+    };
+    wg.set_interface(&interface.name, &config).await
+}
+
+fn compare<E: ToString, A: ToString>(fields: &mut Vec<FieldDrift>, field: &'static str, expected: &E, actual: &A) {
+    let (expected, actual) = (expected.to_string(), actual.to_string());
+    if expected != actual {
+        fields.push(FieldDrift { field, expected, actual });
+    }
+}
+
+fn format_option<T: ToString>(value: Option<T>) -> String {
+    value.map_or("off".to_string(), |value| value.to_string())
+}
+
+// allowed ips compared as sets, wg does not keep them in the configured order
+fn format_ips(ips: &[IpNet]) -> String {
+    let ips: BTreeSet<String> = ips.iter().map(ToString::to_string).collect();
+    ips.into_iter().collect::<Vec<_>>().join(", ")
+}
+
+fn psk_state(preshared_key: &Option<String>) -> String {
+    if preshared_key.is_some() { "set" } else { "unset" }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::wireguard::FakeWg;
+    use crate::service::keys::WgKeyPair;
+    use crate::service::wg_dump::{InterfaceDump, PeerDump};
+
+    fn interface() -> InterfaceModel {
+        InterfaceModel {
+            id: 1,
+            name: "wg0".to_string(),
+            pubkey: "server-public=".to_string(),
+            privkey: "server-private=".to_string(),
+            address: "10.8.0.1/24".to_string(),
+            port: 51820,
+            mtu: None,
+            fwmark: None,
+            post_up: None,
+            post_down: None,
+        }
+    }
+
+    fn peer(public_key: &str, address: &str, enabled: bool) -> PeerModel {
+        PeerModel {
+            name: public_key.trim_end_matches('=').to_string(),
+            enabled,
+            public_key: public_key.to_string(),
+            address: address.to_string(),
+            ..PeerModel::for_tests()
+        }
+    }
+
+    fn live_peer(public_key: &str, allowed_ips: &[&str]) -> PeerDump {
+        PeerDump {
+            interface: "wg0".to_string(),
+            public_key: public_key.to_string(),
+            preshared_key: None,
+            endpoint_addr: None,
+            allowed_ips: allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+            last_handshake_at: None,
+            transfer_rx: 0,
+            transfer_tx: 0,
+            persistent_keepalive: None,
+        }
+    }
+
+    fn live(peers: Vec<PeerDump>) -> WgDump {
+        WgDump {
+            interfaces: vec![InterfaceDump {
+                interface: "wg0".to_string(),
+                private_key: "server-private=".to_string(),
+                public_key: "server-public=".to_string(),
+                listen_port: 51820,
+                fwmark: None,
+            }],
+            peers,
+        }
+    }
+
+    #[test]
+    fn in_sync() {
+        let peers = vec![peer("a=", "10.8.0.2/32, fd00::2/128", true)];
+        let dump = live(vec![live_peer("a=", &["fd00::2/128", "10.8.0.2/32"])]);
+        let report = DriftReport::new(&interface(), &peers, Some(&dump));
+        assert!(report.in_sync, "{report:?}");
+    }
+
+    #[test]
+    fn missing_extra_and_mismatched() {
+        let mut with_psk = peer("c=", "10.8.0.4/32", true);
+        with_psk.preshared_key = Some("psk=".to_string());
+        let disabled = peer("d=", "10.8.0.5/32", false);
+        let peers = vec![
+            peer("a=", "10.8.0.2/32", true),
+            peer("b=", "10.8.0.3/32", true),
+            with_psk,
+            disabled.clone(),
+        ];
+        let mut live_c = live_peer("c=", &["10.8.0.4/32"]);
+        live_c.persistent_keepalive = Some(25);
+        let mut dump = live(vec![
+            live_peer("b=", &["10.8.0.99/32"]),
+            live_c,
+            live_peer("d=", &["10.8.0.5/32"]),
+            live_peer("stranger=", &["10.8.0.6/32"]),
+        ]);
+        dump.interfaces[0].listen_port = 51821;
+
+        let report = DriftReport::new(&interface(), &peers, Some(&dump));
+        assert!(!report.in_sync);
+        assert_eq!(report.settings[0].field, "listen_port");
+        assert_eq!(report.missing.iter().map(|p| p.public_key.as_str()).collect::<Vec<_>>(), vec!["a="]);
+        let extra: Vec<_> = report.extra.iter().map(|p| (p.public_key.as_str(), p.peer_id)).collect();
+        assert_eq!(extra, vec![("d=", Some(disabled.id)), ("stranger=", None)]);
+
+        assert_eq!(report.mismatched.len(), 2);
+        assert_eq!(report.mismatched[0].peer.public_key, "b=");
+        assert_eq!(report.mismatched[0].fields[0].field, "allowed_ips");
+        let fields: Vec<_> = report.mismatched[1].fields.iter().map(|f| f.field).collect();
+        assert_eq!(fields, vec!["preshared_key", "persistent_keepalive"]);
+        // the key itself never ends up in the report
+        assert_eq!(report.mismatched[1].fields[0].expected, "set");
+    }
+
+    #[test]
+    fn interface_down() {
+        let peers = vec![peer("a=", "10.8.0.2/32", true), peer("b=", "10.8.0.3/32", false)];
+        let report = DriftReport::new(&interface(), &peers, None);
+        assert!(!report.in_sync);
+        assert!(!report.interface_up);
+        assert_eq!(report.missing.len(), 1);
+        assert!(report.extra.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bring_up_creates_the_interface() {
+        let wg = FakeWg::default();
+        let keypair = WgKeyPair::generate();
+        let interface = InterfaceModel {
+            pubkey: keypair.public_key.clone(),
+            privkey: keypair.private_key,
+            ..interface()
+        };
+        assert!(set_interface(&wg, &interface).await.is_err());
+        bring_up(&wg, &interface).await.unwrap();
+
+        let dump = wg.show("wg0").await.unwrap();
+        let report = DriftReport::new(&interface, &[], Some(&dump));
+        assert!(report.in_sync, "{report:?}");
+        // the link exists now, a second attempt does not create another
+        assert!(bring_up(&wg, &interface).await.is_err());
+    }
+}
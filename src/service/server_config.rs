@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use serde::Serialize;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::domain::models::interface::InterfaceModel;
+use crate::errors::{BaseError, Result};
+use crate::service::peer::{apply_error_source, interface_peers};
+use crate::service::wg_config::{render_server_config, render_sync_config};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// The server side wg-quick config of an interface, rendered from the database. Installed
+// next to wg-quick's own files, a reboot brings back the interface the database describes.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppliedConfig {
+    pub interface_id: i32,
+    pub interface: String,
+    // where the config was written, None when no config directory is set
+    pub path: Option<String>,
+    pub peers: usize,
+}
+
+pub async fn render(state: &AppState, interface: &InterfaceModel) -> Result<String> {
+    let peers = interface_peers(&state.pool, interface.id).await?;
+    Ok(render_server_config(interface, &peers))
+}
+
+// Writes the config to `config_dir` when given, then loads it into the live interface with
+// `wg syncconf`, which leaves the sessions of unchanged peers alone
+pub async fn apply(
+    state: &AppState,
+    interface: &InterfaceModel,
+    config_dir: Option<&str>,
+) -> Result<AppliedConfig> {
+    let peers = interface_peers(&state.pool, interface.id).await?;
+    let path = match config_dir {
+        Some(dir) => {
+            let path = Path::new(dir).join(format!("{}.conf", interface.name));
+            write_atomic(&path, &render_server_config(interface, &peers))
+                .await
+                .map_err(|err| BaseError::Execution {
+                    source: format!("can not write {}: {err}", path.display()),
+                })?;
+            Some(path.display().to_string())
+        }
+        None => None,
+    };
+    state
+        .wg
+        .syncconf(&interface.name, &render_sync_config(interface, &peers))
+        .await
+        .map_err(|err| BaseError::WgApply { source: apply_error_source(err) })?;
+    Ok(AppliedConfig {
+        interface_id: interface.id,
+        interface: interface.name.clone(),
+        path,
+        peers: peers.iter().filter(|peer| peer.enabled).count(),
+    })
+}
+
+// Writes next to the target and renames over it, so wg-quick never reads a half written
+// file. The config holds the private key, only its owner may read it.
+async fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let tmp = path.with_extension("conf.tmp");
+    // a leftover of an interrupted write could have other permissions
+    match fs::remove_file(&tmp).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .await?;
+    file.write_all(contents.as_bytes()).await?;
+    file.sync_all().await?;
+    fs::rename(&tmp, path).await
+}
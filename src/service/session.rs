@@ -0,0 +1,180 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use uuid::Uuid;
+
+use crate::domain::models::peer::PeerError;
+use crate::errors::{BaseError, Result};
+use crate::infra::errors::InfraError;
+use crate::infra::peer_repository;
+use crate::infra::session_repository::{self, NewPeerSession, PeerSession, PeerSessionForm, SessionChange};
+use crate::service::wg_dump::PeerDump;
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Connection history of the peers, derived by the usage poller from the handshakes. A session
+// lasts while the handshakes of the peer come at most the idle threshold apart, and ends at
+// its last handshake.
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+// What a poll does to the sessions of a peer, given its open session and the traffic it had
+// since the previous poll
+pub fn changes(
+    peer_id: Uuid,
+    open: Option<&PeerSession>,
+    live: Option<&PeerDump>,
+    (rx, tx): (i64, i64),
+    gap: TimeDelta,
+    now: NaiveDateTime,
+) -> Vec<SessionChange> {
+    let handshake = live
+        .and_then(|live| live.last_handshake_at)
+        .map(|at| at.naive_utc())
+        .filter(|at| now - *at <= gap);
+    let endpoint_addr = live.and_then(|live| live.endpoint_addr).map(|addr| addr.to_string());
+    let start = |at: NaiveDateTime, rx: i64, tx: i64| {
+        SessionChange::Open(NewPeerSession {
+            peer_id,
+            started_at: at,
+            last_handshake_at: at,
+            endpoint_addr: endpoint_addr.clone(),
+            rx,
+            tx,
+        })
+    };
+    let close = |session: &PeerSession, rx: i64, tx: i64| {
+        SessionChange::Update(
+            session.id,
+            PeerSessionForm {
+                last_handshake_at: session.last_handshake_at,
+                ended_at: Some(session.last_handshake_at),
+                endpoint_addr: None,
+                rx: session.rx + rx,
+                tx: session.tx + tx,
+            },
+        )
+    };
+    match (open, handshake) {
+        (Some(session), Some(at)) if at - session.last_handshake_at <= gap => {
+            let roamed = endpoint_addr.is_some() && endpoint_addr != session.endpoint_addr;
+            if at <= session.last_handshake_at && rx == 0 && tx == 0 && !roamed {
+                return vec![];
+            }
+            vec![SessionChange::Update(
+                session.id,
+                PeerSessionForm {
+                    last_handshake_at: at.max(session.last_handshake_at),
+                    ended_at: None,
+                    endpoint_addr,
+                    rx: session.rx + rx,
+                    tx: session.tx + tx,
+                },
+            )]
+        }
+        // back after a gap, the traffic belongs to the new session
+        (Some(session), Some(at)) => vec![close(session, 0, 0), start(at, rx, tx)],
+        (Some(session), None) => vec![close(session, rx, tx)],
+        (None, Some(at)) => vec![start(at, rx, tx)],
+        (None, None) => vec![],
+    }
+}
+
+// The latest sessions of a peer, the newest first
+pub async fn list(state: &AppState, peer_id: Uuid, limit: Option<i64>) -> Result<Vec<PeerSession>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(BaseError::BodyParsingError(format!("limit must be between 1 and {MAX_LIMIT}")));
+    }
+    peer_repository::read(&state.pool, peer_id)
+        .await
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => PeerError::NotFound(peer_id),
+            _ => PeerError::InfraError(db_error),
+        })?;
+    session_repository::list(&state.pool, peer_id, limit)
+        .await
+        .map_err(|db_error| PeerError::InfraError(db_error).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    const GAP: TimeDelta = TimeDelta::seconds(900);
+
+    fn at(seconds: i64) -> NaiveDateTime {
+        DateTime::from_timestamp(1_720_000_000 + seconds, 0).unwrap().naive_utc()
+    }
+
+    fn live(handshake: Option<i64>, endpoint: &str) -> PeerDump {
+        PeerDump {
+            interface: "wg0".to_string(),
+            public_key: "a=".to_string(),
+            preshared_key: None,
+            endpoint_addr: Some(endpoint.parse().unwrap()),
+            allowed_ips: vec![],
+            last_handshake_at: handshake.map(|seconds| at(seconds).and_utc()),
+            transfer_rx: 0,
+            transfer_tx: 0,
+            persistent_keepalive: None,
+        }
+    }
+
+    fn open(last_handshake: i64) -> PeerSession {
+        PeerSession {
+            id: 7,
+            peer_id: Uuid::nil(),
+            started_at: at(0),
+            last_handshake_at: at(last_handshake),
+            ended_at: None,
+            endpoint_addr: Some("203.0.113.5:51820".to_string()),
+            rx: 100,
+            tx: 10,
+        }
+    }
+
+    #[test]
+    fn first_handshake_opens_a_session() {
+        let changes = changes(Uuid::nil(), None, Some(&live(Some(0), "203.0.113.5:51820")), (5, 1), GAP, at(30));
+        let [SessionChange::Open(session)] = changes.as_slice() else { panic!("expected a new session") };
+        assert_eq!((session.started_at, session.rx, session.tx), (at(0), 5, 1));
+        assert_eq!(session.endpoint_addr.as_deref(), Some("203.0.113.5:51820"));
+
+        // a stale handshake, e.g. after a restart of the server, is no connection
+        assert!(super::changes(Uuid::nil(), None, Some(&live(Some(0), "203.0.113.5:51820")), (0, 0), GAP, at(901)).is_empty());
+        assert!(super::changes(Uuid::nil(), None, Some(&live(None, "203.0.113.5:51820")), (0, 0), GAP, at(0)).is_empty());
+    }
+
+    #[test]
+    fn handshakes_extend_the_session() {
+        let session = open(120);
+        let changes = changes(Uuid::nil(), Some(&session), Some(&live(Some(240), "198.51.100.7:4500")), (50, 5), GAP, at(250));
+        let [SessionChange::Update(7, form)] = changes.as_slice() else { panic!("expected an update") };
+        assert_eq!((form.last_handshake_at, form.ended_at, form.rx, form.tx), (at(240), None, 150, 15));
+        assert_eq!(form.endpoint_addr.as_deref(), Some("198.51.100.7:4500"));
+
+        // nothing new
+        assert!(super::changes(Uuid::nil(), Some(&session), Some(&live(Some(120), "203.0.113.5:51820")), (0, 0), GAP, at(250)).is_empty());
+    }
+
+    #[test]
+    fn silence_or_a_gap_ends_the_session() {
+        let session = open(120);
+        let changes = changes(Uuid::nil(), Some(&session), Some(&live(Some(120), "203.0.113.5:51820")), (0, 0), GAP, at(1021));
+        let [SessionChange::Update(7, form)] = changes.as_slice() else { panic!("expected the session to end") };
+        assert_eq!(form.ended_at, Some(at(120)));
+
+        // disabled, or the interface went down
+        let changes = super::changes(Uuid::nil(), Some(&session), None, (0, 0), GAP, at(130));
+        assert!(matches!(changes.as_slice(), [SessionChange::Update(7, form)] if form.ended_at == Some(at(120))));
+
+        // the poller missed the end, the new handshake starts the next session
+        let changes = super::changes(Uuid::nil(), Some(&session), Some(&live(Some(1500), "203.0.113.5:51820")), (8, 0), GAP, at(1510));
+        let [SessionChange::Update(7, closed), SessionChange::Open(next)] = changes.as_slice() else {
+            panic!("expected a closed and a new session")
+        };
+        assert_eq!((closed.ended_at, closed.rx), (Some(at(120)), 100));
+        assert_eq!((next.started_at, next.rx), (at(1500), 8));
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::domain::models::peer::PeerError;
+use crate::errors::{BaseError, Result};
+use crate::infra::errors::InfraError;
+use crate::infra::peer_repository;
+use crate::infra::traffic_repository::{self, TrafficBucket, TrafficSource};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Bandwidth history of the peers. The usage poller stores what every peer moved since the
+// previous poll, this module folds those samples into hourly and daily buckets, drops what is
+// past its retention, and sums series from the resolution that matches the requested step.
+
+const ROLL_UP_EVERY: Duration = Duration::from_secs(5 * 60);
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+// bounds the size of a response, a longer range needs a larger step
+const MAX_POINTS: i64 = 2000;
+// a year of traffic in one point is as coarse as a series gets
+const MAX_STEP: i64 = 366 * DAY;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrafficRetention {
+    pub samples: TimeDelta,
+    pub hourly: TimeDelta,
+    pub daily: TimeDelta,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrafficSeries {
+    pub peer_id: Uuid,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    // seconds per point
+    pub step: i64,
+    pub total_rx: i64,
+    pub total_tx: i64,
+    pub points: Vec<TrafficPoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrafficPoint {
+    pub at: DateTime<Utc>,
+    pub rx: i64,
+    pub tx: i64,
+    // average bytes per second over the step
+    pub rx_rate: f64,
+    pub tx_rate: f64,
+}
+
+pub fn spawn_rollups(state: AppState, retention: TrafficRetention) {
+    tokio::spawn(async move {
+        let mut ticker = interval(ROLL_UP_EVERY);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = roll_up(&state, retention).await {
+                warn!("traffic roll up failed: {err:?}");
+            }
+        }
+    });
+}
+
+async fn roll_up(state: &AppState, retention: TrafficRetention) -> std::result::Result<(), InfraError> {
+    // rolled up first, so no sample is deleted before it is counted in its hour
+    let (hours, days) = traffic_repository::roll_up(&state.pool).await?;
+    let now = Utc::now().naive_utc();
+    let pruned = traffic_repository::prune(
+        &state.pool,
+        now - retention.samples,
+        now - retention.hourly,
+        now - retention.daily,
+    )
+    .await?;
+    debug!("traffic roll up: {hours} hourly and {days} daily bucket(s) updated, {pruned} row(s) pruned");
+    Ok(())
+}
+
+// Traffic of a peer between `from` and `to`, the last 24 hours by default, with one point per
+// step and zeros where the peer was idle. Steps of whole days are summed from the daily
+// buckets and steps of whole hours from the hourly ones, so they reach back as far as those
+// are kept. Other steps need the raw samples.
+pub async fn series(
+    state: &AppState,
+    peer_id: Uuid,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    step: Option<&str>,
+) -> Result<TrafficSeries> {
+    let step = step.map_or(Ok(HOUR), parse_step)?;
+    let to = to.unwrap_or_else(Utc::now);
+    let from = match from {
+        Some(from) => from,
+        None => to
+            .checked_sub_signed(TimeDelta::days(1))
+            .ok_or_else(|| invalid("to is out of range"))?,
+    };
+    if from >= to {
+        return Err(invalid("from must be before to"));
+    }
+    let to = to
+        .checked_add_signed(TimeDelta::seconds(step - 1))
+        .ok_or_else(|| invalid("to is out of range"))?;
+    let (from, to) = (align_down(from, step), align_down(to, step));
+    if (to - from).num_seconds() / step > MAX_POINTS {
+        return Err(invalid(&format!("more than {MAX_POINTS} points, use a larger step")));
+    }
+
+    peer_repository::read(&state.pool, peer_id)
+        .await
+        .map_err(|db_error| match db_error {
+            InfraError::NotFound => PeerError::NotFound(peer_id),
+            _ => PeerError::InfraError(db_error),
+        })?;
+    let source = match step {
+        step if step % DAY == 0 => TrafficSource::Daily,
+        step if step % HOUR == 0 => TrafficSource::Hourly,
+        _ => TrafficSource::Samples,
+    };
+    let buckets = traffic_repository::series(
+        &state.pool,
+        peer_id,
+        source,
+        from.naive_utc(),
+        to.naive_utc(),
+        step,
+    )
+    .await
+    .map_err(PeerError::InfraError)?;
+
+    let points = fill(from, to, step, &buckets);
+    Ok(TrafficSeries {
+        peer_id,
+        from,
+        to,
+        step,
+        total_rx: points.iter().map(|point| point.rx).sum(),
+        total_tx: points.iter().map(|point| point.tx).sum(),
+        points,
+    })
+}
+
+// "300", "300s", "5m", "1h" or "1d", up to MAX_STEP
+fn parse_step(step: &str) -> Result<i64> {
+    let (number, unit) = match step.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => step.split_at(index),
+        None => (step, "s"),
+    };
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => HOUR,
+        "d" => DAY,
+        _ => return Err(invalid(&format!("invalid step `{step}`, e.g. 300, 5m, 1h or 1d"))),
+    };
+    match number.parse::<i64>().ok().filter(|number| *number > 0) {
+        Some(number) => number
+            .checked_mul(unit)
+            .filter(|step| *step <= MAX_STEP)
+            .ok_or_else(|| invalid(&format!("step `{step}` is longer than 366 days"))),
+        None => Err(invalid(&format!("invalid step `{step}`, e.g. 300, 5m, 1h or 1d"))),
+    }
+}
+
+// Buckets are aligned to the epoch, as in the query
+fn align_down(at: DateTime<Utc>, step: i64) -> DateTime<Utc> {
+    let seconds = at.timestamp().div_euclid(step) * step;
+    DateTime::from_timestamp(seconds, 0).unwrap_or(at)
+}
+
+fn fill(from: DateTime<Utc>, to: DateTime<Utc>, step: i64, buckets: &[TrafficBucket]) -> Vec<TrafficPoint> {
+    let mut buckets = buckets.iter().peekable();
+    let mut points = vec![];
+    let mut at = from;
+    while at < to {
+        let (rx, tx) = match buckets.peek() {
+            Some(bucket) if bucket.bucket_start == at.naive_utc() => {
+                let bucket = buckets.next().unwrap();
+                (bucket.rx, bucket.tx)
+            }
+            _ => (0, 0),
+        };
+        points.push(TrafficPoint {
+            at,
+            rx,
+            tx,
+            rx_rate: rx as f64 / step as f64,
+            tx_rate: tx as f64 / step as f64,
+        });
+        at += TimeDelta::seconds(step);
+    }
+    points
+}
+
+fn invalid(message: &str) -> BaseError {
+    BaseError::BodyParsingError(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(parse_step("300").unwrap(), 300);
+        assert_eq!(parse_step("5m").unwrap(), 300);
+        assert_eq!(parse_step("1h").unwrap(), HOUR);
+        assert_eq!(parse_step("7d").unwrap(), 7 * DAY);
+        assert!(parse_step("0").is_err());
+        assert!(parse_step("h").is_err());
+        assert!(parse_step("1w").is_err());
+        assert!(parse_step("-5m").is_err());
+        assert_eq!(parse_step("366d").unwrap(), MAX_STEP);
+        assert!(parse_step("367d").is_err());
+        assert!(parse_step("10000000000000000").is_err());
+        // would overflow i64 once multiplied
+        assert!(parse_step("99999999999999999d").is_err());
+        assert!(parse_step("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn alignment() {
+        assert_eq!(align_down(at(DAY + 3 * HOUR + 59), HOUR), at(DAY + 3 * HOUR));
+        assert_eq!(align_down(at(DAY + 3 * HOUR), DAY), at(DAY));
+    }
+
+    #[test]
+    fn idle_steps_are_zero() {
+        let buckets = vec![
+            TrafficBucket { bucket_start: at(HOUR).naive_utc(), rx: 3600, tx: 360 },
+            TrafficBucket { bucket_start: at(3 * HOUR).naive_utc(), rx: 7200, tx: 0 },
+        ];
+        let points = fill(at(0), at(4 * HOUR), HOUR, &buckets);
+        let rx: Vec<_> = points.iter().map(|point| point.rx).collect();
+        assert_eq!(rx, vec![0, 3600, 0, 7200]);
+        assert_eq!(points[1].rx_rate, 1.0);
+        assert_eq!(points[1].tx_rate, 0.1);
+        assert_eq!(points[3].at, at(3 * HOUR));
+    }
+}
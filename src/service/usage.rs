@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::domain::models::interface::InterfaceError;
+use crate::domain::models::peer::PeerError;
+use crate::errors::Result;
+use crate::infra::peer_repository::{self, PeerCounters, PeerUsageForm};
+use crate::infra::session_repository::{self, PeerSession};
+use crate::infra::traffic_repository::NewTrafficSample;
+use crate::infra::wg_if_repository;
+use crate::service::{quota, session};
+use crate::service::wg_dump::{PeerDump, ALL_INTERFACES};
+use crate::utils::middlewares::mw_ctx::AppState;
+
+// Copies the traffic counters, handshakes and endpoints of the live interfaces into the
+// peers table, so the API serves usage without a round trip to the kernel, keeps the
+// connection sessions of the peers and enforces their quotas on the fresh totals.
+
+pub fn spawn_poller(state: AppState, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = interval(every);
+        // a slow `wg show` delays the next poll instead of firing a burst of them
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match poll(&state).await {
+                Ok(updated) => debug!("usage poll: {updated} peer(s) updated"),
+                Err(err) => {
+                    warn!("usage poll failed: {err:?}");
+                    continue;
+                }
+            }
+            if let Err(err) = quota::enforce(&state).await {
+                warn!("quota enforcement failed: {err:?}");
+            }
+        }
+    });
+}
+
+// One snapshot of every interface, stored in one transaction
+pub async fn poll(state: &AppState) -> Result<usize> {
+    let dump = state.wg.show(ALL_INTERFACES).await?;
+    let interfaces: HashMap<i32, String> = wg_if_repository::get_all(&state.pool)
+        .await
+        .map_err(InterfaceError::InfraError)?
+        .into_iter()
+        .map(|interface| (interface.id, interface.name))
+        .collect();
+    let stored = peer_repository::get_counters(&state.pool)
+        .await
+        .map_err(PeerError::InfraError)?;
+
+    let open: HashMap<Uuid, PeerSession> = session_repository::get_open(&state.pool)
+        .await
+        .map_err(PeerError::InfraError)?
+        .into_iter()
+        .map(|session| (session.peer_id, session))
+        .collect();
+
+    let live: HashMap<(&str, &str), &PeerDump> = dump
+        .peers
+        .iter()
+        .map(|peer| ((peer.interface.as_str(), peer.public_key.as_str()), peer))
+        .collect();
+    let now = Utc::now().naive_utc();
+    let (mut usages, mut samples, mut sessions) = (vec![], vec![], vec![]);
+    for peer in &stored {
+        let Some(interface) = interfaces.get(&peer.interface_id) else {
+            continue;
+        };
+        let live = live.get(&(interface.as_str(), peer.public_key.as_str())).copied();
+        let usage = usage_update(peer, live, now);
+        let sample = usage.as_ref().and_then(|usage| traffic_sample(peer, usage, now));
+        let traffic = sample.as_ref().map_or((0, 0), |sample| (sample.rx, sample.tx));
+        sessions.extend(session::changes(peer.id, open.get(&peer.id), live, traffic, state.presence.idle, now));
+        usages.extend(usage.map(|usage| (peer.id, usage)));
+        samples.extend(sample);
+    }
+    peer_repository::update_usage(&state.pool, usages, samples, sessions)
+        .await
+        .map_err(|db_error| PeerError::InfraError(db_error).into())
+}
+
+// What to store for a peer after a poll, None when nothing changes. A peer missing from the
+// dump, disabled or on an interface that is down, has no kernel counters any more: the next
+// time it shows up they start from zero.
+fn usage_update(
+    stored: &PeerCounters,
+    live: Option<&PeerDump>,
+    now: NaiveDateTime,
+) -> Option<PeerUsageForm> {
+    let Some(live) = live else {
+        return (stored.kernel_rx != 0 || stored.kernel_tx != 0).then_some(PeerUsageForm {
+            transfer_rx: stored.transfer_rx,
+            transfer_tx: stored.transfer_tx,
+            kernel_rx: 0,
+            kernel_tx: 0,
+            last_handshake_at: None,
+            endpoint_addr: None,
+            usage_updated_at: None,
+        });
+    };
+    let kernel_rx = live.transfer_rx as i64;
+    let kernel_tx = live.transfer_tx as i64;
+    Some(PeerUsageForm {
+        transfer_rx: accumulate(stored.transfer_rx, stored.kernel_rx, kernel_rx),
+        transfer_tx: accumulate(stored.transfer_tx, stored.kernel_tx, kernel_tx),
+        kernel_rx,
+        kernel_tx,
+        last_handshake_at: live.last_handshake_at.map(|at| at.naive_utc()),
+        endpoint_addr: live.endpoint_addr.map(|addr| addr.to_string()),
+        usage_updated_at: Some(now),
+    })
+}
+
+// The traffic since the previous poll, None for idle or missing peers
+fn traffic_sample(
+    stored: &PeerCounters,
+    usage: &PeerUsageForm,
+    now: NaiveDateTime,
+) -> Option<NewTrafficSample> {
+    let rx = usage.transfer_rx - stored.transfer_rx;
+    let tx = usage.transfer_tx - stored.transfer_tx;
+    (rx > 0 || tx > 0).then_some(NewTrafficSample {
+        peer_id: stored.id,
+        sampled_at: now,
+        rx,
+        tx,
+    })
+}
+
+// A reading below the previous one means the counter restarted from zero in between.
+// Traffic from the last poll up to the restart is lost, a short interval keeps that small.
+fn accumulate(total: i64, last_kernel: i64, kernel: i64) -> i64 {
+    if kernel >= last_kernel {
+        total + (kernel - last_kernel)
+    } else {
+        total + kernel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    fn stored(transfer_rx: i64, kernel_rx: i64) -> PeerCounters {
+        PeerCounters {
+            id: Uuid::new_v4(),
+            interface_id: 1,
+            public_key: "a=".to_string(),
+            transfer_rx,
+            transfer_tx: 0,
+            kernel_rx,
+            kernel_tx: 0,
+        }
+    }
+
+    fn live(transfer_rx: u64) -> PeerDump {
+        PeerDump {
+            interface: "wg0".to_string(),
+            public_key: "a=".to_string(),
+            preshared_key: None,
+            endpoint_addr: Some("203.0.113.5:51820".parse().unwrap()),
+            allowed_ips: vec![],
+            last_handshake_at: DateTime::from_timestamp(1_720_000_000, 0),
+            transfer_rx,
+            transfer_tx: 0,
+            persistent_keepalive: None,
+        }
+    }
+
+    #[test]
+    fn counters_accumulate() {
+        assert_eq!(accumulate(1000, 400, 900), 1500);
+        assert_eq!(accumulate(1000, 400, 400), 1000);
+        // the interface restarted and the counter began again
+        assert_eq!(accumulate(1000, 400, 150), 1150);
+        // past what an Int4 column could hold
+        assert_eq!(accumulate(3 << 30, 0, 3 << 30), 6 << 30);
+    }
+
+    #[test]
+    fn live_peer() {
+        let now = Utc::now().naive_utc();
+        let usage = usage_update(&stored(1000, 400), Some(&live(900)), now).unwrap();
+        assert_eq!(usage.transfer_rx, 1500);
+        assert_eq!(usage.kernel_rx, 900);
+        assert_eq!(usage.endpoint_addr.as_deref(), Some("203.0.113.5:51820"));
+        assert!(usage.last_handshake_at.is_some());
+        assert_eq!(usage.usage_updated_at, Some(now));
+    }
+
+    #[test]
+    fn missing_peer_resets_the_baseline() {
+        let now = Utc::now().naive_utc();
+        let usage = usage_update(&stored(1000, 400), None, now).unwrap();
+        assert_eq!((usage.transfer_rx, usage.kernel_rx), (1000, 0));
+        assert_eq!(usage.usage_updated_at, None);
+        assert_eq!(usage_update(&stored(1000, 0), None, now), None);
+
+        // enabled again, the fresh counter is all new traffic
+        let usage = usage_update(&stored(1000, 0), Some(&live(50)), now).unwrap();
+        assert_eq!(usage.transfer_rx, 1050);
+    }
+
+    #[test]
+    fn samples_hold_the_traffic_since_the_last_poll() {
+        let now = Utc::now().naive_utc();
+        let peer = stored(1000, 400);
+        let usage = usage_update(&peer, Some(&live(900)), now).unwrap();
+        let sample = traffic_sample(&peer, &usage, now).unwrap();
+        assert_eq!((sample.rx, sample.tx, sample.sampled_at), (500, 0, now));
+
+        let idle = usage_update(&peer, Some(&live(400)), now).unwrap();
+        assert_eq!(traffic_sample(&peer, &idle, now), None);
+        let missing = usage_update(&peer, None, now).unwrap();
+        assert_eq!(traffic_sample(&peer, &missing, now), None);
+    }
+}
@@ -0,0 +1,342 @@
+use std::net::Ipv6Addr;
+
+use crate::domain::models::interface::InterfaceModel;
+use crate::domain::models::peer::{PeerError, PeerModel};
+use crate::errors::{BaseError, Result};
+use crate::service::peer::wg_peer_config;
+
+// One `[Interface]` or `[Peer]` section of a wg / wg-quick config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigSection {
+    pub name: String,
+    // the comment right above the section header or before its first key, e.g. `# laptop`
+    pub comment: Option<String>,
+    pub entries: Vec<(String, String)>,
+}
+
+impl ConfigSection {
+    // keys are case insensitive, like in wg itself
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // every value of a key wg-quick allows more than once, e.g. PostUp
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+}
+
+// Renders the wg-quick file a client imports to connect to `interface` as `peer`
+pub fn render_client_config(
+    peer: &PeerModel,
+    interface: &InterfaceModel,
+    endpoint_host: &str,
+    dns: Option<&str>,
+) -> std::result::Result<String, PeerError> {
+    let private_key = peer
+        .private_key
+        .as_ref()
+        .ok_or(PeerError::PrivateKeyUnknown(peer.id))?;
+    let mut lines = vec![
+        "[Interface]".to_string(),
+        format!("PrivateKey = {private_key}"),
+        format!("Address = {}", peer.address),
+    ];
+    if let Some(dns) = dns {
+        lines.push(format!("DNS = {dns}"));
+    }
+    if let Some(mtu) = interface.mtu {
+        lines.push(format!("MTU = {mtu}"));
+    }
+    lines.push(String::new());
+    lines.push("[Peer]".to_string());
+    lines.push(format!("PublicKey = {}", interface.pubkey));
+    if let Some(preshared_key) = &peer.preshared_key {
+        lines.push(format!("PresharedKey = {preshared_key}"));
+    }
+    lines.push(format!("Endpoint = {}", endpoint(endpoint_host, interface.port)));
+    lines.push(format!("AllowedIPs = {}", peer.allowed_ips));
+    if peer.persistent_keepalive > 0 {
+        lines.push(format!("PersistentKeepalive = {}", peer.persistent_keepalive));
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+// Renders the wg-quick file of the server side of `interface`, e.g. /etc/wireguard/wg0.conf,
+// with every enabled peer named by the comment above its section
+pub fn render_server_config(interface: &InterfaceModel, peers: &[PeerModel]) -> String {
+    render_server(interface, peers, true)
+}
+
+// The same config without the wg-quick only keys, which `wg syncconf` refuses (see `wg-quick strip`)
+pub fn render_sync_config(interface: &InterfaceModel, peers: &[PeerModel]) -> String {
+    render_server(interface, peers, false)
+}
+
+fn render_server(interface: &InterfaceModel, peers: &[PeerModel], wg_quick: bool) -> String {
+    let mut lines = vec!["[Interface]".to_string()];
+    if wg_quick {
+        lines.push(format!("Address = {}", interface.address));
+    }
+    lines.push(format!("ListenPort = {}", interface.port));
+    lines.push(format!("PrivateKey = {}", interface.privkey));
+    if let Some(fwmark) = interface.fwmark {
+        lines.push(format!("FwMark = {fwmark}"));
+    }
+    if wg_quick {
+        if let Some(mtu) = interface.mtu {
+            lines.push(format!("MTU = {mtu}"));
+        }
+        if let Some(post_up) = &interface.post_up {
+            lines.push(format!("PostUp = {post_up}"));
+        }
+        if let Some(post_down) = &interface.post_down {
+            lines.push(format!("PostDown = {post_down}"));
+        }
+    }
+    // oldest first, so the installed file only changes where the peers do
+    let mut enabled: Vec<&PeerModel> = peers.iter().filter(|peer| peer.enabled).collect();
+    enabled.sort_by_key(|peer| peer.created_at);
+    for peer in enabled {
+        let config = wg_peer_config(peer);
+        lines.push(String::new());
+        lines.push(format!("# {}", peer.name.replace(['\n', '\r'], " ")));
+        lines.push("[Peer]".to_string());
+        lines.push(format!("PublicKey = {}", config.public_key));
+        if let Some(preshared_key) = &config.preshared_key {
+            lines.push(format!("PresharedKey = {preshared_key}"));
+        }
+        let allowed_ips: Vec<String> = config.allowed_ips.iter().map(ToString::to_string).collect();
+        lines.push(format!("AllowedIPs = {}", allowed_ips.join(", ")));
+    }
+    lines.join("\n") + "\n"
+}
+
+// WireGuard apps name the tunnel after the file, and only accept what wg-quick accepts
+pub fn config_file_name(peer_name: &str) -> String {
+    let name: String = peer_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "_=+.-".contains(c) { c } else { '-' })
+        .take(15)
+        .collect();
+    let name = name.trim_matches('-');
+    format!("{}.conf", if name.is_empty() { "wg0" } else { name })
+}
+
+// Splits a config file into its sections, dropping blank lines and all but the section comments
+pub fn parse_config(text: &str) -> Result<Vec<ConfigSection>> {
+    let mut sections: Vec<ConfigSection> = vec![];
+    let mut comment: Option<String> = None;
+    for (index, line) in text.lines().enumerate() {
+        let (line, line_comment) = match line.split_once('#') {
+            Some((line, line_comment)) => (line.trim(), Some(line_comment)),
+            None => (line.trim(), None),
+        };
+        if line.is_empty() {
+            // a whole line comment, the last one before a section or its first key names it
+            let text = line_comment.map(|c| c.trim_matches(|c: char| c == '#' || c.is_whitespace()));
+            if let Some(text) = text.filter(|text| !text.is_empty()) {
+                match sections.last_mut() {
+                    Some(section) if section.entries.is_empty() && section.comment.is_none() => {
+                        section.comment = Some(text.to_string())
+                    }
+                    _ => comment = Some(text.to_string()),
+                }
+            }
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push(ConfigSection {
+                name: name.trim().to_string(),
+                comment: comment.take(),
+                entries: vec![],
+            });
+            continue;
+        }
+        let entry = line.split_once('=').map(|(k, v)| (k.trim().to_string(), v.trim().to_string()));
+        match (sections.last_mut(), entry) {
+            (Some(section), Some(entry)) => section.entries.push(entry),
+            _ => {
+                return Err(BaseError::BodyParsingError(format!(
+                    "config line {}: expected a [section] or a key = value pair",
+                    index + 1
+                )))
+            }
+        }
+    }
+    Ok(sections)
+}
+
+fn endpoint(host: &str, port: i32) -> String {
+    match host.parse::<Ipv6Addr>() {
+        Ok(_) => format!("[{host}]:{port}"),
+        Err(_) => format!("{host}:{port}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> PeerModel {
+        PeerModel {
+            preshared_key: Some("psk=".to_string()),
+            ..PeerModel::for_tests()
+        }
+    }
+
+    fn interface() -> InterfaceModel {
+        InterfaceModel {
+            id: 1,
+            name: "wg0".to_string(),
+            pubkey: "server-public=".to_string(),
+            privkey: "server-private=".to_string(),
+            address: "10.8.0.1/24".to_string(),
+            port: 51820,
+            mtu: Some(1420),
+            fwmark: None,
+            post_up: None,
+            post_down: None,
+        }
+    }
+
+    #[test]
+    fn full_client_config() {
+        let conf =
+            render_client_config(&peer(), &interface(), "vpn.example.com", Some("1.1.1.1")).unwrap();
+        assert_eq!(
+            conf,
+            "[Interface]\n\
+             PrivateKey = peer-private=\n\
+             Address = 10.8.0.2/32\n\
+             DNS = 1.1.1.1\n\
+             MTU = 1420\n\
+             \n\
+             [Peer]\n\
+             PublicKey = server-public=\n\
+             PresharedKey = psk=\n\
+             Endpoint = vpn.example.com:51820\n\
+             AllowedIPs = 0.0.0.0/0, ::/0\n\
+             PersistentKeepalive = 25\n"
+        );
+    }
+
+    #[test]
+    fn optional_lines_are_omitted() {
+        let mut peer = peer();
+        peer.preshared_key = None;
+        peer.persistent_keepalive = 0;
+        let mut interface = interface();
+        interface.mtu = None;
+        let conf = render_client_config(&peer, &interface, "2001:db8::1", None).unwrap();
+        assert!(!conf.contains("DNS"));
+        assert!(!conf.contains("MTU"));
+        assert!(!conf.contains("PresharedKey"));
+        assert!(!conf.contains("PersistentKeepalive"));
+        assert!(conf.contains("Endpoint = [2001:db8::1]:51820\n"));
+    }
+
+    #[test]
+    fn parses_rendered_config() {
+        let conf = render_client_config(&peer(), &interface(), "vpn.example.com", None).unwrap();
+        let sections = parse_config(&("# laptop\n".to_string() + &conf)).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].name, "Interface");
+        assert_eq!(sections[0].comment.as_deref(), Some("laptop"));
+        assert_eq!(sections[1].comment, None);
+        assert_eq!(sections[0].get("privatekey"), Some("peer-private="));
+        assert_eq!(sections[1].get("AllowedIPs"), Some("0.0.0.0/0, ::/0"));
+        // base64 padding is part of the value
+        assert_eq!(sections[1].get("PublicKey"), Some("server-public="));
+        assert!(parse_config("PrivateKey = x").is_err());
+    }
+
+    #[test]
+    fn section_comments() {
+        let conf = "[Interface]\nPrivateKey = server=\n# not a name, the peer below has its own\n\n\
+            ### Client alice\n[Peer]\nPublicKey = alice= # inline comments are dropped\n\n\
+            [Peer]\n# bob\nPublicKey = bob=\n# trailing comment\n";
+        let sections = parse_config(conf).unwrap();
+        let comments: Vec<_> = sections.iter().map(|s| s.comment.as_deref()).collect();
+        assert_eq!(comments, vec![None, Some("Client alice"), Some("bob")]);
+        assert_eq!(sections[1].get("PublicKey"), Some("alice="));
+    }
+
+    #[test]
+    fn imported_peer_has_no_client_config() {
+        let mut peer = peer();
+        peer.private_key = None;
+        assert!(matches!(
+            render_client_config(&peer, &interface(), "vpn.example.com", None),
+            Err(PeerError::PrivateKeyUnknown(_))
+        ));
+    }
+
+    #[test]
+    fn server_config() {
+        let mut interface = interface();
+        interface.fwmark = Some(51820);
+        interface.post_up = Some("iptables -A FORWARD -i %i -j ACCEPT".to_string());
+        let mut disabled = peer();
+        disabled.enabled = false;
+        disabled.public_key = "disabled=".to_string();
+        let mut no_psk = peer();
+        no_psk.name = "phone".to_string();
+        no_psk.preshared_key = None;
+        no_psk.address = "10.8.0.3/32, fd00::3/128".to_string();
+        let peers = vec![peer(), disabled, no_psk];
+
+        assert_eq!(
+            render_server_config(&interface, &peers),
+            "[Interface]\n\
+             Address = 10.8.0.1/24\n\
+             ListenPort = 51820\n\
+             PrivateKey = server-private=\n\
+             FwMark = 51820\n\
+             MTU = 1420\n\
+             PostUp = iptables -A FORWARD -i %i -j ACCEPT\n\
+             \n\
+             # laptop\n\
+             [Peer]\n\
+             PublicKey = peer-public=\n\
+             PresharedKey = psk=\n\
+             AllowedIPs = 10.8.0.2/32\n\
+             \n\
+             # phone\n\
+             [Peer]\n\
+             PublicKey = peer-public=\n\
+             AllowedIPs = 10.8.0.3/32, fd00::3/128\n"
+        );
+
+        let sync = render_sync_config(&interface, &peers);
+        let sections = parse_config(&sync).unwrap();
+        assert_eq!(sections.len(), 3);
+        for key in ["Address", "MTU", "PostUp", "PostDown"] {
+            assert_eq!(sections[0].get(key), None);
+        }
+        assert_eq!(sections[0].get("FwMark"), Some("51820"));
+        assert_eq!(sections[2].comment.as_deref(), Some("phone"));
+    }
+
+    #[test]
+    fn repeated_keys() {
+        let sections = parse_config("[Interface]\nPostUp = a\npostup = b\n").unwrap();
+        assert_eq!(sections[0].get_all("PostUp"), vec!["a", "b"]);
+        assert!(sections[0].get_all("PostDown").is_empty());
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(config_file_name("laptop"), "laptop.conf");
+        assert_eq!(config_file_name("Alex's phone"), "Alex-s-phone.conf");
+        assert_eq!(config_file_name("a very long peer name"), "a-very-long-pee.conf");
+        assert_eq!(config_file_name("  "), "wg0.conf");
+    }
+}
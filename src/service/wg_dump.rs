@@ -0,0 +1,237 @@
+use std::net::SocketAddr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use ipnet::IpNet;
+use serde::Serialize;
+
+use crate::domain::models::peer::{PeerPresence, PresenceThresholds};
+use crate::errors::{BaseError, Result};
+
+pub use crate::domain::models::interface::ALL_INTERFACES;
+
+// Parses the tab separated output of `wg show <interface> dump`.
+// The first line describes the interface, every following line one of its peers:
+//   private-key  public-key  listen-port  fwmark
+//   public-key  preshared-key  endpoint  allowed-ips  latest-handshake  transfer-rx  transfer-tx  persistent-keepalive
+// `wg show all dump` prints the same lines, each prefixed with the interface name.
+
+const INTERFACE_FIELDS: usize = 4;
+const PEER_FIELDS: usize = 8;
+const NONE: &str = "(none)";
+const OFF: &str = "off";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InterfaceDump {
+    pub interface: String,
+    #[serde(skip)]
+    pub private_key: String,
+    pub public_key: String,
+    pub listen_port: u16,
+    pub fwmark: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerDump {
+    pub interface: String,
+    pub public_key: String,
+    pub preshared_key: Option<String>,
+    pub endpoint_addr: Option<SocketAddr>,
+    pub allowed_ips: Vec<IpNet>,
+    // None until the first handshake
+    pub last_handshake_at: Option<DateTime<Utc>>,
+    pub transfer_rx: u64,
+    pub transfer_tx: u64,
+    // keepalive interval in seconds, None when "off"
+    pub persistent_keepalive: Option<u16>,
+}
+
+impl PeerDump {
+    pub fn handshake_age(&self, now: DateTime<Utc>) -> Option<TimeDelta> {
+        self.last_handshake_at.map(|at| now - at)
+    }
+
+    pub fn presence(&self, thresholds: PresenceThresholds, now: DateTime<Utc>) -> PeerPresence {
+        thresholds.presence(self.handshake_age(now))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WgDump {
+    pub interfaces: Vec<InterfaceDump>,
+    pub peers: Vec<PeerDump>,
+}
+
+// `interface` is the name passed to `wg show`, ALL_INTERFACES when the lines carry the name
+pub fn parse_dump(interface: &str, output: &str) -> Result<WgDump> {
+    let mut dump = WgDump::default();
+    for (index, line) in output.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_no = index + 1;
+        let mut fields: Vec<&str> = line.split('\t').collect();
+        let if_name = if interface == ALL_INTERFACES {
+            fields.remove(0).to_string()
+        } else {
+            interface.to_string()
+        };
+        match fields.len() {
+            INTERFACE_FIELDS => dump.interfaces.push(InterfaceDump {
+                interface: if_name,
+                private_key: fields[0].to_string(),
+                public_key: fields[1].to_string(),
+                listen_port: parse_field(line_no, "listen-port", fields[2])?,
+                fwmark: parse_fwmark(line_no, fields[3])?,
+            }),
+            PEER_FIELDS => dump.peers.push(PeerDump {
+                interface: if_name,
+                public_key: fields[0].to_string(),
+                preshared_key: optional(fields[1]).map(str::to_string),
+                endpoint_addr: optional(fields[2])
+                    .map(|endpoint| parse_field(line_no, "endpoint", endpoint))
+                    .transpose()?,
+                allowed_ips: parse_allowed_ips(line_no, fields[3])?,
+                last_handshake_at: parse_handshake(line_no, fields[4])?,
+                transfer_rx: parse_field(line_no, "transfer-rx", fields[5])?,
+                transfer_tx: parse_field(line_no, "transfer-tx", fields[6])?,
+                persistent_keepalive: match fields[7] {
+                    OFF => None,
+                    keepalive => Some(parse_field(line_no, "persistent-keepalive", keepalive)?),
+                },
+            }),
+            count => {
+                return Err(BaseError::PeerDumpError(format!(
+                    "wg dump line {line_no}: unexpected number of fields ({count})"
+                )))
+            }
+        }
+    }
+    Ok(dump)
+}
+
+fn optional(value: &str) -> Option<&str> {
+    (value != NONE).then_some(value)
+}
+
+fn parse_field<T: std::str::FromStr>(line_no: usize, name: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| {
+        BaseError::PeerDumpError(format!("wg dump line {line_no}: invalid {name} `{value}`"))
+    })
+}
+
+// wg prints the fwmark in hex, or "off" when unset
+fn parse_fwmark(line_no: usize, value: &str) -> Result<Option<u32>> {
+    if value == OFF {
+        return Ok(None);
+    }
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+    parsed.map(Some).ok_or_else(|| {
+        BaseError::PeerDumpError(format!("wg dump line {line_no}: invalid fwmark `{value}`"))
+    })
+}
+
+fn parse_allowed_ips(line_no: usize, value: &str) -> Result<Vec<IpNet>> {
+    optional(value)
+        .map(|ips| {
+            ips.split(',')
+                .map(|ip| parse_field(line_no, "allowed-ips", ip.trim()))
+                .collect()
+        })
+        .unwrap_or(Ok(vec![]))
+}
+
+// seconds since the epoch, 0 when the peer never completed a handshake
+fn parse_handshake(line_no: usize, value: &str) -> Result<Option<DateTime<Utc>>> {
+    let seconds: i64 = parse_field(line_no, "latest-handshake", value)?;
+    if seconds == 0 {
+        return Ok(None);
+    }
+    DateTime::from_timestamp(seconds, 0).map(Some).ok_or_else(|| {
+        BaseError::PeerDumpError(format!("wg dump line {line_no}: invalid latest-handshake `{value}`"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WG0: &str = "wg0-private=\twg0-public=\t51820\toff\n\
+        peer-a=\t(none)\t203.0.113.7:51820\t10.8.0.2/32\t1718368989\t7340032\t9961472\t25\n\
+        peer-b=\tpsk=\t(none)\t10.8.0.3/32,fd00::3/128\t0\t0\t0\toff\n";
+
+    #[test]
+    fn single_interface() {
+        let dump = parse_dump("wg0", WG0).unwrap();
+        assert_eq!(
+            dump.interfaces,
+            vec![InterfaceDump {
+                interface: "wg0".to_string(),
+                private_key: "wg0-private=".to_string(),
+                public_key: "wg0-public=".to_string(),
+                listen_port: 51820,
+                fwmark: None,
+            }]
+        );
+        let [a, b] = dump.peers.as_slice() else { panic!("expected two peers") };
+        assert_eq!(a.interface, "wg0");
+        assert_eq!(a.preshared_key, None);
+        assert_eq!(a.endpoint_addr, Some("203.0.113.7:51820".parse().unwrap()));
+        assert_eq!(a.last_handshake_at, DateTime::from_timestamp(1718368989, 0));
+        // well past what a u16 can hold
+        assert_eq!((a.transfer_rx, a.transfer_tx), (7340032, 9961472));
+        assert_eq!(a.persistent_keepalive, Some(25));
+
+        assert_eq!(b.preshared_key.as_deref(), Some("psk="));
+        assert_eq!(b.endpoint_addr, None);
+        assert_eq!(
+            b.allowed_ips,
+            vec!["10.8.0.3/32".parse::<IpNet>().unwrap(), "fd00::3/128".parse().unwrap()]
+        );
+        assert_eq!(b.last_handshake_at, None);
+        assert_eq!(b.persistent_keepalive, None);
+    }
+
+    #[test]
+    fn all_interfaces() {
+        let output = "wg0\twg0-private=\twg0-public=\t51820\t0xca6c\n\
+            wg0\tpeer-a=\t(none)\t[2001:db8::7]:4444\t(none)\t0\t1\t2\toff\n\
+            wg1\twg1-private=\twg1-public=\t51821\toff\n";
+        let dump = parse_dump(ALL_INTERFACES, output).unwrap();
+        assert_eq!(dump.interfaces.len(), 2);
+        assert_eq!(dump.interfaces[0].fwmark, Some(0xca6c));
+        assert_eq!(dump.interfaces[1].interface, "wg1");
+        assert_eq!(dump.peers[0].interface, "wg0");
+        assert_eq!(dump.peers[0].endpoint_addr, Some("[2001:db8::7]:4444".parse().unwrap()));
+        assert!(dump.peers[0].allowed_ips.is_empty());
+    }
+
+    #[test]
+    fn presence() {
+        let dump = parse_dump("wg0", WG0).unwrap();
+        let handshake = DateTime::from_timestamp(1718368989, 0).unwrap();
+        let thresholds = PresenceThresholds { online: TimeDelta::seconds(180), idle: TimeDelta::seconds(900) };
+        let [a, b] = dump.peers.as_slice() else { panic!("expected two peers") };
+        assert_eq!(a.handshake_age(handshake + TimeDelta::seconds(42)), Some(TimeDelta::seconds(42)));
+        let after = |seconds| handshake + TimeDelta::seconds(seconds);
+        assert_eq!(a.presence(thresholds, after(180)), PeerPresence::Online);
+        assert_eq!(a.presence(thresholds, after(181)), PeerPresence::Idle);
+        assert_eq!(a.presence(thresholds, after(900)), PeerPresence::Idle);
+        assert_eq!(a.presence(thresholds, after(901)), PeerPresence::Offline);
+        assert_eq!(b.presence(thresholds, handshake), PeerPresence::NeverConnected);
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        let truncated = "peer-a=\t(none)\t(none)\t10.8.0.2/32\t0\t1";
+        let bad_counter = "peer-a=\t(none)\t(none)\t10.8.0.2/32\t0\tlots\t1\toff";
+        let bad_endpoint = "peer-a=\t(none)\tnowhere\t10.8.0.2/32\t0\t1\t1\toff";
+        for output in [truncated, bad_counter, bad_endpoint] {
+            assert!(matches!(parse_dump("wg0", output), Err(BaseError::PeerDumpError(_))));
+        }
+        let err = parse_dump("wg0", bad_counter).unwrap_err();
+        assert_eq!(err.to_string(), "wg dump line 1: invalid transfer-rx `lots`");
+    }
+}
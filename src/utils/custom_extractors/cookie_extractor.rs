@@ -31,19 +31,19 @@
 //             let token = verify_token(dec_key, RS256, &value.to_string()).unwrap();
 //             Ok(ExtractJwt(value.to_string()))
 //         } else {
-//             Err(Self::Rejection::AuthFailNoJwtCookie)
+//             Err(Self::Rejection::AuthFailNoToken)
 //         }
 //         // jar.get("key")
 //         //     .ok_or({
 //         //         CookieJar::remove()
 //         //         jar.remove(Cookie::from(key));
-//         //         Self::Rejection::AuthFailNoJwtCookie
+//         //         Self::Rejection::AuthFailNoToken
 //         //     })
 //         //     // .and_then(|jwt|verify_token(st.clone(),HS256,jwt.value()))
 //         //     .map(|v|ExtractJwt(v.to_string()))
 //         // st.key_dec
 //         // jar.get(key)
-//         //     .ok_or(BaseError::AuthFailNoJwtCookie)
+//         //     .ok_or(BaseError::AuthFailNoToken)
 //         // .and_then(|cookie| verify_token(dec_key, alg, cookie.value()))
 //     }
 // }
\ No newline at end of file
@@ -1,4 +1,5 @@
 pub(crate) mod json_extractor;
 pub(crate) mod path_extractor;
 pub(crate) mod cookie_extractor;
+#[allow(dead_code)]
 mod agent_extractor;
@@ -1,6 +1,7 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use axum::{
+    extract::MatchedPath,
     http::{Method, Uri},
     response::Response,
 };
@@ -8,6 +9,7 @@ use serde::Serialize;
 use serde_json::json;
 use tracing::{debug, info};
 
+use crate::service::metrics::{observe_request, UNMATCHED_ROUTE};
 use crate::{domain::ctx::Ctx, errors::BaseError};
 
 #[derive(Serialize, Debug)]
@@ -22,7 +24,16 @@ struct RequestLog {
     req_id: String,
 }
 
-pub async fn mw_req_logger(ctx: Ctx, uri: Uri, req_method: Method, res: Response) -> Response {
+pub async fn mw_req_logger(
+    ctx: Ctx,
+    uri: Uri,
+    req_method: Method,
+    matched_path: Option<MatchedPath>,
+    res: Response,
+) -> Response {
+    let route = matched_path.as_ref().map_or(UNMATCHED_ROUTE, MatchedPath::as_str);
+    observe_request(req_method.as_str(), route, res.status().as_u16(), ctx.elapsed());
+
     let log = RequestLog {
         req_id: ctx.req_id().to_string(),
         user: ctx.user_id().ok(),
@@ -5,7 +5,9 @@ use crate::errors::{Result, BaseError};
 pub use custom_extractors::json_extractor::JsonExtractor;
 pub use custom_extractors::path_extractor::PathExtractor;
 // pub use custom_extractors::cookie_extractor::ExtractJwt;
-use std::process::Output;
+use serde::{Deserialize, Deserializer};
+use std::process::{Output, Stdio};
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 
 pub async fn sudo_exec(cmd: Vec<&str>) -> Result<Output> {
@@ -15,3 +17,49 @@ pub async fn sudo_exec(cmd: Vec<&str>) -> Result<Output> {
         .await
         .map_err(|_err| BaseError::Execution { source: _err.to_string() })
 }
+
+// Same as sudo_exec, writing `input` to the stdin of the command
+pub async fn sudo_exec_stdin(cmd: Vec<&str>, input: &str) -> Result<Output> {
+    let execution_error = |err: std::io::Error| BaseError::Execution { source: err.to_string() };
+    let mut child = Command::new("sudo")
+        .args(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(execution_error)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // dropping stdin afterwards closes it, so the command sees the end of its input
+        stdin.write_all(input.as_bytes()).await.map_err(execution_error)?;
+    }
+    child.wait_with_output().await.map_err(execution_error)
+}
+
+// For `Option<Option<T>>` fields with `#[serde(default)]`: a missing field stays None,
+// an explicit null becomes Some(None), so an update can tell "keep" from "clear"
+pub fn deserialize_some<'de, T, D>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Update {
+        #[serde(default, deserialize_with = "super::deserialize_some")]
+        expires_at: Option<Option<String>>,
+    }
+
+    #[test]
+    fn missing_null_and_set_are_told_apart() {
+        let parse = |json: &str| serde_json::from_str::<Update>(json).unwrap().expires_at;
+        assert_eq!(parse("{}"), None);
+        assert_eq!(parse(r#"{"expires_at":null}"#), Some(None));
+        assert_eq!(parse(r#"{"expires_at":"2024-08-01T00:00:00Z"}"#), Some(Some("2024-08-01T00:00:00Z".to_string())));
+    }
+}
@@ -46,7 +46,7 @@ async fn log_result(req: reqwest::RequestBuilder, res: Response) {
     for i in res_headers {
         res_headers_str = format!(
             "\t➤  {}: {}\n{}",
-            i.0.to_string(),
+            i.0,
             i.1.to_str().unwrap(),
             res_headers_str
         );
@@ -65,11 +65,11 @@ Request  :
  🡆  status \t: {}
  🡆  Headers \n{}
  🡆  body   \t: {}",
-        req.url().to_string(),
+        req.url(),
         req.version(),
         req.method(),
         req_headers_str,
-        req.body().unwrap_or_else(|| &body),
+        req.body().unwrap_or(&body),
         res.status(),
         res_headers_str,
         res.text().await.unwrap_or("None".to_string()) // res.text().await.unwrap_or("None".to_string())